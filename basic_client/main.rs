@@ -1,9 +1,12 @@
-use std::io::{Read, Write};
 use std::net::{Shutdown, TcpStream};
-use std::str::from_utf8;
+use std::thread;
+use std::time::Duration;
 
-mod game_data;
-use game_data::{GameMatch, GameMatchServer, Point2};
+use vagabond_protocol::frame::{write_frame, FrameReader};
+use vagabond_protocol::handshake::{Hello, HelloResponse};
+use vagabond_protocol::input::InputFrame;
+use vagabond_protocol::lobby::{LobbyRequest, LobbyResponse};
+use vagabond_protocol::message::{ClientMessage, ServerMessage};
 
 // I want the client to first receive an id from the server once it connects, then I want the main communication "loop" to occur
 
@@ -12,7 +15,7 @@ fn main() {
         Ok(stream) => {
             println!("Successfully connected to server in port 1337");
 
-            handle_connection(stream, Option::None)
+            handle_connection(stream)
         }
         Err(e) => {
             println!("Failed to connect: {}", e);
@@ -22,59 +25,72 @@ fn main() {
     println!("Terminated.");
 }
 
-fn handle_connection(mut stream: TcpStream, game_match: Option<GameMatch>) {
-    let mut data = [0u8; 4096];
+fn handle_connection(mut stream: TcpStream) {
+    let mut reader = FrameReader::new(stream.try_clone().unwrap());
 
-    let id: u8;
-    let mut string_data: String;
-
-    // first acquire id
-    stream.read(&mut data[..]).unwrap();
-    string_data = String::from(from_utf8(&data).unwrap());
-    string_data = String::from(string_data.trim_matches(char::from(0)));
-    id = string_data.parse().unwrap(); // because id's type is declared earlier we do not need to do `parse::<u8>()`
-
-    println!("we have made it here pog id:{}", id);
+    // introduce ourselves to get into the lobby
+    write_frame(&mut stream, &Hello::new()).expect("Could not send hello");
+    match reader.read_frame() {
+        Ok(HelloResponse::Lobby(server_info)) => println!("In the lobby of {}", server_info.name),
+        Ok(HelloResponse::Rejected(reason)) => {
+            println!("Server rejected the connection: {}", reason);
+            return;
+        }
+        Ok(response) => {
+            println!("Unexpected answer to the hello: {:?}", response);
+            return;
+        }
+        Err(e) => {
+            println!("Handshake failed: {}", e);
+            return;
+        }
+    }
 
-    // create GameMatch if one doesn't exist already
-    let mut game_match = match game_match {
-        Some(mut gmatch) => {
-            gmatch.id = id;
-            gmatch
+    // then acquire id by waiting in the queue until someone else connects
+    let quick_match = ClientMessage::Lobby(LobbyRequest::QuickMatch);
+    write_frame(&mut stream, &quick_match).expect("Could not join the queue");
+    let (id, tick_rate) = loop {
+        match reader.read_frame() {
+            Ok(ServerMessage::Lobby(LobbyResponse::Joined(welcome))) => {
+                break (welcome.player_id, welcome.tick_rate)
+            }
+            Ok(ServerMessage::Lobby(LobbyResponse::Waiting(position))) => {
+                println!("Waiting for an opponent, number {} in the queue", position)
+            }
+            Ok(ServerMessage::Lobby(LobbyResponse::Rejected(reason))) => {
+                println!("Server turned us down: {}", reason);
+                return;
+            }
+            Ok(_) => (),
+            Err(e) => {
+                println!("Lost the server while waiting: {}", e);
+                return;
+            }
         }
-        _ => GameMatch::new(),
     };
 
-    // because this is the basic client we will artificially update game match data
-    game_match.entities[(id as usize) - 1].set_vel(Point2::<f32> { x: 1.0, y: 0.0 });
-    for entity in &mut game_match.entities {
-        entity.update();
-    }
-    // serialize data to send to the server
-    let serialized_data =
-        serde_json::to_string(&game_match).expect("Couldn't serialize game match");
+    println!("we have made it here pog id:{}", id);
 
-    // convert data to byte array and send to server
-    let data_as_bytes = serialized_data.into_bytes();
-    stream
-        .write_all(&data_as_bytes)
-        .expect("Could not write bytes to stream");
-    stream.flush().expect("Could not flush stream");
+    // because this is the basic client we will artificially hold the right button for a few frames
+    let mut server_message = None;
+    for tick in 1..=30 {
+        let mut input = InputFrame::new(tick);
+        input.right = true;
 
-    // we will then intercept the new data and deserialize it
-    stream.read(&mut data[..]).unwrap();
-    string_data = String::from(from_utf8(&data).unwrap());
-    string_data = String::from(string_data.trim_matches(char::from(0)));
-    let server_match: GameMatchServer = serde_json::from_str(string_data.as_str()).unwrap();
-    let mut server_match_iter = server_match.entities.into_iter();
+        write_frame(&mut stream, &ClientMessage::Input(input)).expect("Could not send input");
+        server_message = Some(reader.read_frame().expect("Could not receive match"));
 
-    // update the game match
-    for entity in &mut game_match.entities {
-        entity.update_data(0u8, server_match_iter.next().unwrap());
+        // send at the same rate the server simulates
+        thread::sleep(Duration::from_secs(1) / tick_rate);
     }
 
     // output data to console to show the game match after data is received from the server
-    println!("{:?}", game_match);
+    if let Some(ServerMessage::Snapshot(snapshot)) = server_message {
+        println!(
+            "tick {} (input {} simulated): {:?}",
+            snapshot.tick, snapshot.ack, snapshot.game_match.server_entities[id]
+        );
+    }
 
     // close the stream when we are done
     stream.shutdown(Shutdown::Both).unwrap();
@@ -1,6 +1,6 @@
 use std::time::{Duration, Instant};
 
-use crate::server_data::ServerAnimator;
+use vagabond_protocol::server_data::ServerAnimator;
 
 #[derive(Clone, Debug)]
 pub struct Animator {
@@ -1,8 +1,9 @@
 use ggez::graphics::Color;
 
-pub const SCALE: f32 = 5.5;
-pub const TILE_SIZE: f32 = 32.0;
-pub const SCREEN_WIDTH: f32 = 800.0;
-pub const SCREEN_HEIGHT: f32 = 600.0;
-pub const MAX_HP: i8 = 5;
+// sizes shared with the server live in the protocol crate
+pub use vagabond_protocol::constants::{
+    MAX_GUARD, MAX_HP, SCALE, SCREEN_HEIGHT, SCREEN_WIDTH, TICK_RATE, TILE_SIZE,
+};
+
 pub const PLAYER_TWO_COLOR: Color = Color::new(1.0, 0.5, 1.0, 1.0);
+pub const PARRY_FLASH_COLOR: Color = Color::new(0.5, 1.0, 1.0, 1.0);
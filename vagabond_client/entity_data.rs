@@ -1,53 +1,17 @@
 use ggez;
-use ggez::event::{KeyCode, KeyMods};
 use ggez::graphics;
 use ggez::graphics::{DrawParam, Image};
 use ggez::{Context, GameResult};
 
 use cgmath::Vector2;
 
-use serde::{Deserialize, Serialize};
+use vagabond_protocol::server_data::{Action, EntityActions, MatchStatus, ServerEntity};
 
 use std::time::Duration;
 
 use crate::animate::Animator;
-use crate::constants::{MAX_HP, PLAYER_TWO_COLOR, SCALE, SCREEN_HEIGHT, SCREEN_WIDTH, TILE_SIZE};
-use crate::game_data::{KeyboardControlledActor, MatchStatus};
-use crate::geometry::{Point2, Rect};
-use crate::server_data::ServerEntity;
-
-// all possible action states for an entity to be in
-#[derive(Serialize, Deserialize, Clone, Debug)]
-pub enum Action {
-    Left,
-    Right,
-}
-
-// helper struct for cleaning up Entity struct
-#[derive(Serialize, Deserialize, Clone, Debug)]
-pub struct EntityActions {
-    pub facing: Action, // Left or Right
-    pub moving_left: bool,
-    pub moving_right: bool,
-    pub can_attack: bool,
-    pub attacking: bool,
-    pub damage_check: bool,
-    pub blocking: bool,
-}
-
-impl EntityActions {
-    pub fn new(facing: Action) -> EntityActions {
-        EntityActions {
-            facing: facing,
-            moving_left: false,
-            moving_right: false,
-            can_attack: true,
-            attacking: false,
-            damage_check: false,
-            blocking: false,
-        }
-    }
-}
+use crate::constants::{MAX_HP, PARRY_FLASH_COLOR, PLAYER_TWO_COLOR, SCALE};
+use crate::geometry::{AsMint, Point2, Rect};
 
 // Serialize, Deserialize -- not needed because there will be a struct that will be used for sending to the server that is not this
 #[derive(Clone, Debug)]
@@ -63,117 +27,47 @@ pub struct Entity {
     attack_bound: Rect,
     scale: Point2, // make changes to reflect in server code because right now that is reflected as a f32 only
     redo_status: MatchStatus,
-    reset: bool
+    resets: u32,
+    guard: u8,
+    stunned: bool, // guard was broken and the player can not move for a moment
+    parried: bool, // just turned a hit back, drawn flashing in the blocking pose
 }
 impl Entity {
     pub fn new(id: usize) -> Entity {
-        // entity location
-        let position = match id {
-            1 => Point2::new(
-                SCREEN_WIDTH - (20.0 * SCALE),
-                SCREEN_HEIGHT - (TILE_SIZE * SCALE),
-            ),
-            _ => Point2::new(20.0 * SCALE, SCREEN_HEIGHT - (TILE_SIZE * SCALE)),
-        };
-
-        // Rect.top_left location
-        let bound_top_left_position = match id {
-            1 => {
-                let mut pos = position.clone();
-                pos.x -= TILE_SIZE * SCALE;
-                pos
-            }
-            _ => position.clone(),
-        };
-
-        // Rect.bottom_right location
-        let bound_bottom_right_position = Point2::new(
-            bound_top_left_position.x + (TILE_SIZE * SCALE),
-            bound_top_left_position.y + (TILE_SIZE * SCALE),
-        );
-
-        // Attack Rect.top_left location
-        let attack_top_left_position = match id {
-            1 => Point2::new(
-                bound_top_left_position.x,
-                bound_top_left_position.y + (6.0 * SCALE),
-            ),
-            _ => Point2::new(
-                bound_top_left_position.x + (20.0 * SCALE),
-                bound_top_left_position.y + (6.0 * SCALE),
-            ),
-        };
-
-        // Attack Rect.bottom_right location
-        let attack_bottom_right_position = match id {
-            1 => Point2::new(
-                bound_top_left_position.x + (12.0 * SCALE),
-                bound_top_left_position.y + (17.0 * SCALE),
-            ),
-            _ => Point2::new(
-                bound_bottom_right_position.x,
-                bound_top_left_position.y + (17.0 * SCALE),
-            ),
-        };
-
-        let facing = match id {
-            0 => Action::Right,
-            1 => Action::Left,
-            _ => Action::Right,
-        };
+        // where the entity starts is decided by the shared code so the client and server agree
+        let spawn = ServerEntity::new(id);
 
         Entity {
             id: id,
             hp: MAX_HP,
-            entity_actions: EntityActions::new(facing),
-            pos: position,
+            entity_actions: spawn.get_entity_actions(),
+            pos: spawn.get_pos(),
             movement_animator: Animator::new(3, Duration::from_millis(148), -1),
             attack_animator: Animator::new(3, Duration::from_millis(100), 1),
             vel: Point2::new(0.0, 0.0),
-            bound: Rect::new(bound_top_left_position, bound_bottom_right_position),
-            attack_bound: Rect::new(attack_top_left_position, attack_bottom_right_position),
+            bound: spawn.get_bound(),
+            attack_bound: spawn.get_attack_bound(),
             scale: Point2::new(SCALE, SCALE),
             redo_status: MatchStatus::InProgress,
-            reset: false,
+            resets: 0,
+            guard: spawn.get_guard(),
+            stunned: false,
+            parried: false,
         }
     }
 
     pub fn update(&mut self) -> GameResult {
-        // update velocity
-        if self.entity_actions.attacking == false && self.entity_actions.blocking == false {
-            self.vel.x = if self.entity_actions.moving_right {
-                self.movement_animator.update();
-                SCALE / 2.0
-            } else if self.entity_actions.moving_left {
-                self.movement_animator.update();
-                -SCALE / 2.0
-            } else {
-                self.movement_animator.end();
-                0.0
-            };
+        // where the entity is comes from the server (or the prediction for the local player),
+        // all that is left to do here is the walk cycle
+        if self.entity_actions.attacking == false
+            && self.entity_actions.blocking == false
+            && (self.entity_actions.moving_left || self.entity_actions.moving_right)
+        {
+            self.movement_animator.update();
         } else {
             self.movement_animator.end();
-
-            if self.entity_actions.attacking == true {
-                if self.attack_animator.current_repeat() > self.attack_animator.max_repeats() {
-                    self.entity_actions.damage_check = true;
-                    self.entity_actions.attacking = false;
-                    self.entity_actions.can_attack = true;
-                } else {
-                    self.attack_animator.update();
-                }
-            }
-
-            self.vel.x = 0.0;
         }
 
-        // update position
-        self.pos.x += self.vel.x;
-        self.pos.y += self.vel.y;
-
-        self.bound.translate(&self.vel);
-        self.attack_bound.translate(&self.vel);
-
         Ok(())
     }
 
@@ -192,6 +86,9 @@ impl Entity {
                 .scale(Vector2::new(-self.scale.x, self.scale.y))
                 .color(PLAYER_TWO_COLOR);
         }
+        if self.parried {
+            draw_param = draw_param.color(PARRY_FLASH_COLOR);
+        }
 
         // let random_rect = graphics::Rect::new(self.bound.top_left.x, self.bound.top_left.y, TILE_SIZE*SCALE, TILE_SIZE*SCALE);
         // let random_attack_rect = graphics::Rect::new(self.attack_bound.top_left.x, self.attack_bound.top_left.y, self.attack_bound.bottom_right.x - self.attack_bound.top_left.x, self.attack_bound.bottom_right.y - self.attack_bound.top_left.y);
@@ -206,6 +103,11 @@ impl Entity {
     }
 
     fn get_draw_param(&self) -> usize {
+        // the parry holds the block up for as long as it flashes
+        if self.parried {
+            return 0;
+        }
+
         // movement animation
         if self.entity_actions.attacking {
             if self.attack_animator.current_repeat() < self.attack_animator.max_repeats() {
@@ -235,55 +137,28 @@ impl Entity {
     }
 }
 
-// accessors and one mutator
+// accessors
 impl Entity {
-    pub fn get_id(&self) -> usize {
-        self.id
-    }
     pub fn get_hp(&self) -> i8 {
         self.hp
     }
 
-    pub fn get_pos(&self) -> Point2 {
-        self.pos.clone()
-    }
-
-    pub fn get_vel(&self) -> Point2 {
-        self.vel.clone()
-    }
-
-    pub fn get_bound(&self) -> Rect {
-        self.bound.clone()
-    }
-
-    pub fn get_attack_bound(&self) -> Rect {
-        self.attack_bound.clone()
-    }
-
     // might implement later
     #[allow(dead_code)]
     pub fn get_movement_animator(&self) -> Animator {
         self.movement_animator.clone()
     }
 
-    pub fn get_attack_animator(&self) -> Animator {
-        self.attack_animator.clone()
+    pub fn get_resets(&self) -> u32 {
+        self.resets
     }
 
-    pub fn get_entity_actions(&self) -> EntityActions {
-        self.entity_actions.clone()
+    pub fn get_guard(&self) -> u8 {
+        self.guard
     }
 
-    pub fn get_redo_status(&self) -> MatchStatus {
-        self.redo_status.clone()
-    }
-
-    pub fn set_redo_status(&mut self, status: MatchStatus) {
-        self.redo_status = status;
-    }
-
-    pub fn get_reset(&self) -> bool {
-        self.reset.clone()
+    pub fn is_stunned(&self) -> bool {
+        self.stunned
     }
 }
 
@@ -300,38 +175,9 @@ impl Entity {
         self.bound = server_entity.get_bound();
         self.attack_bound = server_entity.get_attack_bound();
         self.redo_status = server_entity.get_redo_status();
-        self.reset = server_entity.get_reset();
-    }
-}
-
-impl KeyboardControlledActor for Entity {
-    fn key_down_event(&mut self, keycode: KeyCode, _keymods: KeyMods, _repeat: bool) {
-        match keycode {
-            KeyCode::Space => {
-                if self.entity_actions.can_attack {
-                    self.entity_actions.attacking = true;
-                    self.attack_animator.end();
-                }
-                self.entity_actions.can_attack = false;
-            }
-            KeyCode::Left => {
-                self.entity_actions.moving_left = true;
-                self.entity_actions.facing = Action::Left;
-            }
-            KeyCode::Right => {
-                self.entity_actions.moving_right = true;
-                self.entity_actions.facing = Action::Right;
-            }
-            KeyCode::Down => self.entity_actions.blocking = true,
-            _ => (),
-        };
-    }
-    fn key_up_event(&mut self, keycode: KeyCode, _keymods: KeyMods) {
-        match keycode {
-            KeyCode::Left => self.entity_actions.moving_left = false,
-            KeyCode::Right => self.entity_actions.moving_right = false,
-            KeyCode::Down => self.entity_actions.blocking = false,
-            _ => (),
-        };
+        self.resets = server_entity.get_resets();
+        self.guard = server_entity.get_guard();
+        self.stunned = server_entity.is_stunned();
+        self.parried = server_entity.get_parried();
     }
 }
@@ -4,13 +4,24 @@ use ggez::graphics::{DrawParam, Font, Image};
 use ggez::input::mouse::MouseButton;
 use ggez::{Context, GameResult};
 
-use serde::{Deserialize, Serialize};
+use std::time::{Duration, Instant};
+
+use vagabond_protocol::handshake::MatchRules;
+use vagabond_protocol::input::InputFrame;
+use vagabond_protocol::message::Snapshot;
+use vagabond_protocol::server_data::{
+    Clock, EndReason, MatchResult, MatchStatus, RematchStatus, ServerEntity,
+};
 
 use crate::constants::{SCREEN_HEIGHT, SCREEN_WIDTH};
 use crate::entity_data::Entity;
-use crate::geometry::Point2;
-use crate::gui_data::{create_text_with_background, Button, Clock, HealthBar};
-use crate::server_data::ServerGameMatch;
+use crate::geometry::{AsMint, Point2};
+use crate::gui_data::{
+    create_text_with_background, draw_clock, Button, GuardBar, HealthBar, RoundPips,
+};
+use crate::interpolation::Interpolator;
+use crate::network::ConnectionStatus;
+use crate::prediction::Predictor;
 
 // user controlled entities require this
 pub trait KeyboardControlledActor {
@@ -23,18 +34,30 @@ pub trait MouseControlledActor {
     fn mouse_button_down_event(&mut self, mouse_button: &MouseButton, x: f32, y: f32);
 }
 
-#[derive(Serialize, Deserialize, Clone, Debug)]
-pub enum RematchStatus {
-    Yes,
-    No,
-    Maybe,
-}
-
-#[derive(Serialize, Deserialize, Clone, Debug)]
-pub enum MatchStatus {
-    InProgress,
-    Over(usize), // player id will go in the usize
-    Rematch(RematchStatus),
+// the buttons being held are collected here and sent to the server every frame
+impl KeyboardControlledActor for InputFrame {
+    fn key_down_event(&mut self, keycode: KeyCode, _keymods: KeyMods, repeat: bool) {
+        match keycode {
+            KeyCode::Space => {
+                // holding the button down should not keep attacking
+                if repeat == false {
+                    self.attack_pressed = true;
+                }
+            }
+            KeyCode::Left => self.left = true,
+            KeyCode::Right => self.right = true,
+            KeyCode::Down => self.block = true,
+            _ => (),
+        };
+    }
+    fn key_up_event(&mut self, keycode: KeyCode, _keymods: KeyMods) {
+        match keycode {
+            KeyCode::Left => self.left = false,
+            KeyCode::Right => self.right = false,
+            KeyCode::Down => self.block = false,
+            _ => (),
+        };
+    }
 }
 
 pub struct GameMatch {
@@ -43,18 +66,36 @@ pub struct GameMatch {
     clock: Clock,
     health_bar_1: HealthBar,
     health_bar_2: HealthBar,
+    guard_bars: Vec<GuardBar>,
+    round_pips: Vec<RoundPips>, // rounds each player has taken, drawn under their health bar
     rematch_button: Button,
     quit_button: Button,
     match_status: MatchStatus,
+    connection_status: ConnectionStatus,
+    input: InputFrame,
+    prediction: Predictor, // the local player moves before the server confirms it
+    interpolators: Vec<Interpolator>, // everyone else is drawn slightly in the past, unused for our own id
+    rematch_vote: Option<RematchStatus>,
+    leave_requested: bool, // quit was clicked, the room should be left for the lobby
+    spectating: bool,      // both samurai come from the server and the keyboard does nothing
+    tick_rate: u32,        // the server's, inputs are sent and predicted this often
+    resets: u32,           // round restarts seen so far
     pub entities: Vec<Entity>,
 }
 
 impl GameMatch {
-    pub fn new(ctx: &mut Context, id: usize, font: Font) -> GameMatch {
+    pub fn new(
+        ctx: &mut Context,
+        id: usize,
+        rules: &MatchRules,
+        tick_rate: u32,
+        font: Font,
+        interpolation_delay: Duration,
+    ) -> GameMatch {
         let ent = Entity::new(0);
-        let hp_bar_1 = HealthBar::new(0);
+        let hp_bar_1 = HealthBar::new(0, rules.max_hp);
         let ent1 = Entity::new(1);
-        let hp_bar_2 = HealthBar::new(1);
+        let hp_bar_2 = HealthBar::new(1, rules.max_hp);
         let entity_vector = vec![ent, ent1];
         let match_status = MatchStatus::InProgress;
 
@@ -92,17 +133,72 @@ impl GameMatch {
             clock: Clock::new(),
             health_bar_1: hp_bar_1,
             health_bar_2: hp_bar_2,
+            guard_bars: vec![GuardBar::new(0), GuardBar::new(1)],
+            round_pips: vec![
+                RoundPips::new(0, rules.rounds_to_win()),
+                RoundPips::new(1, rules.rounds_to_win()),
+            ],
             rematch_button: rematch_button,
             quit_button: quit_button,
             match_status: match_status,
+            connection_status: ConnectionStatus::Connected,
+            input: InputFrame::new(0),
+            prediction: Predictor::new(ServerEntity::new(id), tick_rate),
+            interpolators: vec![
+                Interpolator::new(interpolation_delay, tick_rate),
+                Interpolator::new(interpolation_delay, tick_rate),
+            ],
+            rematch_vote: None,
+            leave_requested: false,
+            spectating: false,
+            tick_rate: tick_rate,
+            resets: 0,
             entities: entity_vector,
         }
     }
 
+    // watching someone else's match, nobody is predicted and only the quit button is offered
+    pub fn spectate(
+        ctx: &mut Context,
+        rules: &MatchRules,
+        tick_rate: u32,
+        font: Font,
+        interpolation_delay: Duration,
+    ) -> GameMatch {
+        let mut game_match = GameMatch::new(ctx, 0, rules, tick_rate, font, interpolation_delay);
+        game_match.spectating = true;
+
+        // out of the way in the corner since it is there the whole match
+        let quit_height = game_match.quit_button.get_text_height();
+        game_match
+            .quit_button
+            .change_location(Point2::new(10.0, SCREEN_HEIGHT - quit_height - 30.0));
+        game_match.quit_button.visible = true;
+
+        game_match
+    }
+
     pub fn update(&mut self) -> GameResult {
+        // the local player is drawn where we predict it to be
+        if self.spectating == false {
+            self.prediction.smooth();
+            self.entities[self.id].update_from_server_entity(&self.prediction.drawn_entity());
+        }
+
+        // and the other player where the server had them a moment ago
+        let now = Instant::now();
+        for id in 0..self.entities.len() {
+            if id == self.id && self.spectating == false {
+                continue;
+            }
+            if let Some(server_entity) = self.interpolators[id].sample(now) {
+                self.entities[id].update_from_server_entity(&server_entity);
+            }
+        }
+
         // update entities
         match &self.match_status {
-            MatchStatus::InProgress => {
+            MatchStatus::InProgress | MatchStatus::SuddenDeath => {
                 self.entities[0].update().unwrap();
                 self.entities[1].update().unwrap();
                 if self.rematch_button.visible == true {
@@ -119,8 +215,11 @@ impl GameMatch {
             _ => (),
         }
 
-        // reset players positions after round restart
-        if self.entities[0].get_reset() == true || self.entities[1].get_reset() == true {
+        // reset players positions after round restart, which the server counts so it is
+        // noticed even if the snapshots around it were dropped or sent together
+        let resets = self.entities[0].get_resets();
+        if resets != self.resets {
+            self.resets = resets;
             self.entities[0] = Entity::new(0);
             self.entities[1] = Entity::new(1);
             self.match_status = MatchStatus::InProgress;
@@ -130,14 +229,26 @@ impl GameMatch {
 
         self.health_bar_1.update(self.entities[0].get_hp());
         self.health_bar_2.update(self.entities[1].get_hp());
+        for (guard_bar, entity) in self.guard_bars.iter_mut().zip(&self.entities) {
+            guard_bar.update(entity.get_guard(), entity.is_stunned());
+        }
 
-        // we can assume both buttons are visible if one is visible since they will always be visible at the same time
-        if self.rematch_button.visible == true {
-            if self.rematch_button.mouse_clicked() == true {
-                self.entities[self.id].set_redo_status(MatchStatus::Rematch(RematchStatus::Yes));
-            } else if self.quit_button.mouse_clicked() == true {
-                self.entities[self.id].set_redo_status(MatchStatus::Rematch(RematchStatus::No))
-            }
+        // without a server there is nothing to rematch, but the player still needs a way out,
+        // and spectators can always leave but never vote
+        let lost = match self.connection_status {
+            ConnectionStatus::Lost(_) => true,
+            _ => false,
+        };
+        if lost || self.spectating {
+            self.rematch_button.visible = false;
+            self.quit_button.visible = true;
+        }
+
+        if self.rematch_button.visible == true && self.rematch_button.mouse_clicked() == true {
+            self.rematch_vote = Some(RematchStatus::Yes);
+        }
+        if self.quit_button.take_click() {
+            self.leave_requested = true;
         }
 
         Ok(())
@@ -159,76 +270,192 @@ impl GameMatch {
         // draw health bars
         self.health_bar_1.draw(ctx).unwrap();
         self.health_bar_2.draw(ctx).unwrap();
+        for guard_bar in &self.guard_bars {
+            guard_bar.draw(ctx).unwrap();
+        }
+        for round_pips in &self.round_pips {
+            round_pips.draw(ctx).unwrap();
+        }
 
         // draw buttons
         self.rematch_button.draw(ctx).unwrap();
         self.quit_button.draw(ctx).unwrap();
 
-        // draw clock
-        self.clock.draw(ctx, &self.font).unwrap();
+        // draw clock, sudden death has no clock so it gets a banner in its place
+        if self.is_sudden_death() {
+            self.draw_message(ctx, String::from("Sudden Death"), 36.0, 22.0);
+            self.draw_message(ctx, String::from("first hit wins"), 12.0, 60.0);
+        } else {
+            draw_clock(ctx, &self.clock, &self.font).unwrap();
+        }
 
-        // draw match winner text
+        // draw the result of the match or the round
         match self.match_status {
-            MatchStatus::Over(player_id) => {
-                let mut text_string = String::from("Player ");
-                text_string.push_str(&player_id.to_string());
-                text_string.push_str(" has won.");
-                let (text, mesh) = create_text_with_background(
-                    ctx,
-                    text_string,
-                    &self.font,
-                    ggez::graphics::Scale::uniform(36.0),
-                );
-
-                let location = Point2::new(
-                    (SCREEN_WIDTH / 2.0) - (text.width(ctx) as f32 / 2.0),
-                    (SCREEN_HEIGHT / 2.0) - (text.height(ctx) as f32 / 2.0),
-                );
-
-                ggez::graphics::draw(ctx, &mesh, DrawParam::new().dest(location.as_mint_point()))
-                    .unwrap();
-                ggez::graphics::draw(ctx, &text, DrawParam::new().dest(location.as_mint_point()))
-                    .unwrap();
-            }
+            MatchStatus::Over(result) => self.draw_result(ctx, &result, false),
+            MatchStatus::RoundOver(result) => self.draw_result(ctx, &result, true),
             _ => (), // match is still playing out
         }
 
+        if self.spectating {
+            self.draw_message(ctx, String::from("Spectating"), 12.0, SCREEN_HEIGHT - 20.0);
+        }
+
+        // let the player know why nothing is moving
+        match &self.connection_status {
+            ConnectionStatus::Connecting | ConnectionStatus::Connected => (),
+            ConnectionStatus::Stalled => {
+                self.draw_message(ctx, String::from("Waiting for server..."), 24.0, 100.0)
+            }
+            ConnectionStatus::Reconnecting(attempt, reason) => {
+                let text_string = format!("Reconnecting (attempt {})...", attempt);
+                self.draw_message(ctx, text_string, 24.0, 100.0);
+                self.draw_message(ctx, reason.clone(), 16.0, 130.0);
+            }
+            ConnectionStatus::Lost(reason) => self.draw_message(ctx, reason.clone(), 16.0, 100.0),
+        }
+
         Ok(())
     }
+
+    // who won and how, with the health they were left with and how long it took above that
+    fn draw_result(&self, ctx: &mut Context, result: &MatchResult, round: bool) {
+        let headline = match (result.winner, round) {
+            (Some(id), false) => format!("Player {} has won.", id + 1),
+            (Some(id), true) => format!("Player {} takes the round.", id + 1),
+            (None, false) => String::from("The match is a draw."),
+            (None, true) => String::from("The round is a draw."),
+        };
+        self.draw_message(ctx, headline, 36.0, SCREEN_HEIGHT / 2.0);
+
+        let reason = match result.reason {
+            EndReason::Knockout => "by knockout",
+            EndReason::TimeUp => "on time",
+            EndReason::Forfeit => "by forfeit",
+            EndReason::Disconnect => "by disconnect",
+            EndReason::DoubleKnockout => "double knockout",
+            EndReason::Rounds => "on rounds",
+        };
+        self.draw_message(
+            ctx,
+            String::from(reason),
+            18.0,
+            (SCREEN_HEIGHT / 2.0) - 45.0,
+        );
+
+        let seconds = result.duration.as_secs();
+        let details = format!(
+            "{} - {} health after {}:{:02}",
+            result.final_hp[0],
+            result.final_hp[1],
+            seconds / 60,
+            seconds % 60
+        );
+        self.draw_message(ctx, details, 12.0, (SCREEN_HEIGHT / 2.0) - 75.0);
+    }
+
+    // text with a background centered horizontally around the given height
+    fn draw_message(&self, ctx: &mut Context, text_string: String, size: f32, center_y: f32) {
+        let (text, mesh) = create_text_with_background(
+            ctx,
+            text_string,
+            &self.font,
+            ggez::graphics::Scale::uniform(size),
+        );
+
+        let location = Point2::new(
+            (SCREEN_WIDTH / 2.0) - (text.width(ctx) as f32 / 2.0),
+            center_y - (text.height(ctx) as f32 / 2.0),
+        );
+
+        ggez::graphics::draw(ctx, &mesh, DrawParam::new().dest(location.as_mint_point())).unwrap();
+        ggez::graphics::draw(ctx, &text, DrawParam::new().dest(location.as_mint_point())).unwrap();
+    }
 }
 
-// accessors
+// what gets sent to the server
 impl GameMatch {
-    pub fn get_match_status(&self) -> MatchStatus {
-        self.match_status.clone()
+    // hands out the input for this tick, an attack press is only ever sent once
+    pub fn next_input(&mut self) -> InputFrame {
+        self.input.tick = self.input.tick.wrapping_add(1);
+        let input = self.input;
+        self.input.attack_pressed = false;
+
+        // the server does not move anyone once the match is over or we are gone
+        let connected = match self.connection_status {
+            ConnectionStatus::Connected | ConnectionStatus::Stalled => true,
+            _ => false,
+        };
+        let fighting = self.match_status == MatchStatus::InProgress || self.is_sudden_death();
+        if fighting && connected {
+            self.prediction.predict(&input);
+        }
+
+        input
     }
-    pub fn get_clock(&self) -> Clock {
-        self.clock.clone()
+
+    pub fn is_sudden_death(&self) -> bool {
+        self.match_status == MatchStatus::SuddenDeath
+    }
+
+    pub fn get_tick_rate(&self) -> u32 {
+        self.tick_rate
+    }
+
+    // spectators never send anything, not even empty inputs
+    pub fn is_spectating(&self) -> bool {
+        self.spectating
+    }
+
+    pub fn take_rematch_vote(&mut self) -> Option<RematchStatus> {
+        self.rematch_vote.take()
+    }
+
+    // true once the player has asked to go back to the lobby
+    pub fn take_leave_request(&mut self) -> bool {
+        let leave_requested = self.leave_requested;
+        self.leave_requested = false;
+        leave_requested
     }
-    pub fn get_entities(&self) -> Vec<Entity> {
-        self.entities.clone()
+
+    pub fn set_connection_status(&mut self, connection_status: ConnectionStatus) {
+        self.connection_status = connection_status;
     }
 }
 
-// update GameMatch with data from ServerGameMatch
+// update GameMatch with data from a server snapshot
 impl GameMatch {
-    pub fn update_from_server_game_match(&mut self, server_game_match: &ServerGameMatch) {
+    pub fn update_from_snapshot(&mut self, snapshot: &Snapshot) {
+        let server_game_match = &snapshot.game_match;
         self.clock = server_game_match.get_clock();
         self.match_status = server_game_match.get_match_status();
+        for (id, won) in server_game_match.round_wins.iter().enumerate() {
+            self.round_pips[id].update(*won);
+        }
 
-        let server_entities = server_game_match.get_server_entities();
-
-        self.entities[0].update_from_server_entity(&server_entities[0]);
-        self.entities[1].update_from_server_entity(&server_entities[1]);
+        let received = Instant::now();
+        for server_entity in &server_game_match.server_entities {
+            let id = server_entity.get_id();
+            if id == self.id && self.spectating == false {
+                // the server is behind our prediction so only use it as a starting point
+                self.prediction.reconcile(snapshot.ack, server_entity);
+            } else {
+                self.interpolators[id].push(snapshot.tick, server_entity, received);
+            }
+        }
     }
 }
 
 impl KeyboardControlledActor for GameMatch {
+    // spectators do not control either samurai
     fn key_down_event(&mut self, keycode: KeyCode, keymods: KeyMods, repeat: bool) {
-        &self.entities[self.id].key_down_event(keycode, keymods, repeat);
+        if self.spectating == false {
+            self.input.key_down_event(keycode, keymods, repeat);
+        }
     }
     fn key_up_event(&mut self, keycode: KeyCode, keymods: KeyMods) {
-        &self.entities[self.id].key_up_event(keycode, keymods);
+        if self.spectating == false {
+            self.input.key_up_event(keycode, keymods);
+        }
     }
 }
 
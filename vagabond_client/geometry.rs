@@ -1,57 +1,19 @@
-use serde::{Deserialize, Serialize};
+// Point2 and Rect are shared with the server, the client only adds conversions for drawing
+pub use vagabond_protocol::geometry::{Point2, Rect};
 
-#[derive(Serialize, Deserialize, Copy, Clone, Debug)]
-pub struct Point2 {
-    pub x: f32,
-    pub y: f32,
-}
-impl Point2 {
-    pub fn new(x: f32, y: f32) -> Point2 {
-        Point2 { x: x, y: y }
-    }
-
-    pub fn as_mint_point(&self) -> cgmath::Point2<f32> {
-        cgmath::Point2::<f32>::new(self.x, self.y)
-    }
+pub trait AsMint {
+    fn as_mint_point(&self) -> cgmath::Point2<f32>;
 
     #[allow(dead_code)]
-    pub fn as_mint_vector(&self) -> cgmath::Vector2<f32> {
-        cgmath::Vector2::<f32>::new(self.x, self.y)
-    }
+    fn as_mint_vector(&self) -> cgmath::Vector2<f32>;
 }
 
-#[derive(Serialize, Deserialize, Copy, Clone, Debug)]
-pub struct Rect {
-    pub top_left: Point2,
-    pub bottom_right: Point2,
-}
-
-impl Rect {
-    pub fn new(top_left: Point2, bottom_right: Point2) -> Rect {
-        Rect {
-            top_left: top_left,
-            bottom_right: bottom_right,
-        }
-    }
-
-    pub fn translate(&mut self, vel: &Point2) {
-        self.top_left.x += vel.x;
-        self.top_left.y += vel.y;
-        self.bottom_right.x += vel.x;
-        self.bottom_right.y += vel.y;
+impl AsMint for Point2 {
+    fn as_mint_point(&self) -> cgmath::Point2<f32> {
+        cgmath::Point2::<f32>::new(self.x, self.y)
     }
 
-    pub fn check_bounds_point(self, point: &Point2) -> bool {
-        // if point is on left side of other
-        if self.top_left.x >= point.x || point.x >= self.bottom_right.x {
-            return false;
-        }
-
-        // if point is above other
-        if self.top_left.y >= point.y || point.y >= self.bottom_right.y {
-            return false;
-        }
-
-        true
+    fn as_mint_vector(&self) -> cgmath::Vector2<f32> {
+        cgmath::Vector2::<f32>::new(self.x, self.y)
     }
 }
@@ -1,14 +1,15 @@
+use ggez::event::KeyCode;
 use ggez::graphics;
 use ggez::graphics::{Color, DrawParam, Font, Mesh, MeshBuilder, Text, TextFragment};
 use ggez::input::mouse::MouseButton;
 use ggez::{Context, GameResult};
 
-use serde::{Deserialize, Serialize};
-
 use cgmath::Vector2;
 
-use crate::constants::{MAX_HP, SCALE, SCREEN_WIDTH};
-use crate::geometry::{Point2, Rect};
+use vagabond_protocol::server_data::Clock;
+
+use crate::constants::{MAX_GUARD, SCALE, SCREEN_WIDTH};
+use crate::geometry::{AsMint, Point2, Rect};
 
 pub struct HealthBar {
     id: usize, // player id
@@ -20,7 +21,7 @@ pub struct HealthBar {
     draw_param: DrawParam,
 }
 impl HealthBar {
-    pub fn new(id: usize) -> HealthBar {
+    pub fn new(id: usize, max_hp: i8) -> HealthBar {
         let width = match id {
             1 => -10.0 * SCALE * 4.0,
             _ => 10.0 * SCALE * 4.0,
@@ -42,7 +43,7 @@ impl HealthBar {
 
         HealthBar {
             id: id,
-            max_hp: MAX_HP,
+            max_hp: max_hp,
             foreground_rectangle: f_rect,
             background_rectangle: b_rect,
             foreground_color: f_color,
@@ -96,44 +97,159 @@ impl HealthBar {
     }
 }
 
-#[derive(Clone, Serialize, Deserialize, Debug)]
-pub struct Clock {
-    current: u16,
+// how much guard a player has left, a thin bar right under their health bar
+pub struct GuardBar {
+    full_rectangle: graphics::Rect,
+    rectangle: graphics::Rect,
+    color: Color,
+    broken_color: Color,
+    background_color: Color,
+    broken: bool,
 }
-impl Clock {
-    pub fn new() -> Clock {
-        Clock { current: 0 }
+impl GuardBar {
+    pub fn new(id: usize) -> GuardBar {
+        // same width as the health bar and growing in from the same edge
+        let width = match id {
+            1 => -10.0 * SCALE * 4.0,
+            _ => 10.0 * SCALE * 4.0,
+        };
+        let height = SCALE * 2.0;
+        let x = match id {
+            1 => SCREEN_WIDTH,
+            _ => 0.0,
+        };
+        let y = 3.0 * SCALE * 2.0;
+
+        let rect = graphics::Rect::new(x, y, width, height);
+
+        GuardBar {
+            full_rectangle: rect,
+            rectangle: rect,
+            color: Color::new(0.2, 0.6, 1.0, 1.0),
+            broken_color: Color::new(1.0, 0.6, 0.0, 1.0),
+            background_color: Color::new(0.5, 0.5, 0.5, 0.5),
+            broken: false,
+        }
     }
 
-    pub fn draw(&self, ctx: &mut Context, font: &Font) -> GameResult {
-        let scale = graphics::Scale::uniform(36.0);
+    pub fn update(&mut self, guard: u8, broken: bool) {
+        self.rectangle.w = (self.full_rectangle.w / MAX_GUARD as f32) * guard as f32;
+        self.broken = broken;
+    }
 
-        let (timer_text, rect_mesh) =
-            create_text_with_background(ctx, self.current.to_string(), font, scale);
+    pub fn draw(&self, ctx: &mut Context) -> GameResult {
+        // the empty bar lights up while the player is stunned so it is clear why they froze
+        let (background_color, color) = if self.broken {
+            (self.broken_color, self.broken_color)
+        } else {
+            (self.background_color, self.color)
+        };
 
-        let location = Point2::new(
-            (SCREEN_WIDTH / 2.0) - (timer_text.width(ctx) as f32 / 2.0),
-            0.0,
+        let mut mesh_builder = MeshBuilder::new();
+        mesh_builder.rectangle(
+            graphics::DrawMode::Fill(graphics::FillOptions::default()),
+            self.full_rectangle,
+            background_color,
         );
+        if self.rectangle.w != 0.0 {
+            mesh_builder.rectangle(
+                graphics::DrawMode::Fill(graphics::FillOptions::default()),
+                self.rectangle,
+                color,
+            );
+        }
 
-        graphics::draw(
-            ctx,
-            &rect_mesh,
-            DrawParam::new().dest(location.as_mint_point()),
-        )
-        .unwrap();
+        let mesh = mesh_builder.build(ctx).unwrap();
+        graphics::draw(ctx, &mesh, DrawParam::new()).unwrap();
 
-        graphics::draw(
-            ctx,
-            &timer_text,
-            DrawParam::new().dest(location.as_mint_point()),
-        )
-        .unwrap();
+        Ok(())
+    }
+}
+
+// one pip for each round a player needs to take the match, filled in for the ones they have
+pub struct RoundPips {
+    id: usize, // player id
+    needed: u8,
+    won: u8,
+}
+impl RoundPips {
+    pub fn new(id: usize, needed: u8) -> RoundPips {
+        RoundPips {
+            id: id,
+            needed: needed,
+            won: 0,
+        }
+    }
+
+    pub fn update(&mut self, won: u8) {
+        self.won = won;
+    }
+
+    pub fn draw(&self, ctx: &mut Context) -> GameResult {
+        let radius = 6.0;
+        let spacing = 18.0;
+        // just under the health and guard bars, counting in from the same edge of the screen
+        let y = 3.0 * SCALE * 2.0 + SCALE * 2.0 + 12.0;
+        let filled_color = Color::new(1.0, 0.84, 0.0, 1.0);
+        let empty_color = Color::new(0.5, 0.5, 0.5, 0.5);
+
+        let mut mesh_builder = MeshBuilder::new();
+        for pip in 0..self.needed {
+            let offset = 14.0 + pip as f32 * spacing;
+            let x = match self.id {
+                1 => SCREEN_WIDTH - offset,
+                _ => offset,
+            };
+            let color = if pip < self.won {
+                filled_color
+            } else {
+                empty_color
+            };
+            mesh_builder.circle(
+                graphics::DrawMode::Fill(graphics::FillOptions::default()),
+                Point2::new(x, y).as_mint_point(),
+                radius,
+                0.1,
+                color,
+            );
+        }
+
+        let mesh = mesh_builder.build(ctx).unwrap();
+        graphics::draw(ctx, &mesh, DrawParam::new()).unwrap();
 
         Ok(())
     }
 }
 
+// the clock itself comes from the server, we only draw it
+pub fn draw_clock(ctx: &mut Context, clock: &Clock, font: &Font) -> GameResult {
+    let scale = graphics::Scale::uniform(36.0);
+
+    let (timer_text, rect_mesh) =
+        create_text_with_background(ctx, clock.current().to_string(), font, scale);
+
+    let location = Point2::new(
+        (SCREEN_WIDTH / 2.0) - (timer_text.width(ctx) as f32 / 2.0),
+        0.0,
+    );
+
+    graphics::draw(
+        ctx,
+        &rect_mesh,
+        DrawParam::new().dest(location.as_mint_point()),
+    )
+    .unwrap();
+
+    graphics::draw(
+        ctx,
+        &timer_text,
+        DrawParam::new().dest(location.as_mint_point()),
+    )
+    .unwrap();
+
+    Ok(())
+}
+
 pub struct Button {
     bounds: Rect,
     hovered_color: Color,
@@ -141,6 +257,7 @@ pub struct Button {
     clicked_color: Color,
     mouse_hover: bool,
     mouse_clicked: bool,
+    released: bool, // clicked and let go over the button since the last take_click
     button_text: Text,
     button_text_position: Point2,
     text_width: f32,
@@ -202,6 +319,7 @@ impl Button {
             clicked_color: clicked_color,
             mouse_hover: false,
             mouse_clicked: false,
+            released: false,
             button_text: button_text,
             button_text_position: button_text_pos,
             text_width: text_width,
@@ -299,18 +417,28 @@ impl Button {
         }
     }
 
-    pub fn mouse_button_up_event(&mut self, mouse_button: &MouseButton, _mouse_position: &Point2) {
+    pub fn mouse_button_up_event(&mut self, mouse_button: &MouseButton, mouse_position: &Point2) {
         if self.visible == false {
             return ();
         }
         match mouse_button {
             MouseButton::Left => {
+                if self.mouse_clicked == true && self.bounds.check_bounds_point(mouse_position) {
+                    self.released = true;
+                }
                 self.mouse_clicked = false;
             }
             _ => (),
         }
     }
 
+    // true once for every full click, for buttons that should only do something a single time
+    pub fn take_click(&mut self) -> bool {
+        let released = self.released && self.visible;
+        self.released = false;
+        released
+    }
+
     pub fn change_location(&mut self, position: Point2) {
         self.bounds.top_left = position;
         self.bounds.bottom_right = Point2::new(
@@ -329,6 +457,129 @@ impl Button {
     }
 }
 
+// a box the player can click on and type into
+pub struct TextField {
+    label: String,
+    text: String,
+    max_length: usize,
+    hidden: bool, // draw stars instead of what was typed, for passwords
+    focused: bool,
+    bounds: Rect,
+    pub visible: bool,
+}
+impl TextField {
+    pub fn new(
+        label: String,
+        text: String,
+        max_length: usize,
+        hidden: bool,
+        position: Point2,
+        width: f32,
+    ) -> TextField {
+        let bottom_right = Point2::new(position.x + width, position.y + 30.0);
+
+        TextField {
+            label: label,
+            text: text,
+            max_length: max_length,
+            hidden: hidden,
+            focused: false,
+            bounds: Rect::new(position, bottom_right),
+            visible: true,
+        }
+    }
+
+    pub fn draw(&self, ctx: &mut Context, font: &Font) -> GameResult {
+        if self.visible == false {
+            return Ok(());
+        }
+
+        // the label sits just above the box
+        let label = create_text(self.label.clone(), font, graphics::Scale::uniform(12.0));
+        let label_position = Point2::new(self.bounds.top_left.x, self.bounds.top_left.y - 16.0);
+        graphics::draw(
+            ctx,
+            &label,
+            DrawParam::new().dest(label_position.as_mint_point()),
+        )
+        .unwrap();
+
+        let fill_color = if self.focused {
+            Color::new(1.0, 1.0, 0.85, 1.0)
+        } else {
+            Color::new(1.0, 1.0, 1.0, 1.0)
+        };
+        let fill = create_rect_mesh(
+            ctx,
+            &self.bounds,
+            graphics::DrawMode::Fill(graphics::FillOptions::default()),
+            fill_color,
+        );
+        let outline = create_rect_mesh(
+            ctx,
+            &self.bounds,
+            graphics::DrawMode::Stroke(graphics::StrokeOptions::default()),
+            Color::new(0.0, 0.0, 0.0, 1.0),
+        );
+        graphics::draw(ctx, &fill, DrawParam::new()).unwrap();
+        graphics::draw(ctx, &outline, DrawParam::new()).unwrap();
+
+        let mut shown = if self.hidden {
+            "*".repeat(self.text.chars().count())
+        } else {
+            self.text.clone()
+        };
+        if self.focused {
+            shown.push('_');
+        }
+        let text = create_text(shown, font, graphics::Scale::uniform(16.0));
+        let text_position = Point2::new(self.bounds.top_left.x + 7.0, self.bounds.top_left.y + 7.0);
+        graphics::draw(
+            ctx,
+            &text,
+            DrawParam::new()
+                .dest(text_position.as_mint_point())
+                .color(Color::new(0.0, 0.0, 0.0, 1.0)),
+        )
+        .unwrap();
+
+        Ok(())
+    }
+
+    // clicking the box starts typing into it, clicking anywhere else stops
+    pub fn mouse_button_down_event(&mut self, mouse_button: &MouseButton, mouse_position: &Point2) {
+        if self.visible == false {
+            return;
+        }
+        if let MouseButton::Left = mouse_button {
+            self.focused = self.bounds.check_bounds_point(mouse_position);
+        }
+    }
+
+    pub fn text_input_event(&mut self, character: char) {
+        if self.visible == false || self.focused == false {
+            return;
+        }
+        // backspace and friends come through here too, they are handled as keys instead
+        if character.is_control() == false && self.text.chars().count() < self.max_length {
+            self.text.push(character);
+        }
+    }
+
+    pub fn key_down_event(&mut self, keycode: KeyCode) {
+        if self.visible == false || self.focused == false {
+            return;
+        }
+        if let KeyCode::Back = keycode {
+            self.text.pop();
+        }
+    }
+
+    pub fn get_text(&self) -> &str {
+        &self.text
+    }
+}
+
 pub fn create_text_with_background(
     ctx: &mut Context,
     text: String,
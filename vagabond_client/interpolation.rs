@@ -0,0 +1,297 @@
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+use vagabond_protocol::server_data::ServerEntity;
+
+use crate::geometry::Point2;
+
+// how far in the past remote entities are drawn by default, enough to always have
+// a snapshot on either side even when a few arrive late
+pub const DEFAULT_INTERPOLATION_DELAY: Duration = Duration::from_millis(100);
+
+// draws an entity we do not control slightly in the past so there is always a snapshot
+// before and after the moment we draw, and blends between them
+pub struct Interpolator {
+    snapshots: VecDeque<(u32, ServerEntity)>, // server tick and the entity on that tick, oldest first
+    latest_received: Option<Instant>,         // when the newest snapshot arrived
+    delay: Duration,
+    tick_rate: u32, // the server's, snapshot ticks are turned into time with it
+}
+
+impl Interpolator {
+    pub fn new(delay: Duration, tick_rate: u32) -> Interpolator {
+        Interpolator {
+            snapshots: VecDeque::with_capacity(tick_rate as usize),
+            latest_received: None,
+            delay,
+            tick_rate,
+        }
+    }
+
+    pub fn push(&mut self, tick: u32, entity: &ServerEntity, received: Instant) {
+        if let Some((latest_tick, _)) = self.snapshots.back() {
+            // the server already sent us this tick or a later one
+            if ticks_between(*latest_tick, tick) <= 0 {
+                return;
+            }
+        }
+
+        // the round restarted and the entity was sent back to its spawn, sliding it
+        // there from across the screen would look wrong. the count only goes up so a
+        // restart is noticed even when the snapshot it happened on never arrived
+        let reset = match self.snapshots.back() {
+            Some((_, latest)) => latest.get_resets() != entity.get_resets(),
+            None => false,
+        };
+        if reset {
+            self.snapshots.clear();
+        }
+
+        self.snapshots.push_back((tick, entity.clone()));
+        self.latest_received = Some(received);
+
+        // once the second oldest snapshot is further back than we ever draw,
+        // the oldest one can not be needed anymore
+        // and never more than a second of them
+        let delay_ticks = self.delay_ticks();
+        while self.snapshots.len() > self.tick_rate as usize
+            || (self.snapshots.len() > 2 && self.age(1) as f32 >= delay_ticks)
+        {
+            self.snapshots.pop_front();
+        }
+    }
+
+    // the entity as it was `delay` ago
+    pub fn sample(&self, now: Instant) -> Option<ServerEntity> {
+        let latest_received = self.latest_received?;
+
+        // how many ticks behind the newest snapshot we want to draw
+        let elapsed = now.duration_since(latest_received).as_secs_f32() * self.tick_rate as f32;
+        let target_age = self.delay_ticks() - elapsed;
+
+        // find the two snapshots either side of the target, anything outside of the
+        // buffer uses the closest snapshot we have
+        for index in (0..self.snapshots.len()).rev() {
+            let age = self.age(index) as f32;
+            if age < target_age {
+                continue;
+            }
+
+            let (_, older) = &self.snapshots[index];
+            if index + 1 == self.snapshots.len() {
+                return Some(older.clone());
+            }
+
+            let (_, newer) = &self.snapshots[index + 1];
+            let newer_age = self.age(index + 1) as f32;
+            let alpha = (age - target_age) / (age - newer_age);
+
+            return Some(blend(older, newer, alpha));
+        }
+
+        self.snapshots.front().map(|(_, oldest)| oldest.clone())
+    }
+
+    fn delay_ticks(&self) -> f32 {
+        self.delay.as_secs_f32() * self.tick_rate as f32
+    }
+
+    // how many ticks older the snapshot at the index is than the newest one
+    fn age(&self, index: usize) -> i32 {
+        let (latest_tick, _) = self.snapshots.back().unwrap();
+        ticks_between(self.snapshots[index].0, *latest_tick)
+    }
+}
+
+// ticks wrap around so only the distance between them matters
+fn ticks_between(from: u32, to: u32) -> i32 {
+    to.wrapping_sub(from) as i32
+}
+
+// position is blended, everything else (facing, blocking, attack frame, hp) stays
+// what it was on the older snapshot until the newer one is reached
+fn blend(older: &ServerEntity, newer: &ServerEntity, alpha: f32) -> ServerEntity {
+    let mut entity = older.clone();
+    let offset = Point2::new(
+        (newer.pos.x - older.pos.x) * alpha,
+        (newer.pos.y - older.pos.y) * alpha,
+    );
+
+    entity.pos = Point2::new(older.pos.x + offset.x, older.pos.y + offset.y);
+    entity.bound.translate(&offset);
+    entity.attack_bound.translate(&offset);
+
+    entity
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use vagabond_protocol::server_data::{Action, ServerAnimator};
+
+    use crate::constants::TICK_RATE;
+
+    const TICK: Duration = Duration::from_micros(1_000_000 / TICK_RATE as u64);
+
+    fn entity_at(x: f32) -> ServerEntity {
+        let mut entity = ServerEntity::new(1);
+        let offset = Point2::new(x - entity.pos.x, 0.0);
+        entity.pos.x = x;
+        entity.bound.translate(&offset);
+        entity.attack_bound.translate(&offset);
+        entity
+    }
+
+    // one snapshot every tick starting at x = 0 and moving 10 every tick
+    fn filled(delay: Duration, ticks: u32, start: Instant) -> Interpolator {
+        let mut interpolator = Interpolator::new(delay, TICK_RATE);
+        for tick in 0..ticks {
+            interpolator.push(tick, &entity_at(tick as f32 * 10.0), start + TICK * tick);
+        }
+        interpolator
+    }
+
+    #[test]
+    fn empty_buffer_has_nothing_to_draw() {
+        let interpolator = Interpolator::new(DEFAULT_INTERPOLATION_DELAY, TICK_RATE);
+        assert!(interpolator.sample(Instant::now()).is_none());
+    }
+
+    #[test]
+    fn position_is_blended_between_snapshots() {
+        let start = Instant::now();
+        let interpolator = filled(TICK * 4, 10, start);
+
+        // drawing four ticks behind the newest snapshot (tick 9)
+        let latest = start + TICK * 9;
+        let entity = interpolator.sample(latest).unwrap();
+        assert!((entity.pos.x - 50.0).abs() < 0.01);
+
+        // half a tick later we are half way to the next snapshot
+        let entity = interpolator.sample(latest + TICK / 2).unwrap();
+        assert!((entity.pos.x - 55.0).abs() < 0.01);
+        assert!((entity.bound.top_left.x - (entity_at(55.0).bound.top_left.x)).abs() < 0.01);
+    }
+
+    #[test]
+    fn discrete_state_comes_from_the_older_snapshot() {
+        let start = Instant::now();
+        let mut interpolator = Interpolator::new(TICK * 2, TICK_RATE);
+
+        let mut attacking = entity_at(0.0);
+        attacking.entity_actions.facing = Action::Right;
+        attacking.entity_actions.attacking = true;
+        attacking.attack_animator = ServerAnimator::with_frame(1, 0);
+        interpolator.push(0, &attacking, start);
+
+        let mut blocking = entity_at(10.0);
+        blocking.entity_actions.facing = Action::Left;
+        blocking.entity_actions.blocking = true;
+        interpolator.push(1, &blocking, start + TICK);
+        interpolator.push(2, &blocking, start + TICK * 2);
+
+        // a quarter of the way there, still attacking
+        let entity = interpolator.sample(start + TICK * 2 + TICK / 4).unwrap();
+        assert!(entity.entity_actions.attacking);
+        assert!(!entity.entity_actions.blocking);
+        assert_eq!(entity.attack_animator.current_frame(), 1);
+        assert!(matches!(entity.entity_actions.facing, Action::Right));
+
+        // and once the newer snapshot is reached its state takes over
+        let entity = interpolator.sample(start + TICK * 3 + TICK / 4).unwrap();
+        assert!(entity.entity_actions.blocking);
+        assert!(!entity.entity_actions.attacking);
+    }
+
+    #[test]
+    fn newest_snapshot_is_held_when_updates_stop() {
+        let start = Instant::now();
+        let interpolator = filled(TICK * 4, 10, start);
+
+        // no extrapolation past the last thing the server told us
+        let entity = interpolator.sample(start + Duration::from_secs(5)).unwrap();
+        assert_eq!(entity.pos.x, 90.0);
+    }
+
+    #[test]
+    fn oldest_snapshot_is_used_without_enough_history() {
+        let start = Instant::now();
+        let interpolator = filled(TICK * 20, 3, start);
+
+        let entity = interpolator.sample(start + TICK * 2).unwrap();
+        assert_eq!(entity.pos.x, 0.0);
+    }
+
+    #[test]
+    fn delay_is_configurable() {
+        let start = Instant::now();
+        let latest = start + TICK * 29;
+
+        let short = filled(TICK * 2, 30, start);
+        let long = filled(TICK * 12, 30, start);
+        assert!((short.sample(latest).unwrap().pos.x - 270.0).abs() < 0.01);
+        assert!((long.sample(latest).unwrap().pos.x - 170.0).abs() < 0.01);
+    }
+
+    #[test]
+    fn delay_follows_the_server_tick_rate() {
+        // a server running at half the rate sends half as many snapshots, 100ms is 3 ticks
+        let tick = TICK * 2;
+        let start = Instant::now();
+        let mut interpolator = Interpolator::new(Duration::from_millis(100), TICK_RATE / 2);
+        for index in 0..10 {
+            interpolator.push(index, &entity_at(index as f32 * 10.0), start + tick * index);
+        }
+
+        let entity = interpolator.sample(start + tick * 9).unwrap();
+        assert!((entity.pos.x - 60.0).abs() < 0.01);
+    }
+
+    #[test]
+    fn stale_and_duplicate_snapshots_are_ignored() {
+        let start = Instant::now();
+        let mut interpolator = filled(TICK * 2, 5, start);
+
+        interpolator.push(4, &entity_at(1000.0), start + TICK * 5);
+        interpolator.push(2, &entity_at(1000.0), start + TICK * 5);
+
+        let entity = interpolator.sample(start + TICK * 10).unwrap();
+        assert_eq!(entity.pos.x, 40.0);
+    }
+
+    #[test]
+    fn reset_snapshot_is_not_blended() {
+        let start = Instant::now();
+        let mut interpolator = filled(TICK * 4, 10, start);
+
+        // the snapshot the reset happened on was lost, the next one still shows it
+        let mut spawn = ServerEntity::new(1);
+        spawn.resets = 1;
+        interpolator.push(11, &spawn, start + TICK * 11);
+
+        let entity = interpolator.sample(start + TICK * 11).unwrap();
+        assert_eq!(entity.pos, spawn.pos);
+    }
+
+    #[test]
+    fn buffer_only_keeps_what_it_needs() {
+        let start = Instant::now();
+        let interpolator = filled(TICK * 4, 200, start);
+
+        assert!(interpolator.snapshots.len() <= 6);
+        assert!((interpolator.sample(start + TICK * 199).unwrap().pos.x - 1950.0).abs() < 0.01);
+    }
+
+    #[test]
+    fn ticks_can_wrap() {
+        let start = Instant::now();
+        let mut interpolator = Interpolator::new(TICK, TICK_RATE);
+
+        interpolator.push(u32::MAX, &entity_at(0.0), start);
+        interpolator.push(0, &entity_at(10.0), start + TICK);
+
+        let entity = interpolator.sample(start + TICK + TICK / 2).unwrap();
+        assert!((entity.pos.x - 5.0).abs() < 0.1);
+    }
+}
@@ -0,0 +1,451 @@
+use ggez::event::KeyCode;
+use ggez::graphics::{DrawParam, Font, Scale};
+use ggez::input::mouse::MouseButton;
+use ggez::{Context, GameResult};
+
+use std::time::{Duration, Instant};
+
+use vagabond_protocol::handshake::ServerInfo;
+use vagabond_protocol::lobby::{LobbyRequest, LobbyResponse, RoomSummary, MAX_ROOM_NAME_LENGTH};
+use vagabond_protocol::server_data::MatchStatus;
+
+use crate::constants::SCREEN_WIDTH;
+use crate::game_data::MouseControlledActor;
+use crate::geometry::{AsMint, Point2};
+use crate::gui_data::{create_text_with_background, Button, TextField};
+use crate::network::ConnectionStatus;
+
+// how often the room list is asked for again while looking at it
+const REFRESH_TIME: Duration = Duration::from_secs(1);
+
+// rooms that fit on the screen, anything past this is not shown
+const MAX_ROOMS_SHOWN: usize = 7;
+
+const ROOM_LIST_TOP: f32 = 130.0;
+const ROOM_ROW_HEIGHT: f32 = 40.0;
+const WATCH_BUTTON_X: f32 = 660.0;
+
+// addresses and passwords longer than this do not fit in their box
+const MAX_ADDRESS_LENGTH: usize = 21;
+const MAX_PASSWORD_LENGTH: usize = 16;
+
+// what the lobby wants done after the player clicked on something
+pub enum LobbyAction {
+    Connect(String), // open a connection to this address
+    Send(LobbyRequest),
+}
+
+// where players pick a server, then a room to play in
+pub struct Lobby {
+    font: Font,
+    server_info: Option<ServerInfo>,
+    message: Option<String>, // why the last request failed
+    connected: bool,
+    queue_position: Option<u32>, // set while waiting for an opponent, 0 until the server answers
+    last_refresh: Option<Instant>,
+    rooms: Vec<RoomSummary>,
+    rooms_changed: bool, // the buttons for the rooms have to be made again
+    room_buttons: Vec<Button>,
+    watch_buttons: Vec<Button>, // one next to every room button
+    address_field: TextField,
+    name_field: TextField,
+    password_field: TextField,
+    connect_button: Button,
+    create_button: Button,
+    quick_match_button: Button,
+    cancel_button: Button,
+}
+
+impl Lobby {
+    pub fn new(ctx: &mut Context, font: Font, ip_address: String) -> Lobby {
+        let scale = Scale::uniform(16.0);
+
+        let address_field = TextField::new(
+            String::from("Server address"),
+            ip_address,
+            MAX_ADDRESS_LENGTH,
+            false,
+            Point2::new(40.0, 470.0),
+            360.0,
+        );
+        let name_field = TextField::new(
+            String::from("Room name"),
+            String::new(),
+            MAX_ROOM_NAME_LENGTH,
+            false,
+            Point2::new(40.0, 470.0),
+            420.0,
+        );
+        let password_field = TextField::new(
+            String::from("Password (optional)"),
+            String::new(),
+            MAX_PASSWORD_LENGTH,
+            true,
+            Point2::new(40.0, 530.0),
+            420.0,
+        );
+
+        let connect_button = Button::new(
+            ctx,
+            String::from("Connect"),
+            &font,
+            scale,
+            Point2::new(420.0, 468.0),
+            false,
+        );
+        let create_button = Button::new(
+            ctx,
+            String::from("Create room"),
+            &font,
+            scale,
+            Point2::new(500.0, 468.0),
+            false,
+        );
+        let quick_match_button = Button::new(
+            ctx,
+            String::from("Quick match"),
+            &font,
+            scale,
+            Point2::new(500.0, 528.0),
+            false,
+        );
+        let cancel_button = Button::new(
+            ctx,
+            String::from("Cancel"),
+            &font,
+            scale,
+            Point2::new(500.0, 528.0),
+            false,
+        );
+
+        Lobby {
+            font,
+            server_info: None,
+            message: None,
+            connected: false,
+            queue_position: None,
+            last_refresh: None,
+            rooms: Vec::new(),
+            rooms_changed: false,
+            room_buttons: Vec::new(),
+            watch_buttons: Vec::new(),
+            address_field,
+            name_field,
+            password_field,
+            connect_button,
+            create_button,
+            quick_match_button,
+            cancel_button,
+        }
+    }
+
+    // works out what is on screen for the connection and turns clicks into actions
+    pub fn update(&mut self, ctx: &mut Context, status: &ConnectionStatus) -> Vec<LobbyAction> {
+        let mut actions = Vec::new();
+
+        let connected = matches!(
+            status,
+            ConnectionStatus::Connected | ConnectionStatus::Stalled
+        );
+        if connected != self.connected {
+            // whatever we knew about the rooms on the old connection is stale
+            self.connected = connected;
+            self.queue_position = None;
+            self.last_refresh = None;
+            self.rooms.clear();
+            self.rooms_changed = true;
+        }
+        if let ConnectionStatus::Lost(reason) = status {
+            self.message = Some(reason.clone());
+        }
+
+        if self.rooms_changed {
+            self.rebuild_room_buttons(ctx);
+        }
+
+        let queued = self.queue_position.is_some();
+        let choosing = connected && !queued;
+        self.address_field.visible = !connected;
+        self.connect_button.visible = matches!(status, ConnectionStatus::Lost(_));
+        self.name_field.visible = connected;
+        self.password_field.visible = connected;
+        self.create_button.visible = choosing;
+        self.quick_match_button.visible = choosing;
+        self.cancel_button.visible = connected && queued;
+        for button in self
+            .room_buttons
+            .iter_mut()
+            .chain(self.watch_buttons.iter_mut())
+        {
+            button.visible = choosing;
+        }
+
+        if self.connect_button.take_click() {
+            let ip_address = self.address_field.get_text().trim().to_string();
+            self.message = None;
+            actions.push(LobbyAction::Connect(ip_address));
+        }
+        if !connected {
+            return actions;
+        }
+
+        let refresh_due = match self.last_refresh {
+            Some(refreshed) => refreshed.elapsed() >= REFRESH_TIME,
+            None => true,
+        };
+        if refresh_due {
+            actions.push(LobbyAction::Send(LobbyRequest::ListRooms));
+            self.last_refresh = Some(Instant::now());
+        }
+
+        if self.create_button.take_click() {
+            self.message = None;
+            actions.push(LobbyAction::Send(LobbyRequest::CreateRoom {
+                name: self.name_field.get_text().to_string(),
+                password: self.password(),
+                rules: None, // whatever the server plays by
+            }));
+        }
+        if self.quick_match_button.take_click() {
+            self.message = None;
+            self.queue_position = Some(0);
+            actions.push(LobbyAction::Send(LobbyRequest::QuickMatch));
+        }
+        if self.cancel_button.take_click() {
+            self.queue_position = None;
+            actions.push(LobbyAction::Send(LobbyRequest::LeaveRoom));
+        }
+        for index in 0..self.room_buttons.len() {
+            if self.room_buttons[index].take_click() {
+                self.message = None;
+                actions.push(LobbyAction::Send(LobbyRequest::JoinRoom {
+                    room_id: self.rooms[index].room_id,
+                    password: self.password(),
+                }));
+            }
+            if self.watch_buttons[index].take_click() {
+                self.message = None;
+                actions.push(LobbyAction::Send(LobbyRequest::Spectate {
+                    room_id: self.rooms[index].room_id,
+                    password: self.password(),
+                }));
+            }
+        }
+
+        actions
+    }
+
+    pub fn handle_response(&mut self, response: LobbyResponse) {
+        match response {
+            LobbyResponse::Rooms(rooms) => {
+                if rooms != self.rooms {
+                    self.rooms = rooms;
+                    self.rooms_changed = true;
+                }
+            }
+            LobbyResponse::Waiting(position) => {
+                // an update might still be on its way after we left the queue
+                if self.queue_position.is_some() {
+                    self.queue_position = Some(position);
+                }
+            }
+            LobbyResponse::Rejected(reason) => {
+                self.queue_position = None;
+                self.message = Some(reason);
+            }
+            LobbyResponse::Joined(_) => self.queue_position = None,
+        }
+    }
+
+    pub fn set_server_info(&mut self, server_info: ServerInfo) {
+        self.server_info = Some(server_info);
+    }
+
+    pub fn draw(&self, ctx: &mut Context, status: &ConnectionStatus) -> GameResult {
+        let title = match (&self.server_info, status) {
+            (_, ConnectionStatus::Connecting) => String::from("Connecting..."),
+            (Some(server_info), ConnectionStatus::Connected)
+            | (Some(server_info), ConnectionStatus::Stalled) => server_info.name.clone(),
+            _ => String::from("Vagabond"),
+        };
+        self.draw_centered(ctx, title, 24.0, 40.0);
+
+        // the message of the day goes under the name of the server
+        let motd = self.server_info.as_ref().and_then(|info| info.motd.clone());
+        if let Some(motd) = motd.filter(|_| self.connected) {
+            self.draw_centered(ctx, motd, 12.0, 62.0);
+        }
+
+        // what is going on right now, or what went wrong last
+        let message = match (self.queue_position, &self.message) {
+            (Some(0), _) => Some(String::from("Looking for an opponent...")),
+            (Some(position), _) => Some(format!(
+                "Waiting for an opponent, number {} in the queue",
+                position
+            )),
+            (None, Some(message)) => Some(message.clone()),
+            (None, None) => None,
+        };
+        if let Some(message) = message {
+            self.draw_centered(ctx, message, 12.0, 85.0);
+        }
+
+        if self.connected {
+            if self.rooms.is_empty() {
+                let text = String::from("No rooms yet, create one or find a quick match");
+                self.draw_centered(ctx, text, 12.0, ROOM_LIST_TOP + 20.0);
+            }
+            for button in self.room_buttons.iter().chain(self.watch_buttons.iter()) {
+                button.draw(ctx).unwrap();
+            }
+        }
+
+        self.address_field.draw(ctx, &self.font).unwrap();
+        self.name_field.draw(ctx, &self.font).unwrap();
+        self.password_field.draw(ctx, &self.font).unwrap();
+        self.connect_button.draw(ctx).unwrap();
+        self.create_button.draw(ctx).unwrap();
+        self.quick_match_button.draw(ctx).unwrap();
+        self.cancel_button.draw(ctx).unwrap();
+
+        Ok(())
+    }
+
+    // one button per room, clicking it joins the room, and one to watch it instead
+    fn rebuild_room_buttons(&mut self, ctx: &mut Context) {
+        let mut room_buttons = Vec::new();
+        let mut watch_buttons = Vec::new();
+
+        for (index, room) in self.rooms.iter().take(MAX_ROOMS_SHOWN).enumerate() {
+            let y = ROOM_LIST_TOP + (index as f32 * ROOM_ROW_HEIGHT);
+            room_buttons.push(Button::new(
+                ctx,
+                describe_room(room),
+                &self.font,
+                Scale::uniform(12.0),
+                Point2::new(40.0, y),
+                false,
+            ));
+            watch_buttons.push(Button::new(
+                ctx,
+                String::from("Watch"),
+                &self.font,
+                Scale::uniform(12.0),
+                Point2::new(WATCH_BUTTON_X, y),
+                false,
+            ));
+        }
+
+        self.room_buttons = room_buttons;
+        self.watch_buttons = watch_buttons;
+        self.rooms_changed = false;
+    }
+
+    fn password(&self) -> Option<String> {
+        let password = self.password_field.get_text();
+        if password.is_empty() {
+            return None;
+        }
+
+        Some(password.to_string())
+    }
+
+    fn draw_centered(&self, ctx: &mut Context, text_string: String, size: f32, center_y: f32) {
+        let (text, mesh) =
+            create_text_with_background(ctx, text_string, &self.font, Scale::uniform(size));
+
+        let location = Point2::new(
+            (SCREEN_WIDTH / 2.0) - (text.width(ctx) as f32 / 2.0),
+            center_y - (text.height(ctx) as f32 / 2.0),
+        );
+
+        ggez::graphics::draw(ctx, &mesh, DrawParam::new().dest(location.as_mint_point())).unwrap();
+        ggez::graphics::draw(ctx, &text, DrawParam::new().dest(location.as_mint_point())).unwrap();
+    }
+}
+
+// typing goes to whichever field was clicked last
+impl Lobby {
+    pub fn text_input_event(&mut self, character: char) {
+        self.address_field.text_input_event(character);
+        self.name_field.text_input_event(character);
+        self.password_field.text_input_event(character);
+    }
+
+    pub fn key_down_event(&mut self, keycode: KeyCode) {
+        self.address_field.key_down_event(keycode);
+        self.name_field.key_down_event(keycode);
+        self.password_field.key_down_event(keycode);
+    }
+}
+
+impl MouseControlledActor for Lobby {
+    fn mouse_motion_event(&mut self, x: f32, y: f32) {
+        let mouse_position = Point2::new(x, y);
+
+        for button in self.buttons_mut() {
+            button.mouse_motion_event(&mouse_position);
+        }
+    }
+
+    fn mouse_button_up_event(&mut self, mouse_button: &MouseButton, x: f32, y: f32) {
+        let mouse_position = Point2::new(x, y);
+
+        for button in self.buttons_mut() {
+            button.mouse_button_up_event(mouse_button, &mouse_position);
+        }
+    }
+
+    fn mouse_button_down_event(&mut self, mouse_button: &MouseButton, x: f32, y: f32) {
+        let mouse_position = Point2::new(x, y);
+
+        for button in self.buttons_mut() {
+            button.mouse_button_down_event(mouse_button, &mouse_position);
+        }
+        self.address_field
+            .mouse_button_down_event(mouse_button, &mouse_position);
+        self.name_field
+            .mouse_button_down_event(mouse_button, &mouse_position);
+        self.password_field
+            .mouse_button_down_event(mouse_button, &mouse_position);
+    }
+}
+
+impl Lobby {
+    fn buttons_mut(&mut self) -> Vec<&mut Button> {
+        let mut buttons = vec![
+            &mut self.connect_button,
+            &mut self.create_button,
+            &mut self.quick_match_button,
+            &mut self.cancel_button,
+        ];
+        buttons.extend(self.room_buttons.iter_mut());
+        buttons.extend(self.watch_buttons.iter_mut());
+        buttons
+    }
+}
+
+// a line in the room list, for example "Dojo  2/2  fighting  3 watching  [locked]"
+fn describe_room(room: &RoomSummary) -> String {
+    let status = if room.players < room.max_players {
+        "waiting"
+    } else {
+        match room.status {
+            MatchStatus::InProgress => "fighting",
+            _ => "finished",
+        }
+    };
+
+    let mut description = format!(
+        "{}  {}/{}  {}",
+        room.name, room.players, room.max_players, status
+    );
+    if room.spectators > 0 {
+        description.push_str(&format!("  {} watching", room.spectators));
+    }
+    if room.locked {
+        description.push_str("  [locked]");
+    }
+
+    description
+}
@@ -1,22 +1,23 @@
 use ggez;
-use ggez::conf::{FullscreenType, WindowMode};
+use ggez::conf::{FullscreenType, WindowMode, WindowSetup};
 use ggez::event::{self, EventHandler, KeyCode, KeyMods};
 use ggez::graphics;
 use ggez::graphics::{DrawParam, FilterMode, Font, Image, Rect};
 use ggez::input::mouse::MouseButton;
 use ggez::nalgebra::Point2;
+use ggez::timer;
 use ggez::{Context, GameResult};
 
 use cgmath::Vector2;
 
 // use serde_json;
-use bincode;
+use vagabond_protocol::handshake::Role;
+use vagabond_protocol::lobby::{LobbyRequest, LobbyResponse};
+use vagabond_protocol::message::{ClientMessage, ServerMessage};
 
 use std::env;
-use std::io::{Read, Write};
-use std::net::TcpStream; // Shutdown
 use std::path::{Path, PathBuf};
-use std::str::from_utf8;
+use std::time::{Duration, Instant};
 
 mod animate;
 mod constants;
@@ -24,11 +25,17 @@ mod entity_data;
 mod game_data;
 mod geometry;
 mod gui_data;
-mod server_data;
+mod interpolation;
+mod lobby;
+mod network;
+mod prediction;
 
-use constants::{SCALE, SCREEN_HEIGHT, SCREEN_WIDTH, TILE_SIZE};
+use constants::{SCALE, SCREEN_HEIGHT, SCREEN_WIDTH, TICK_RATE, TILE_SIZE};
 use game_data::{GameMatch, KeyboardControlledActor, MouseControlledActor};
-use server_data::ServerGameMatch;
+use gui_data::create_text_with_background;
+use interpolation::DEFAULT_INTERPOLATION_DELAY;
+use lobby::{Lobby, LobbyAction};
+use network::{Connection, Transport};
 
 /*************************************************************
  *  TODO: Place all images into a spritesheet and subdivide  *
@@ -36,47 +43,76 @@ use server_data::ServerGameMatch;
  *  achieved.                                                *
  *************************************************************/
 
+// how long a notice from the server stays on screen
+const NOTICE_TIME: Duration = Duration::from_secs(6);
+
 struct MainState {
-    game_match: GameMatch,
+    lobby: Lobby,
+    game_match: Option<GameMatch>, // only there while we have a room
+    font: Font,
+    interpolation_delay: Duration,
+    transport: Transport, // every connection the player opens uses this
+    title_set: bool,      // the window shows the server name once we know it
+    notice: Option<(String, Instant)>, // the last notice from the server and when it came in
     entity_spritesheet: Image,
     entity_drawparams: Vec<DrawParam>,
     background_assets: Vec<Image>,
-    server: TcpStream,
+    connection: Connection,
 }
 
 impl MainState {
-    fn new(ctx: &mut Context, ip_address: String) -> GameResult<MainState> {
-        let mut server = TcpStream::connect(ip_address).unwrap();
-
-        let mut data = [0u8; 4096];
-
-        let id: usize;
-        let mut string_data: String;
-
+    fn new(
+        ctx: &mut Context,
+        ip_address: String,
+        interpolation_delay: Duration,
+        transport: Transport,
+    ) -> GameResult<MainState> {
         // load assets
         let (entity_spritesheet, entity_drawparams, background_assets) =
             MainState::load_images(ctx);
 
         let font = Font::new(ctx, "/Fonts/PressStart2P-vaV7.ttf").unwrap();
 
-        // acquire id
-        server.read(&mut data).unwrap();
-        string_data = String::from(from_utf8(&data).unwrap());
-        string_data = String::from(string_data.trim_matches(char::from(0)));
-        id = string_data.parse().unwrap(); // because id's type is declared earlier we do not need to do `parse::<u8>()`
-        println!("{}", id);
-        let gm = GameMatch::new(ctx, id, font);
+        let lobby = Lobby::new(ctx, font.clone(), ip_address.clone());
+
+        // try the address we were started with right away, the lobby lets the player pick
+        // another one if it does not work out
+        let connection = Connection::open(ip_address, transport);
 
         let s = MainState {
-            game_match: gm,
+            lobby: lobby,
+            game_match: None,
+            font: font,
+            interpolation_delay: interpolation_delay,
+            transport: transport,
+            title_set: false,
+            notice: None,
             entity_spritesheet: entity_spritesheet,
             entity_drawparams: entity_drawparams,
             background_assets: background_assets,
-            server: server,
+            connection: connection,
         };
         Ok(s)
     }
 
+    // notices go along the bottom of the screen, over the lobby or the match
+    fn draw_notice(&self, ctx: &mut Context) {
+        let notice = match &self.notice {
+            Some((notice, _)) => notice.clone(),
+            None => return,
+        };
+
+        let (text, mesh) =
+            create_text_with_background(ctx, notice, &self.font, graphics::Scale::uniform(12.0));
+        let location = Point2::new(
+            (SCREEN_WIDTH / 2.0) - (text.width(ctx) as f32 / 2.0),
+            SCREEN_HEIGHT - 40.0,
+        );
+
+        graphics::draw(ctx, &mesh, DrawParam::new().dest(location)).unwrap();
+        graphics::draw(ctx, &text, DrawParam::new().dest(location)).unwrap();
+    }
+
     fn load_images(ctx: &mut Context) -> (Image, Vec<DrawParam>, Vec<Image>) {
         // get path to Background and Samurai directories
         let mut background_directory = Path::new("/Backgrounds/dojo.png");
@@ -126,30 +162,123 @@ impl MainState {
 }
 
 impl EventHandler for MainState {
-    fn update(&mut self, _ctx: &mut Context) -> GameResult {
-        // create the ServerGameMatch that we will serialize
-        let server_game_match = ServerGameMatch::from_game_match(&self.game_match);
-
-        // serialize the data
-        let serialized_data: Vec<u8> = bincode::serialize(&server_game_match).unwrap();
-
-        // Send serialized byte data to server
-        self.server.write(&serialized_data).unwrap();
-
-        // self.server.write_all(&data_as_bytes).expect("Could not write bytes to stream");
-        self.server.flush().expect("Could not flush stream");
-
+    fn update(&mut self, ctx: &mut Context) -> GameResult {
         // code for receiving the data from the server goes here
-        let mut data = [0u8; 1024];
+        // the network thread has already read everything, so this never waits
+        for message in self.connection.poll() {
+            match message {
+                ServerMessage::Snapshot(snapshot) => {
+                    if let Some(game_match) = &mut self.game_match {
+                        game_match.update_from_snapshot(&snapshot);
+                    }
+                }
+                ServerMessage::Lobby(LobbyResponse::Joined(welcome)) => {
+                    self.lobby
+                        .handle_response(LobbyResponse::Joined(welcome.clone()));
+                    let game_match = match welcome.role {
+                        Role::Player => {
+                            println!(
+                                "Joined room {} as player {}",
+                                welcome.room_id,
+                                welcome.player_id + 1
+                            );
+                            GameMatch::new(
+                                ctx,
+                                welcome.player_id,
+                                &welcome.rules,
+                                welcome.tick_rate,
+                                self.font.clone(),
+                                self.interpolation_delay,
+                            )
+                        }
+                        Role::Spectator => {
+                            println!("Watching room {}", welcome.room_id);
+                            GameMatch::spectate(
+                                ctx,
+                                &welcome.rules,
+                                welcome.tick_rate,
+                                self.font.clone(),
+                                self.interpolation_delay,
+                            )
+                        }
+                    };
+                    self.game_match = Some(game_match);
+                }
+                ServerMessage::Lobby(response) => {
+                    // the room closed or the player was sent out of it, which puts them back
+                    // in the lobby
+                    if let LobbyResponse::Rejected(_) = &response {
+                        self.game_match = None;
+                    }
+                    self.lobby.handle_response(response);
+                }
+                ServerMessage::Notice(notice) => {
+                    println!("Notice from the server: {}", notice);
+                    self.notice = Some((notice, Instant::now()));
+                }
+            }
+        }
 
-        self.server.read(&mut data).unwrap();
+        if let Some((_, received)) = &self.notice {
+            if received.elapsed() >= NOTICE_TIME {
+                self.notice = None;
+            }
+        }
 
-        // deserialized data code goes here
-        let server_match: ServerGameMatch = bincode::deserialize(&data).unwrap();
-        self.game_match.update_from_server_game_match(&server_match);
+        if self.title_set == false {
+            if let Some(server_info) = self.connection.get_server_info() {
+                let title = format!("Vagabond - {}", server_info.name);
+                graphics::set_window_title(ctx, &title);
+                self.lobby.set_server_info(server_info.clone());
+                self.title_set = true;
+            }
+        }
 
-        // update the match on client end
-        self.game_match.update().unwrap();
+        let status = self.connection.get_status();
+        match &mut self.game_match {
+            Some(game_match) => {
+                // inputs go out at the rate the server simulates them so the server
+                // uses up exactly one of them every tick, each one is predicted as it goes
+                while timer::check_update_time(ctx, game_match.get_tick_rate()) {
+                    if game_match.is_spectating() {
+                        continue;
+                    }
+                    // the server only needs to know which buttons are pressed
+                    self.connection
+                        .send(ClientMessage::Input(game_match.next_input()));
+                    if let Some(vote) = game_match.take_rematch_vote() {
+                        self.connection.send(ClientMessage::Rematch(vote));
+                    }
+                }
+                game_match.set_connection_status(status);
+
+                // update the match on client end
+                game_match.update().unwrap();
+
+                // quitting takes us back to the lobby instead of closing the game
+                if game_match.take_leave_request() {
+                    self.connection
+                        .send(ClientMessage::Lobby(LobbyRequest::LeaveRoom));
+                    self.game_match = None;
+                }
+            }
+            None => {
+                // nothing is sent on a timer from the lobby, but the timer still has to be drained
+                while timer::check_update_time(ctx, TICK_RATE) {}
+
+                for action in self.lobby.update(ctx, &status) {
+                    match action {
+                        LobbyAction::Connect(ip_address) => {
+                            self.connection = Connection::open(ip_address, self.transport);
+                            self.title_set = false;
+                        }
+                        LobbyAction::Send(request) => {
+                            self.connection.send(ClientMessage::Lobby(request))
+                        }
+                    }
+                }
+            }
+        }
 
         Ok(())
     }
@@ -157,10 +286,29 @@ impl EventHandler for MainState {
     fn draw(&mut self, ctx: &mut Context) -> GameResult {
         graphics::clear(ctx, [0.1, 0.2, 0.3, 1.0].into());
 
-        // draw background
+        let game_match = match &mut self.game_match {
+            Some(game_match) => game_match,
+            None => {
+                // the lobby is drawn over the plain background
+                self.lobby
+                    .draw(ctx, &self.connection.get_status())
+                    .expect("Draw call for Lobby failed");
+
+                self.draw_notice(ctx);
+                graphics::present(ctx)?;
+                return Ok(());
+            }
+        };
+
+        // draw background, the inside of the dojo is saved for sudden death
+        let background = if game_match.is_sudden_death() {
+            &self.background_assets[1]
+        } else {
+            &self.background_assets[0]
+        };
         graphics::draw(
             ctx,
-            &self.background_assets[0],
+            background,
             graphics::DrawParam::new()
                 .dest(Point2::<f32>::new(0.0, 0.0))
                 .scale(Vector2::<f32>::new(4.0, 4.0)),
@@ -168,10 +316,11 @@ impl EventHandler for MainState {
         .expect("Draw call failed");
 
         // draw everything else
-        self.game_match
+        game_match
             .draw(ctx, &self.entity_spritesheet, &self.entity_drawparams)
             .expect("Draw call for GameMatch failed");
 
+        self.draw_notice(ctx);
         graphics::present(ctx)?;
         Ok(())
     }
@@ -183,33 +332,67 @@ impl EventHandler for MainState {
         _keymods: KeyMods,
         _repeat: bool,
     ) {
-        self.game_match.key_down_event(keycode, _keymods, _repeat);
+        match &mut self.game_match {
+            Some(game_match) => game_match.key_down_event(keycode, _keymods, _repeat),
+            None => self.lobby.key_down_event(keycode),
+        }
     }
 
     fn key_up_event(&mut self, _ctx: &mut Context, keycode: KeyCode, keymods: KeyMods) {
-        self.game_match.key_up_event(keycode, keymods);
+        if let Some(game_match) = &mut self.game_match {
+            game_match.key_up_event(keycode, keymods);
+        }
+    }
+
+    fn text_input_event(&mut self, _ctx: &mut Context, character: char) {
+        if self.game_match.is_none() {
+            self.lobby.text_input_event(character);
+        }
     }
 
     fn mouse_motion_event(&mut self, _ctx: &mut Context, x: f32, y: f32, _dx: f32, _dy: f32) {
-        self.game_match.mouse_motion_event(x, y);
+        match &mut self.game_match {
+            Some(game_match) => game_match.mouse_motion_event(x, y),
+            None => self.lobby.mouse_motion_event(x, y),
+        }
     }
 
     fn mouse_button_up_event(&mut self, _ctx: &mut Context, button: MouseButton, x: f32, y: f32) {
-        self.game_match.mouse_button_up_event(&button, x, y);
+        match &mut self.game_match {
+            Some(game_match) => game_match.mouse_button_up_event(&button, x, y),
+            None => self.lobby.mouse_button_up_event(&button, x, y),
+        }
     }
 
     fn mouse_button_down_event(&mut self, _ctx: &mut Context, button: MouseButton, x: f32, y: f32) {
-        self.game_match.mouse_button_down_event(&button, x, y);
+        match &mut self.game_match {
+            Some(game_match) => game_match.mouse_button_down_event(&button, x, y),
+            None => self.lobby.mouse_button_down_event(&button, x, y),
+        }
     }
 }
 
 pub fn main() -> GameResult {
     // command line args
-    let args: Vec<String> = env::args().collect();
-    let ip_address = match args.len() {
-        2 => args[1].clone(),
-        _ => String::from("127.0.0.1:1337"),
-    };
+    // usage: client [ip_address] [--interp-delay <milliseconds>] [--udp]
+    let mut ip_address = String::from("127.0.0.1:1337");
+    let mut interpolation_delay = DEFAULT_INTERPOLATION_DELAY;
+    let mut transport = Transport::Tcp;
+
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--interp-delay" => match args.next().map(|ms| ms.parse::<u64>()) {
+                Some(Ok(ms)) => interpolation_delay = Duration::from_millis(ms),
+                _ => {
+                    println!("--interp-delay needs a number of milliseconds");
+                    return Ok(());
+                }
+            },
+            "--udp" => transport = Transport::Udp, // the server has to be started with --udp too
+            _ => ip_address = arg,
+        }
+    }
 
     // window
     let window = WindowMode {
@@ -225,7 +408,8 @@ pub fn main() -> GameResult {
         resizable: false,
     };
 
-    let mut cb = ggez::ContextBuilder::new("Vagabond Client", "Trevor Crow");
+    let mut cb = ggez::ContextBuilder::new("Vagabond Client", "Trevor Crow")
+        .window_setup(WindowSetup::default().title("Vagabond"));
 
     // get and add resource path
     if let Ok(manifest_dir) = env::var("CARGO_MANIFEST_DIR") {
@@ -237,6 +421,6 @@ pub fn main() -> GameResult {
 
     // build and split context builder with window configuration
     let (ctx, event_loop) = &mut cb.window_mode(window).build()?;
-    let state = &mut MainState::new(ctx, ip_address)?;
+    let state = &mut MainState::new(ctx, ip_address, interpolation_delay, transport)?;
     event::run(ctx, event_loop, state)
 }
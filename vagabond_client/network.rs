@@ -0,0 +1,486 @@
+use std::fmt;
+use std::io::{self, Read, Write};
+use std::net::{Shutdown, SocketAddr, TcpStream, ToSocketAddrs};
+use std::sync::mpsc::{channel, Receiver, RecvTimeoutError, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use serde::Serialize;
+
+use vagabond_protocol::frame::{write_frame, FrameError, FrameReader};
+use vagabond_protocol::handshake::{Hello, HelloResponse, Role, ServerInfo, Welcome};
+use vagabond_protocol::lobby::{LobbyRequest, LobbyResponse};
+use vagabond_protocol::message::{ClientMessage, ServerMessage};
+use vagabond_protocol::udp::UdpConnection;
+
+// the server sends a snapshot every tick, being quiet for this long means something is wrong
+const STALL_TIME: Duration = Duration::from_millis(500);
+
+// and being quiet for this long means the connection is gone
+pub const SERVER_TIMEOUT: Duration = Duration::from_secs(5);
+
+// how often the writer checks whether the reader has lost the connection
+const WRITE_POLL_TIME: Duration = Duration::from_millis(50);
+
+// waits between reconnect attempts, doubling every time up to the max
+const FIRST_RETRY_DELAY: Duration = Duration::from_millis(250);
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(4);
+
+// after this long the server has given our slot away so there is no point in trying
+const GIVE_UP_TIME: Duration = Duration::from_secs(30);
+
+// what we talk to the server over. UDP does better on a connection that loses packets, a lost
+// snapshot or input is replaced by the next one instead of holding up everything behind it
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Transport {
+    Tcp,
+    Udp,
+}
+
+// the connection to the server, over UDP everything but inputs still gets there reliably
+enum ServerSocket {
+    Tcp(TcpStream),
+    Udp(Arc<UdpConnection>),
+}
+
+impl ServerSocket {
+    fn open(addr: &SocketAddr, transport: Transport) -> io::Result<ServerSocket> {
+        match transport {
+            Transport::Tcp => Ok(ServerSocket::Tcp(TcpStream::connect_timeout(
+                addr,
+                SERVER_TIMEOUT,
+            )?)),
+            Transport::Udp => Ok(ServerSocket::Udp(UdpConnection::connect(addr)?)),
+        }
+    }
+
+    fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        match self {
+            ServerSocket::Tcp(stream) => stream.set_read_timeout(timeout),
+            ServerSocket::Udp(connection) => connection.set_read_timeout(timeout),
+        }
+    }
+
+    fn try_clone(&self) -> io::Result<ServerSocket> {
+        match self {
+            ServerSocket::Tcp(stream) => Ok(ServerSocket::Tcp(stream.try_clone()?)),
+            ServerSocket::Udp(connection) => Ok(ServerSocket::Udp(connection.clone())),
+        }
+    }
+
+    fn shutdown(&self) {
+        match self {
+            ServerSocket::Tcp(stream) => {
+                let _ = stream.shutdown(Shutdown::Both);
+            }
+            ServerSocket::Udp(connection) => connection.close(),
+        }
+    }
+
+    // inputs are outdated by the next one, so over UDP a lost one is not sent again
+    fn write_sequenced<T: Serialize>(&mut self, message: &T) -> Result<(), FrameError> {
+        match self {
+            ServerSocket::Tcp(stream) => write_frame(stream, message),
+            ServerSocket::Udp(connection) => connection.write_sequenced(message),
+        }
+    }
+}
+
+impl Read for ServerSocket {
+    fn read(&mut self, buffer: &mut [u8]) -> io::Result<usize> {
+        match self {
+            ServerSocket::Tcp(stream) => stream.read(buffer),
+            ServerSocket::Udp(connection) => (&**connection).read(buffer),
+        }
+    }
+}
+
+impl Write for ServerSocket {
+    fn write(&mut self, buffer: &[u8]) -> io::Result<usize> {
+        match self {
+            ServerSocket::Tcp(stream) => stream.write(buffer),
+            ServerSocket::Udp(connection) => (&**connection).write(buffer),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            ServerSocket::Tcp(stream) => stream.flush(),
+            ServerSocket::Udp(connection) => (&**connection).flush(),
+        }
+    }
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub enum ConnectionStatus {
+    Connecting,
+    Connected,
+    Stalled, // nothing has arrived for a moment but the server might still answer
+    // connection dropped, this is the attempt we are on and what went wrong last
+    Reconnecting(u32, String),
+    Lost(String), // reason the connection is gone for good
+}
+
+#[derive(Debug)]
+pub enum ConnectError {
+    Unreachable(String), // could not get an answer, trying again might help
+    Rejected(String),    // the server answered and said no
+}
+
+impl fmt::Display for ConnectError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ConnectError::Unreachable(reason) => write!(f, "{}", reason),
+            ConnectError::Rejected(reason) => {
+                write!(f, "The server rejected the connection: {}", reason)
+            }
+        }
+    }
+}
+
+// connect to the server and introduce ourselves, the hello decides whether we start out in
+// the lobby or take back our place in a match from an earlier welcome
+fn connect(
+    ip_address: &str,
+    transport: Transport,
+    hello: &Hello,
+) -> Result<(ServerSocket, FrameReader<ServerSocket>, HelloResponse), ConnectError> {
+    let failed = |e: String| ConnectError::Unreachable(e);
+
+    let addr = ip_address
+        .to_socket_addrs()
+        .map_err(|e| failed(format!("Could not find {}: {}", ip_address, e)))?
+        .next()
+        .ok_or_else(|| failed(format!("Could not find {}", ip_address)))?;
+    let mut server = ServerSocket::open(&addr, transport)
+        .map_err(|e| failed(format!("Could not connect to {}: {}", ip_address, e)))?;
+
+    // a server that never answers the hello should not hang the client
+    server
+        .set_read_timeout(Some(SERVER_TIMEOUT))
+        .map_err(|e| failed(format!("Could not set a read timeout: {}", e)))?;
+
+    write_frame(&mut server, hello)
+        .map_err(|e| failed(format!("Could not send hello to the server: {}", e)))?;
+
+    // everything from the server is framed, so reading gets its own handle to the stream
+    let reader_stream = server
+        .try_clone()
+        .map_err(|e| failed(format!("Could not read from the server: {}", e)))?;
+    let mut server_reader = FrameReader::new(reader_stream);
+
+    match server_reader.read_frame() {
+        Ok(HelloResponse::Rejected(reason)) => Err(ConnectError::Rejected(reason)),
+        Ok(response) => Ok((server, server_reader, response)),
+        Err(e) => Err(failed(format!("Could not complete the handshake: {}", e))),
+    }
+}
+
+// what the network thread tells the game about
+enum NetworkEvent {
+    Entered(ServerInfo), // made it into the lobby of this server
+    Message(ServerMessage),
+    Reconnecting(u32, String),
+    Reconnected,
+    Lost(String),
+}
+
+// everything needed to find our way back into the match
+#[derive(Clone)]
+struct Session {
+    room_id: u32,
+    token: u64,
+    player_id: usize,
+}
+
+impl Session {
+    fn new(welcome: &Welcome) -> Session {
+        Session {
+            room_id: welcome.room_id,
+            token: welcome.session_token,
+            player_id: welcome.player_id,
+        }
+    }
+}
+
+// the match we are in, if any, shared between the threads that read and write
+type SharedSession = Arc<Mutex<Option<Session>>>;
+
+// talks to the server on its own threads so the game never waits on the network
+pub struct Connection {
+    outgoing: Sender<ClientMessage>,
+    incoming: Receiver<NetworkEvent>,
+    last_received: Instant,
+    status: ConnectionStatus,
+    server_info: Option<ServerInfo>,
+}
+
+impl Connection {
+    // connecting happens on the network thread, poll tells us when we are in the lobby
+    pub fn open(ip_address: String, transport: Transport) -> Connection {
+        let (outgoing, outgoing_receiver) = channel();
+        let (incoming_sender, incoming) = channel();
+
+        thread::spawn(move || {
+            run_connection(ip_address, transport, outgoing_receiver, incoming_sender)
+        });
+
+        Connection {
+            outgoing,
+            incoming,
+            last_received: Instant::now(),
+            status: ConnectionStatus::Connecting,
+            server_info: None,
+        }
+    }
+
+    // queue a message for the server, nothing is sent unless we are connected
+    pub fn send(&self, message: ClientMessage) {
+        match self.status {
+            ConnectionStatus::Connected | ConnectionStatus::Stalled => {
+                // the network thread only goes away after the connection is lost, which poll will report
+                let _ = self.outgoing.send(message);
+            }
+            _ => (),
+        }
+    }
+
+    // every message that arrived since the last call, oldest first
+    pub fn poll(&mut self) -> Vec<ServerMessage> {
+        let mut messages = Vec::new();
+
+        for event in self.incoming.try_iter() {
+            match event {
+                NetworkEvent::Entered(server_info) => {
+                    self.last_received = Instant::now();
+                    self.status = ConnectionStatus::Connected;
+                    self.server_info = Some(server_info);
+                }
+                NetworkEvent::Message(message) => {
+                    self.last_received = Instant::now();
+                    messages.push(message);
+                }
+                NetworkEvent::Reconnecting(attempt, reason) => {
+                    self.status = ConnectionStatus::Reconnecting(attempt, reason)
+                }
+                NetworkEvent::Reconnected => {
+                    self.last_received = Instant::now();
+                    self.status = ConnectionStatus::Connected;
+                }
+                NetworkEvent::Lost(reason) => self.status = ConnectionStatus::Lost(reason),
+            }
+        }
+
+        match self.status {
+            ConnectionStatus::Connected | ConnectionStatus::Stalled => {
+                self.status = if self.last_received.elapsed() > STALL_TIME {
+                    ConnectionStatus::Stalled
+                } else {
+                    ConnectionStatus::Connected
+                };
+            }
+            _ => (),
+        }
+
+        messages
+    }
+
+    pub fn get_status(&self) -> ConnectionStatus {
+        self.status.clone()
+    }
+
+    pub fn get_server_info(&self) -> Option<&ServerInfo> {
+        self.server_info.as_ref()
+    }
+}
+
+// gets us into the lobby and then keeps the connection going
+fn run_connection(
+    ip_address: String,
+    transport: Transport,
+    messages: Receiver<ClientMessage>,
+    events: Sender<NetworkEvent>,
+) {
+    let (server, server_reader) = match connect(&ip_address, transport, &Hello::new()) {
+        Ok((server, server_reader, HelloResponse::Lobby(server_info))) => {
+            let _ = events.send(NetworkEvent::Entered(server_info));
+            (server, server_reader)
+        }
+        Ok(_) => {
+            let reason = String::from("The server did not let us into its lobby");
+            let _ = events.send(NetworkEvent::Lost(reason));
+            return;
+        }
+        Err(e) => {
+            let _ = events.send(NetworkEvent::Lost(e.to_string()));
+            return;
+        }
+    };
+
+    run_session(
+        ip_address,
+        transport,
+        server,
+        server_reader,
+        messages,
+        events,
+    );
+}
+
+// sends messages for as long as the game is running, and when the connection drops in the
+// middle of a match gets us back into it with the session token
+fn run_session(
+    ip_address: String,
+    transport: Transport,
+    mut server: ServerSocket,
+    mut server_reader: FrameReader<ServerSocket>,
+    messages: Receiver<ClientMessage>,
+    events: Sender<NetworkEvent>,
+) {
+    let session: SharedSession = Arc::new(Mutex::new(None));
+
+    loop {
+        let (dropped_sender, dropped) = channel();
+        let reader_events = events.clone();
+        let reader_session = session.clone();
+        thread::spawn(move || {
+            read_messages(server_reader, reader_events, reader_session, dropped_sender)
+        });
+
+        // write until either side notices the connection is gone
+        let reason = loop {
+            if let Ok(reason) = dropped.try_recv() {
+                break reason;
+            }
+
+            match messages.recv_timeout(WRITE_POLL_TIME) {
+                Ok(message) => {
+                    // there is no match to come back to once we have left it
+                    if message == ClientMessage::Lobby(LobbyRequest::LeaveRoom) {
+                        *session.lock().unwrap() = None;
+                    }
+                    let sent = match message {
+                        ClientMessage::Input(_) => server.write_sequenced(&message),
+                        _ => write_frame(&mut server, &message),
+                    };
+                    if let Err(e) = sent {
+                        break format!("Could not send to the server: {}", e);
+                    }
+                }
+                Err(RecvTimeoutError::Timeout) => (),
+                Err(RecvTimeoutError::Disconnected) => {
+                    // the game has gone away
+                    server.shutdown();
+                    return;
+                }
+            }
+        };
+
+        // wakes up the reader if it is still waiting
+        server.shutdown();
+
+        // a spot in the lobby is not worth coming back for
+        let current_session = session.lock().unwrap().clone();
+        let current_session = match current_session {
+            Some(current_session) => current_session,
+            None => {
+                let _ = events.send(NetworkEvent::Lost(reason));
+                return;
+            }
+        };
+        match reconnect(&ip_address, transport, &current_session, reason, &events) {
+            Ok((new_server, new_server_reader)) => {
+                server = new_server;
+                server_reader = new_server_reader;
+
+                // inputs from while we were gone are stale, the server moved on without them
+                while messages.try_recv().is_ok() {}
+                let _ = events.send(NetworkEvent::Reconnected);
+            }
+            Err(reason) => {
+                let _ = events.send(NetworkEvent::Lost(reason));
+                return;
+            }
+        }
+    }
+}
+
+// keep trying to resume the session, waiting longer after every failed attempt
+fn reconnect(
+    ip_address: &str,
+    transport: Transport,
+    session: &Session,
+    mut reason: String,
+    events: &Sender<NetworkEvent>,
+) -> Result<(ServerSocket, FrameReader<ServerSocket>), String> {
+    let started = Instant::now();
+    let mut delay = FIRST_RETRY_DELAY;
+    let mut attempt = 1;
+
+    loop {
+        // the player sees why we are still trying instead of it scrolling past on the console
+        let status = NetworkEvent::Reconnecting(attempt, reason.clone());
+        if events.send(status).is_err() {
+            return Err(String::from("The game was closed"));
+        }
+
+        let hello = Hello::resume(session.room_id, session.token);
+        match connect(ip_address, transport, &hello) {
+            Ok((server, server_reader, HelloResponse::Welcome(welcome))) => {
+                if welcome.player_id != session.player_id {
+                    return Err(String::from("The server gave us a different player"));
+                }
+                return Ok((server, server_reader));
+            }
+            Ok(_) => return Err(String::from("The server did not put us back in the match")),
+            // the server is there but our slot is not coming back
+            Err(ConnectError::Rejected(reason)) => return Err(reason),
+            Err(ConnectError::Unreachable(failure)) => reason = failure,
+        }
+
+        if started.elapsed() + delay > GIVE_UP_TIME {
+            return Err(String::from("Lost connection to the server"));
+        }
+
+        thread::sleep(delay);
+        delay = (delay * 2).min(MAX_RETRY_DELAY);
+        attempt += 1;
+    }
+}
+
+fn read_messages(
+    mut server_reader: FrameReader<ServerSocket>,
+    events: Sender<NetworkEvent>,
+    session: SharedSession,
+    dropped: Sender<String>,
+) {
+    loop {
+        let event = match server_reader.read_frame() {
+            Ok(message) => {
+                // remember the match so we can get back into it if the connection drops,
+                // spectators have no slot to get back
+                if let ServerMessage::Lobby(LobbyResponse::Joined(welcome)) = &message {
+                    if welcome.role == Role::Player {
+                        *session.lock().unwrap() = Some(Session::new(welcome));
+                    }
+                }
+                NetworkEvent::Message(message)
+            }
+            Err(e) => {
+                let reason = if e.is_timeout() {
+                    String::from("The server stopped responding")
+                } else {
+                    format!("Lost connection to the server: {}", e)
+                };
+                let _ = dropped.send(reason);
+                break;
+            }
+        };
+
+        // the game has gone away
+        if events.send(event).is_err() {
+            break;
+        }
+    }
+}
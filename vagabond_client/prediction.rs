@@ -0,0 +1,294 @@
+use std::collections::VecDeque;
+
+use vagabond_protocol::constants::Timing;
+use vagabond_protocol::input::{is_acknowledged, InputFrame};
+use vagabond_protocol::server_data::ServerEntity;
+
+use crate::constants::{SCALE, TILE_SIZE};
+use crate::geometry::Point2;
+
+// how many inputs we remember while waiting for the server to simulate them (about two seconds)
+pub const INPUT_BUFFER_SIZE: usize = 128;
+
+// fraction of the remaining correction that is blended away every frame
+const CORRECTION_RATE: f32 = 0.2;
+
+// corrections bigger than this are not worth hiding so the entity jumps straight there
+const SNAP_DISTANCE: f32 = TILE_SIZE * SCALE;
+
+// anything smaller than this is not visible anymore
+const MIN_CORRECTION: f32 = 0.01;
+
+// runs the local player's inputs through the same simulation as the server so the player
+// does not have to wait a round trip to see themselves move
+pub struct Predictor {
+    entity: ServerEntity,          // where we think the server will end up
+    pending: VecDeque<InputFrame>, // inputs the server has not simulated yet, oldest first
+    correction: Point2,            // how far the drawn entity is from the prediction
+    timing: Timing,                // the server's tick rate, so we step the way it does
+}
+
+impl Predictor {
+    pub fn new(entity: ServerEntity, tick_rate: u32) -> Predictor {
+        Predictor {
+            entity,
+            pending: VecDeque::with_capacity(INPUT_BUFFER_SIZE),
+            correction: Point2::new(0.0, 0.0),
+            timing: Timing::new(tick_rate),
+        }
+    }
+
+    // simulate an input right away instead of waiting for the server
+    pub fn predict(&mut self, input: &InputFrame) {
+        // the server is too far behind to ever acknowledge the oldest input
+        if self.pending.len() == INPUT_BUFFER_SIZE {
+            self.pending.pop_front();
+        }
+        self.pending.push_back(*input);
+
+        simulate(&mut self.entity, input, &self.timing);
+    }
+
+    // rewind to what the server says happened and replay every input it has not seen yet
+    pub fn reconcile(&mut self, ack: u32, server_entity: &ServerEntity) {
+        while let Some(input) = self.pending.front() {
+            if !is_acknowledged(input.tick, ack) {
+                break;
+            }
+            self.pending.pop_front();
+        }
+
+        let drawn_pos = self.drawn_pos();
+        let reset = self.entity.get_resets() != server_entity.get_resets();
+
+        self.entity = server_entity.clone();
+        for input in &self.pending {
+            simulate(&mut self.entity, input, &self.timing);
+        }
+
+        // keep drawing the entity where it was and blend towards the new prediction
+        // instead of jumping there in a single frame
+        self.correction = Point2::new(
+            drawn_pos.x - self.entity.pos.x,
+            drawn_pos.y - self.entity.pos.y,
+        );
+        // and a new round puts the entity straight back at its spawn
+        if reset || self.correction.x.hypot(self.correction.y) > SNAP_DISTANCE {
+            self.correction = Point2::new(0.0, 0.0);
+        }
+    }
+
+    // called once every frame to shrink the correction
+    pub fn smooth(&mut self) {
+        self.correction.x *= 1.0 - CORRECTION_RATE;
+        self.correction.y *= 1.0 - CORRECTION_RATE;
+
+        if self.correction.x.hypot(self.correction.y) < MIN_CORRECTION {
+            self.correction = Point2::new(0.0, 0.0);
+        }
+    }
+
+    // the predicted entity moved to where it should be drawn this frame
+    pub fn drawn_entity(&self) -> ServerEntity {
+        let mut entity = self.entity.clone();
+        entity.pos = self.drawn_pos();
+        entity.bound.translate(&self.correction);
+        entity.attack_bound.translate(&self.correction);
+
+        entity
+    }
+
+    fn drawn_pos(&self) -> Point2 {
+        Point2::new(
+            self.entity.pos.x + self.correction.x,
+            self.entity.pos.y + self.correction.y,
+        )
+    }
+}
+
+// accessors, only the tests look inside
+#[cfg(test)]
+impl Predictor {
+    pub fn get_entity(&self) -> &ServerEntity {
+        &self.entity
+    }
+
+    pub fn get_pending_len(&self) -> usize {
+        self.pending.len()
+    }
+}
+
+// one tick of the simulation for a single entity, the same steps the server takes
+fn simulate(entity: &mut ServerEntity, input: &InputFrame, timing: &Timing) {
+    entity.apply_input(input);
+    entity.step(timing);
+
+    // only the server decides whether an attack hit
+    entity.entity_actions.damage_check = false;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use vagabond_protocol::constants::{MOVE_SPEED, TICK_RATE};
+    use vagabond_protocol::server_data::ServerGameMatch;
+
+    fn input(tick: u32, right: bool) -> InputFrame {
+        let mut input = InputFrame::new(tick);
+        input.right = right;
+        input
+    }
+
+    // what the entity looks like after running every input through the simulation
+    fn simulated(id: usize, inputs: &[InputFrame]) -> ServerEntity {
+        let mut entity = ServerEntity::new(id);
+        for input in inputs {
+            simulate(&mut entity, input, &Timing::default());
+        }
+        entity
+    }
+
+    #[test]
+    fn input_is_predicted_immediately() {
+        let start = ServerEntity::new(0).pos;
+        let mut predictor = Predictor::new(ServerEntity::new(0), TICK_RATE);
+
+        predictor.predict(&input(1, true));
+
+        assert_eq!(predictor.get_entity().pos.x, start.x + MOVE_SPEED);
+        assert_eq!(predictor.drawn_entity().pos, predictor.get_entity().pos);
+        assert_eq!(predictor.get_pending_len(), 1);
+    }
+
+    #[test]
+    fn reconcile_replays_unacknowledged_inputs() {
+        let inputs: Vec<InputFrame> = (1..=10).map(|tick| input(tick, tick % 3 != 0)).collect();
+
+        let mut predictor = Predictor::new(ServerEntity::new(0), TICK_RATE);
+        for input in &inputs {
+            predictor.predict(input);
+        }
+
+        // the server has only seen the first six inputs
+        let mut server = ServerGameMatch::new();
+        for input in &inputs[..6] {
+            server.update_entity(0, input);
+            server.step();
+        }
+        assert_eq!(server.get_ack(0), 6);
+
+        predictor.reconcile(server.get_ack(0), &server.server_entities[0]);
+
+        assert_eq!(predictor.get_pending_len(), 4);
+        assert_eq!(predictor.get_entity().pos, simulated(0, &inputs).pos);
+        // the prediction was right so nothing needs to be corrected
+        assert_eq!(predictor.drawn_entity().pos, predictor.get_entity().pos);
+    }
+
+    #[test]
+    fn attacks_are_replayed() {
+        let mut attack = InputFrame::new(1);
+        attack.attack_pressed = true;
+        let inputs = vec![attack, input(2, false), input(3, false)];
+
+        let mut predictor = Predictor::new(ServerEntity::new(0), TICK_RATE);
+        for input in &inputs {
+            predictor.predict(input);
+        }
+
+        // nothing has been acknowledged so everything is replayed from the start
+        predictor.reconcile(0, &ServerEntity::new(0));
+
+        let expected = simulated(0, &inputs);
+        assert!(predictor.get_entity().entity_actions.attacking);
+        assert_eq!(
+            predictor.get_entity().attack_animator.current_frame(),
+            expected.attack_animator.current_frame()
+        );
+        assert_eq!(predictor.get_pending_len(), 3);
+    }
+
+    #[test]
+    fn misprediction_is_smoothed_out() {
+        let mut predictor = Predictor::new(ServerEntity::new(0), TICK_RATE);
+        for tick in 1..=4 {
+            predictor.predict(&input(tick, true));
+        }
+        let drawn_before = predictor.drawn_entity().pos;
+
+        // the server was stopped short of where we thought it would be
+        let mut server_entity = simulated(0, &[input(1, true), input(2, true)]);
+        let push = Point2::new(-10.0, 0.0);
+        server_entity.pos.x += push.x;
+        server_entity.bound.translate(&push);
+        predictor.reconcile(2, &server_entity);
+
+        // the prediction jumps back but the drawn entity does not
+        let corrected = predictor.get_entity().pos;
+        assert_eq!(corrected.x, drawn_before.x - 10.0);
+        assert_eq!(predictor.drawn_entity().pos, drawn_before);
+
+        // and every frame it gets closer without overshooting
+        let mut last_error = 10.0;
+        for _ in 0..100 {
+            predictor.smooth();
+            let error = predictor.drawn_entity().pos.x - corrected.x;
+            assert!(error >= 0.0 && error <= last_error);
+            last_error = error;
+        }
+        assert_eq!(predictor.drawn_entity().pos, corrected);
+    }
+
+    #[test]
+    fn large_errors_snap() {
+        let mut predictor = Predictor::new(ServerEntity::new(0), TICK_RATE);
+        predictor.predict(&input(1, true));
+
+        // the other side of the screen, like after a round restart
+        predictor.reconcile(1, &ServerEntity::new(1));
+
+        assert_eq!(predictor.drawn_entity().pos, ServerEntity::new(1).pos);
+        assert_eq!(predictor.get_pending_len(), 0);
+    }
+
+    #[test]
+    fn a_round_restart_snaps_even_when_close() {
+        let mut predictor = Predictor::new(ServerEntity::new(0), TICK_RATE);
+        for tick in 1..=10 {
+            predictor.predict(&input(tick, true));
+        }
+
+        // back at the spawn a short walk away, which would normally be blended
+        let mut spawn = ServerEntity::new(0);
+        spawn.resets = 1;
+        predictor.reconcile(10, &spawn);
+
+        assert_eq!(predictor.drawn_entity().pos, spawn.pos);
+    }
+
+    #[test]
+    fn pending_inputs_are_bounded() {
+        let mut predictor = Predictor::new(ServerEntity::new(0), TICK_RATE);
+        for tick in 1..=(INPUT_BUFFER_SIZE as u32 + 10) {
+            predictor.predict(&input(tick, false));
+        }
+
+        assert_eq!(predictor.get_pending_len(), INPUT_BUFFER_SIZE);
+    }
+
+    #[test]
+    fn acknowledgements_survive_wrapping() {
+        let mut predictor = Predictor::new(ServerEntity::new(0), TICK_RATE);
+        let ticks = [u32::MAX - 1, u32::MAX, 0, 1];
+        for tick in ticks.iter() {
+            predictor.predict(&input(*tick, true));
+        }
+
+        predictor.reconcile(u32::MAX, &simulated(0, &[input(0, true), input(0, true)]));
+        assert_eq!(predictor.get_pending_len(), 2);
+
+        predictor.reconcile(1, &simulated(0, &[input(0, true); 4]));
+        assert_eq!(predictor.get_pending_len(), 0);
+    }
+}
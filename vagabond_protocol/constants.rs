@@ -0,0 +1,85 @@
+// sizes and speeds the client and server both need so the simulation comes out the same on each
+pub const SCALE: f32 = 5.5;
+pub const TILE_SIZE: f32 = 32.0;
+pub const SCREEN_WIDTH: f32 = 800.0;
+pub const SCREEN_HEIGHT: f32 = 600.0;
+pub const MAX_HP: i8 = 5;
+pub const DAMAGE: i8 = 1; // health a hit takes off
+pub const ROUND_TIME: u16 = 60; // in seconds
+pub const ROUNDS: u8 = 3; // a match is best of this many rounds
+
+// the server simulates the match this many times per second unless it is set up to run at
+// another rate. every speed and length of time counted in ticks below is tuned for this rate,
+// Timing works them out for the rate the match actually runs at
+pub const TICK_RATE: u32 = 60;
+
+// how long the result of a round stays up before the next one starts, 2 seconds at 60 ticks
+// per second
+pub const ROUND_BREAK_TICKS: u32 = 120;
+
+// distance an entity walks each tick, so 165 pixels a second
+pub const MOVE_SPEED: f32 = SCALE / 2.0;
+
+// an attack raises the sword over ATTACK_FRAMES frames and lowers it over the same frames again
+pub const ATTACK_FRAMES: usize = 3;
+pub const ATTACK_REPEATS: i8 = 1;
+pub const ATTACK_FRAME_TICKS: u32 = 6; // 100ms at 60 ticks per second
+
+// blocked hits wear the guard down, it only comes back while the player is not blocking. an
+// empty guard leaves the player stunned and unable to do anything for a moment
+pub const MAX_GUARD: u8 = 100;
+pub const GUARD_DAMAGE: u8 = 35; // the third blocked hit in a row breaks the guard
+pub const GUARD_REGEN: u8 = 1; // per tick, so a little under 2 seconds from empty to full
+pub const GUARD_BREAK_TICKS: u16 = 90; // 1.5 seconds at 60 ticks per second
+
+// a block started no more than PARRY_WINDOW_TICKS before a hit lands parries it, the hit is
+// reflected and the attacker is left stunned and open. counted in server ticks so it is the
+// same for everyone no matter their ping. block has to have been let go for PARRY_COOLDOWN_TICKS
+// before it went up, tapping it over and over only ever blocks
+pub const PARRY_WINDOW_TICKS: u16 = 6; // 100ms at 60 ticks per second
+pub const PARRY_COOLDOWN_TICKS: u16 = 30;
+pub const PARRY_STUN_TICKS: u16 = 45;
+pub const PARRY_FLASH_TICKS: u16 = 12; // how long the parry is shown for
+
+// the constants above turned into ticks at the rate a match runs at, so a second of play lasts
+// a second and an entity walks just as far in it no matter how often the server steps
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Timing {
+    pub tick_rate: u32,
+    pub move_speed: f32,
+    pub attack_frame_ticks: u32,
+    pub round_break_ticks: u32,
+    pub guard_break_ticks: u16,
+    pub parry_window_ticks: u16,
+    pub parry_cooldown_ticks: u16,
+    pub parry_stun_ticks: u16,
+    pub parry_flash_ticks: u16,
+}
+
+impl Timing {
+    pub fn new(tick_rate: u32) -> Timing {
+        Timing {
+            tick_rate,
+            move_speed: MOVE_SPEED * TICK_RATE as f32 / tick_rate as f32,
+            attack_frame_ticks: scale_ticks(ATTACK_FRAME_TICKS, tick_rate),
+            round_break_ticks: scale_ticks(ROUND_BREAK_TICKS, tick_rate),
+            guard_break_ticks: scale_ticks(GUARD_BREAK_TICKS as u32, tick_rate) as u16,
+            parry_window_ticks: scale_ticks(PARRY_WINDOW_TICKS as u32, tick_rate) as u16,
+            parry_cooldown_ticks: scale_ticks(PARRY_COOLDOWN_TICKS as u32, tick_rate) as u16,
+            parry_stun_ticks: scale_ticks(PARRY_STUN_TICKS as u32, tick_rate) as u16,
+            parry_flash_ticks: scale_ticks(PARRY_FLASH_TICKS as u32, tick_rate) as u16,
+        }
+    }
+}
+
+impl Default for Timing {
+    fn default() -> Timing {
+        Timing::new(TICK_RATE)
+    }
+}
+
+// the same length of time at another tick rate, rounded to the nearest tick but never less
+// than one so nothing is over before it started
+fn scale_ticks(ticks: u32, tick_rate: u32) -> u32 {
+    ((ticks * tick_rate + TICK_RATE / 2) / TICK_RATE).max(1)
+}
@@ -0,0 +1,303 @@
+use std::error::Error;
+use std::fmt;
+use std::io::{self, ErrorKind, Read, Write};
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+// every message sent over the wire is wrapped in a frame that looks like this:
+// [ version: u8 ][ payload length: u32 big endian ][ payload: bincode bytes ]
+pub const FRAME_VERSION: u8 = 1;
+pub const HEADER_SIZE: usize = 5;
+
+// largest payload we will accept, anything bigger is treated as a corrupt stream
+pub const MAX_FRAME_SIZE: usize = 64 * 1024;
+
+#[derive(Debug)]
+pub enum FrameError {
+    Io(io::Error),
+    Closed,                 // the other end hung up
+    VersionMismatch(u8),    // version byte we received
+    Oversized(usize),       // payload length we were told to expect
+    Serialize(bincode::Error),
+}
+
+impl fmt::Display for FrameError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FrameError::Io(e) => write!(f, "io error: {}", e),
+            FrameError::Closed => write!(f, "connection closed"),
+            FrameError::VersionMismatch(version) => write!(
+                f,
+                "frame version {} does not match expected version {}",
+                version, FRAME_VERSION
+            ),
+            FrameError::Oversized(size) => write!(
+                f,
+                "frame of {} bytes exceeds the limit of {} bytes",
+                size, MAX_FRAME_SIZE
+            ),
+            FrameError::Serialize(e) => write!(f, "serialization error: {}", e),
+        }
+    }
+}
+
+impl Error for FrameError {}
+
+impl From<io::Error> for FrameError {
+    fn from(e: io::Error) -> FrameError {
+        FrameError::Io(e)
+    }
+}
+
+impl From<bincode::Error> for FrameError {
+    fn from(e: bincode::Error) -> FrameError {
+        FrameError::Serialize(e)
+    }
+}
+
+impl FrameError {
+    // read timeouts and non-blocking sockets report this, the stream is still usable afterwards
+    pub fn is_timeout(&self) -> bool {
+        match self {
+            FrameError::Io(e) => {
+                e.kind() == ErrorKind::WouldBlock || e.kind() == ErrorKind::TimedOut
+            }
+            _ => false,
+        }
+    }
+}
+
+// serialize a message and put the frame header in front of it
+pub fn encode<T: Serialize>(message: &T) -> Result<Vec<u8>, FrameError> {
+    let payload = bincode::serialize(message)?;
+    if payload.len() > MAX_FRAME_SIZE {
+        return Err(FrameError::Oversized(payload.len()));
+    }
+
+    let mut frame = Vec::with_capacity(HEADER_SIZE + payload.len());
+    frame.push(FRAME_VERSION);
+    frame.extend_from_slice(&(payload.len() as u32).to_be_bytes());
+    frame.extend_from_slice(&payload);
+
+    Ok(frame)
+}
+
+// checks the header at the start of the buffer and returns the length of the whole frame
+// once every byte of it has arrived, transports that carry frames one per message use this
+// to find where a frame ends
+pub fn complete_frame_len(buffer: &[u8]) -> Result<Option<usize>, FrameError> {
+    if buffer.is_empty() {
+        return Ok(None);
+    }
+
+    if buffer[0] != FRAME_VERSION {
+        return Err(FrameError::VersionMismatch(buffer[0]));
+    }
+
+    if buffer.len() < HEADER_SIZE {
+        return Ok(None);
+    }
+
+    let mut length = [0u8; 4];
+    length.copy_from_slice(&buffer[1..HEADER_SIZE]);
+    let length = u32::from_be_bytes(length) as usize;
+
+    // reject the frame before we ever try to buffer it
+    if length > MAX_FRAME_SIZE {
+        return Err(FrameError::Oversized(length));
+    }
+
+    if buffer.len() < HEADER_SIZE + length {
+        return Ok(None);
+    }
+
+    Ok(Some(HEADER_SIZE + length))
+}
+
+// decode the frame at the start of the buffer
+// returns None if the frame is not complete yet, otherwise the message and how many bytes it used
+pub fn decode<T: DeserializeOwned>(buffer: &[u8]) -> Result<Option<(T, usize)>, FrameError> {
+    match complete_frame_len(buffer)? {
+        Some(frame_len) => {
+            let message = bincode::deserialize(&buffer[HEADER_SIZE..frame_len])?;
+            Ok(Some((message, frame_len)))
+        }
+        None => Ok(None),
+    }
+}
+
+pub fn write_frame<W: Write, T: Serialize>(writer: &mut W, message: &T) -> Result<(), FrameError> {
+    let frame = encode(message)?;
+    writer.write_all(&frame)?;
+    writer.flush()?;
+
+    Ok(())
+}
+
+// reads whole frames out of a stream no matter how the bytes were split up or glued together.
+// bytes of a partially received frame are kept between calls, so a read timeout does not
+// throw away any data
+pub struct FrameReader<R> {
+    inner: R,
+    buffer: Vec<u8>,
+}
+
+impl<R: Read> FrameReader<R> {
+    pub fn new(inner: R) -> FrameReader<R> {
+        FrameReader {
+            inner,
+            buffer: Vec::new(),
+        }
+    }
+
+    pub fn read_frame<T: DeserializeOwned>(&mut self) -> Result<T, FrameError> {
+        let mut chunk = [0u8; 1024];
+
+        loop {
+            if let Some(frame_len) = complete_frame_len(&self.buffer)? {
+                // drain the frame first so a payload that fails to deserialize does not
+                // leave the stream out of sync
+                let frame: Vec<u8> = self.buffer.drain(..frame_len).collect();
+                return Ok(bincode::deserialize(&frame[HEADER_SIZE..])?);
+            }
+
+            match self.inner.read(&mut chunk) {
+                Ok(0) => return Err(FrameError::Closed),
+                Ok(size) => self.buffer.extend_from_slice(&chunk[..size]),
+                Err(ref e) if e.kind() == ErrorKind::Interrupted => (),
+                Err(e) => return Err(FrameError::Io(e)),
+            }
+        }
+    }
+
+    pub fn get_ref(&self) -> &R {
+        &self.inner
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use serde::{Deserialize, Serialize};
+
+    use std::net::{TcpListener, TcpStream};
+    use std::thread;
+
+    #[derive(Serialize, Deserialize, PartialEq, Debug)]
+    struct TestMessage {
+        id: usize,
+        name: String,
+        data: Vec<u8>,
+    }
+
+    fn message(id: usize, size: usize) -> TestMessage {
+        TestMessage {
+            id,
+            name: format!("message {}", id),
+            data: vec![id as u8; size],
+        }
+    }
+
+    // spawns a thread that writes the given bytes using the given chunk size and
+    // returns the receiving end of the connection
+    fn loopback(bytes: Vec<u8>, chunk_size: usize) -> (TcpStream, thread::JoinHandle<()>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let writer = thread::spawn(move || {
+            let mut stream = TcpStream::connect(addr).unwrap();
+            stream.set_nodelay(true).unwrap();
+            for chunk in bytes.chunks(chunk_size) {
+                stream.write_all(chunk).unwrap();
+                stream.flush().unwrap();
+            }
+        });
+
+        let (stream, _) = listener.accept().unwrap();
+        (stream, writer)
+    }
+
+    #[test]
+    fn round_trip_over_loopback() {
+        let mut bytes = Vec::new();
+        for id in 0..3 {
+            bytes.extend(encode(&message(id, 4000)).unwrap());
+        }
+
+        // every frame is coalesced into one write
+        let (stream, writer) = loopback(bytes, usize::MAX);
+        let mut reader = FrameReader::new(stream);
+
+        for id in 0..3 {
+            let received: TestMessage = reader.read_frame().unwrap();
+            assert_eq!(received, message(id, 4000));
+        }
+        writer.join().unwrap();
+
+        match reader.read_frame::<TestMessage>() {
+            Err(FrameError::Closed) => (),
+            other => panic!("expected a closed connection, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn partial_reads_are_reassembled() {
+        let mut bytes = encode(&message(7, 100)).unwrap();
+        bytes.extend(encode(&message(8, 3)).unwrap());
+
+        // one byte at a time
+        let (stream, writer) = loopback(bytes, 1);
+        let mut reader = FrameReader::new(stream);
+
+        assert_eq!(reader.read_frame::<TestMessage>().unwrap(), message(7, 100));
+        assert_eq!(reader.read_frame::<TestMessage>().unwrap(), message(8, 3));
+        writer.join().unwrap();
+    }
+
+    #[test]
+    fn oversized_frames_are_rejected() {
+        assert!(matches!(
+            encode(&message(0, MAX_FRAME_SIZE + 1)),
+            Err(FrameError::Oversized(_))
+        ));
+
+        // hand craft a header that claims a huge payload
+        let mut bytes = vec![FRAME_VERSION];
+        bytes.extend_from_slice(&(MAX_FRAME_SIZE as u32 + 1).to_be_bytes());
+
+        let (stream, writer) = loopback(bytes, usize::MAX);
+        let mut reader = FrameReader::new(stream);
+
+        match reader.read_frame::<TestMessage>() {
+            Err(FrameError::Oversized(size)) => assert_eq!(size, MAX_FRAME_SIZE + 1),
+            other => panic!("expected an oversized frame, got {:?}", other),
+        }
+        writer.join().unwrap();
+    }
+
+    #[test]
+    fn wrong_version_is_rejected() {
+        let mut bytes = encode(&message(1, 1)).unwrap();
+        bytes[0] = FRAME_VERSION + 1;
+
+        match decode::<TestMessage>(&bytes) {
+            Err(FrameError::VersionMismatch(version)) => assert_eq!(version, FRAME_VERSION + 1),
+            other => panic!("expected a version mismatch, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn incomplete_frames_decode_to_none() {
+        let bytes = encode(&message(2, 10)).unwrap();
+
+        for end in 0..bytes.len() {
+            assert!(decode::<TestMessage>(&bytes[..end]).unwrap().is_none());
+        }
+
+        let (received, used) = decode::<TestMessage>(&bytes).unwrap().unwrap();
+        assert_eq!(received, message(2, 10));
+        assert_eq!(used, bytes.len());
+    }
+}
@@ -1,17 +1,17 @@
 use serde::{Deserialize, Serialize};
 
-#[derive(Serialize, Deserialize, Clone, Debug)]
+#[derive(Serialize, Deserialize, PartialEq, Copy, Clone, Debug)]
 pub struct Point2 {
     pub x: f32,
     pub y: f32,
 }
 impl Point2 {
     pub fn new(x: f32, y: f32) -> Point2 {
-        Point2 { x: x, y: y }
+        Point2 { x, y }
     }
 }
 
-#[derive(Serialize, Deserialize, Clone, Debug)]
+#[derive(Serialize, Deserialize, PartialEq, Copy, Clone, Debug)]
 pub struct Rect {
     pub top_left: Point2,
     pub bottom_right: Point2,
@@ -20,11 +20,18 @@ pub struct Rect {
 impl Rect {
     pub fn new(top_left: Point2, bottom_right: Point2) -> Rect {
         Rect {
-            top_left: top_left,
-            bottom_right: bottom_right,
+            top_left,
+            bottom_right,
         }
     }
 
+    pub fn translate(&mut self, vel: &Point2) {
+        self.top_left.x += vel.x;
+        self.top_left.y += vel.y;
+        self.bottom_right.x += vel.x;
+        self.bottom_right.y += vel.y;
+    }
+
     // checks to see if two Rects are intersecting
     // using solution found here: https://www.geeksforgeeks.org/find-two-rectangles-overlap/
     // however I had to make an adjustment because their bound check for the Y-axis was wrong
@@ -41,4 +48,18 @@ impl Rect {
 
         true
     }
+
+    pub fn check_bounds_point(self, point: &Point2) -> bool {
+        // if point is on left side of other
+        if self.top_left.x >= point.x || point.x >= self.bottom_right.x {
+            return false;
+        }
+
+        // if point is above other
+        if self.top_left.y >= point.y || point.y >= self.bottom_right.y {
+            return false;
+        }
+
+        true
+    }
 }
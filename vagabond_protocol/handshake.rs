@@ -0,0 +1,175 @@
+use serde::{Deserialize, Serialize};
+
+use crate::constants::{DAMAGE, MAX_HP, ROUNDS, ROUND_TIME};
+
+// bump this whenever a message changes shape so old clients get told to update
+// instead of misreading the data
+pub const PROTOCOL_VERSION: u16 = 21;
+
+// limits on the rules a player can pick for their own room
+pub const ROUND_TIME_LIMITS: (u16, u16) = (10, 300);
+pub const MAX_HP_LIMITS: (i8, i8) = (1, 20);
+pub const DAMAGE_LIMITS: (i8, i8) = (1, 20);
+pub const ROUNDS_LIMITS: (u8, u8) = (1, 9);
+
+#[derive(Serialize, Deserialize, PartialEq, Clone, Debug)]
+pub struct MatchRules {
+    pub round_time: u16, // in seconds
+    pub max_hp: i8,
+    pub damage: i8, // health a hit takes off
+    pub rounds: u8, // best of this many, always odd so somebody wins
+}
+
+impl Default for MatchRules {
+    fn default() -> MatchRules {
+        MatchRules::new()
+    }
+}
+
+impl MatchRules {
+    pub fn new() -> MatchRules {
+        MatchRules {
+            round_time: ROUND_TIME,
+            max_hp: MAX_HP,
+            damage: DAMAGE,
+            rounds: ROUNDS,
+        }
+    }
+
+    // more than half the rounds
+    pub fn rounds_to_win(&self) -> u8 {
+        self.rounds / 2 + 1
+    }
+
+    // the server refuses to open a room with rules outside the limits
+    pub fn check(&self) -> Result<(), String> {
+        let (min_time, max_time) = ROUND_TIME_LIMITS;
+        if self.round_time < min_time || self.round_time > max_time {
+            return Err(format!(
+                "A round has to last between {} and {} seconds.",
+                min_time, max_time
+            ));
+        }
+
+        let (min_hp, max_hp) = MAX_HP_LIMITS;
+        if self.max_hp < min_hp || self.max_hp > max_hp {
+            return Err(format!(
+                "Players have to start with between {} and {} health.",
+                min_hp, max_hp
+            ));
+        }
+
+        let (min_damage, max_damage) = DAMAGE_LIMITS;
+        if self.damage < min_damage || self.damage > max_damage {
+            return Err(format!(
+                "A hit has to do between {} and {} damage.",
+                min_damage, max_damage
+            ));
+        }
+
+        let (min_rounds, max_rounds) = ROUNDS_LIMITS;
+        if self.rounds < min_rounds || self.rounds > max_rounds || self.rounds % 2 == 0 {
+            return Err(format!(
+                "A match has to be best of an odd number of rounds from {} to {}.",
+                min_rounds, max_rounds
+            ));
+        }
+
+        Ok(())
+    }
+}
+
+// first message a client sends once it is connected
+// protocol_version has to stay the first field so every version of the server can read it
+#[derive(Serialize, Deserialize, PartialEq, Clone, Debug)]
+pub struct Hello {
+    pub protocol_version: u16,
+    pub resume: Option<Resume>, // set when reconnecting to a match after the connection dropped
+}
+
+impl Default for Hello {
+    fn default() -> Hello {
+        Hello::new()
+    }
+}
+
+impl Hello {
+    // start out in the lobby
+    pub fn new() -> Hello {
+        Hello {
+            protocol_version: PROTOCOL_VERSION,
+            resume: None,
+        }
+    }
+
+    // ask for the slot we had before the connection dropped
+    pub fn resume(room_id: u32, session_token: u64) -> Hello {
+        Hello {
+            protocol_version: PROTOCOL_VERSION,
+            resume: Some(Resume {
+                room_id,
+                session_token,
+            }),
+        }
+    }
+}
+
+// where the player was and the token from their welcome to prove it
+#[derive(Serialize, Deserialize, PartialEq, Clone, Debug)]
+pub struct Resume {
+    pub room_id: u32,
+    pub session_token: u64,
+}
+
+// what a connection is allowed to do in a room
+#[derive(Serialize, Deserialize, PartialEq, Clone, Copy, Debug)]
+pub enum Role {
+    Player,
+    Spectator, // gets the match every tick but can not send inputs
+}
+
+// everything the client needs to know before the match starts
+#[derive(Serialize, Deserialize, PartialEq, Clone, Debug)]
+pub struct Welcome {
+    pub protocol_version: u16,
+    pub room_id: u32,
+    pub role: Role,
+    pub player_id: usize,   // only means something for players
+    pub session_token: u64, // hand this back in a hello to reconnect as the same player
+    pub rules: MatchRules,
+    pub tick_rate: u32, // the server steps the match this often, inputs have to be sent as often
+}
+
+// the server answers a Hello with exactly one of these
+#[derive(Serialize, Deserialize, PartialEq, Clone, Debug)]
+pub enum HelloResponse {
+    Welcome(Welcome),  // back in the match we were resuming
+    Rejected(String),  // reason the client was turned away
+    Lobby(ServerInfo), // in the lobby of this server
+}
+
+// what the lobby shows about the server it belongs to
+#[derive(Serialize, Deserialize, PartialEq, Clone, Debug)]
+pub struct ServerInfo {
+    pub name: String,
+    pub motd: Option<String>, // message of the day, set by whoever runs the server
+}
+
+// used by the server to decide whether it can talk to the client at all
+pub fn check_version(hello: &Hello) -> Result<(), String> {
+    if hello.protocol_version == PROTOCOL_VERSION {
+        return Ok(());
+    }
+
+    // whichever side has the lower version is the one that is out of date
+    let outdated = if hello.protocol_version < PROTOCOL_VERSION {
+        "client"
+    } else {
+        "server"
+    };
+
+    Err(format!(
+        "Protocol version mismatch: client uses version {} but the server uses version {}. Please update the {}.",
+        hello.protocol_version, PROTOCOL_VERSION, outdated
+    ))
+}
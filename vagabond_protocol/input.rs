@@ -0,0 +1,29 @@
+use serde::{Deserialize, Serialize};
+
+// the buttons a player is pressing during one tick, this is all the server needs from a client
+#[derive(Serialize, Deserialize, PartialEq, Copy, Clone, Debug)]
+pub struct InputFrame {
+    pub tick: u32,
+    pub left: bool,
+    pub right: bool,
+    pub attack_pressed: bool, // only true on the tick the attack button went down
+    pub block: bool,
+}
+
+impl InputFrame {
+    pub fn new(tick: u32) -> InputFrame {
+        InputFrame {
+            tick,
+            left: false,
+            right: false,
+            attack_pressed: false,
+            block: false,
+        }
+    }
+}
+
+// input ticks count up forever and wrap around, so compare them by distance instead of size
+// returns true if the input with the given tick is at or before the acknowledged tick
+pub fn is_acknowledged(tick: u32, ack: u32) -> bool {
+    ack.wrapping_sub(tick) < u32::MAX / 2
+}
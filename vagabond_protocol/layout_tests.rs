@@ -0,0 +1,342 @@
+// If one of these tests fails the bytes on the wire have changed. Old clients and servers can
+// no longer understand the new layout, so bump PROTOCOL_VERSION and update the expected bytes.
+
+use serde::Serialize;
+
+use crate::geometry::{Point2, Rect};
+use crate::handshake::{Hello, HelloResponse, MatchRules, Role, ServerInfo, Welcome};
+use crate::input::InputFrame;
+use crate::lobby::{LobbyRequest, LobbyResponse, RoomSummary};
+use crate::message::{ClientMessage, ServerMessage, Snapshot};
+use crate::packet::{Packet, PacketBody, ReliableFrame};
+use crate::server_data::{
+    Action, Clock, EndReason, EntityActions, MatchResult, MatchStatus, RematchStatus,
+    ServerAnimator, ServerEntity, ServerGameMatch,
+};
+use std::time::Duration;
+
+fn to_hex<T: Serialize>(value: &T) -> String {
+    bincode::serialize(value)
+        .unwrap()
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+fn sample_entity() -> ServerEntity {
+    let mut entity = ServerEntity::new(1);
+    entity.hp = 3;
+    entity.entity_actions.moving_left = true;
+    entity.attack_animator = ServerAnimator::with_frame(2, 1);
+    entity.pos = Point2::new(1.0, 2.0);
+    entity.vel = Point2::new(-0.5, 0.0);
+    entity.bound = Rect::new(Point2::new(1.0, 2.0), Point2::new(3.0, 4.0));
+    entity.redo_status = MatchStatus::Rematch(RematchStatus::Yes);
+    entity
+}
+
+#[test]
+fn geometry_layout() {
+    assert_eq!(to_hex(&Point2::new(1.0, -2.0)), "0000803f000000c0");
+    assert_eq!(
+        to_hex(&Rect::new(Point2::new(0.0, 1.0), Point2::new(2.0, 3.0))),
+        "000000000000803f0000004000004040"
+    );
+}
+
+#[test]
+fn handshake_layout() {
+    assert_eq!(to_hex(&Hello::new()), "150000");
+    assert_eq!(
+        to_hex(&Hello::resume(3, 0x0102030405060708)),
+        "150001030000000807060504030201"
+    );
+    assert_eq!(to_hex(&MatchRules::new()), "3c00050103");
+    assert_eq!(to_hex(&Role::Spectator), "01000000");
+
+    let welcome = Welcome {
+        protocol_version: 1,
+        room_id: 3,
+        role: Role::Player,
+        player_id: 1,
+        session_token: 42,
+        rules: MatchRules::new(),
+        tick_rate: 60,
+    };
+    assert_eq!(
+        to_hex(&welcome),
+        "0100030000000000000001000000000000002a000000000000003c000501033c000000"
+    );
+    assert_eq!(
+        to_hex(&HelloResponse::Welcome(welcome)),
+        concat!(
+            "000000000100030000000000000001000000000000002a000000000000003c00",
+            "0501033c000000",
+        )
+    );
+    assert_eq!(
+        to_hex(&HelloResponse::Rejected(String::from("no"))),
+        "0100000002000000000000006e6f"
+    );
+    assert_eq!(
+        to_hex(&HelloResponse::Lobby(ServerInfo {
+            name: String::from("dojo"),
+            motd: Some(String::from("hi")),
+        })),
+        "020000000400000000000000646f6a6f0102000000000000006869"
+    );
+}
+
+#[test]
+fn match_status_layout() {
+    assert_eq!(to_hex(&RematchStatus::Yes), "00000000");
+    assert_eq!(to_hex(&RematchStatus::No), "01000000");
+    assert_eq!(to_hex(&RematchStatus::Maybe), "02000000");
+    assert_eq!(to_hex(&MatchStatus::InProgress), "00000000");
+    assert_eq!(to_hex(&EndReason::Knockout), "00000000");
+    assert_eq!(to_hex(&EndReason::TimeUp), "01000000");
+    assert_eq!(to_hex(&EndReason::Forfeit), "02000000");
+    assert_eq!(to_hex(&EndReason::Disconnect), "03000000");
+    assert_eq!(to_hex(&EndReason::DoubleKnockout), "04000000");
+    assert_eq!(to_hex(&EndReason::Rounds), "05000000");
+
+    let result = MatchResult {
+        winner: Some(1),
+        reason: EndReason::Forfeit,
+        final_hp: [5, 0],
+        duration: Duration::from_millis(42500),
+    };
+    assert_eq!(
+        to_hex(&result),
+        "0101000000000000000200000005002a000000000000000065cd1d"
+    );
+    assert_eq!(
+        to_hex(&MatchResult {
+            winner: None,
+            ..result
+        }),
+        "000200000005002a000000000000000065cd1d"
+    );
+    assert_eq!(
+        to_hex(&MatchStatus::Over(result)),
+        "010000000101000000000000000200000005002a000000000000000065cd1d"
+    );
+    assert_eq!(
+        to_hex(&MatchStatus::Rematch(RematchStatus::No)),
+        "0200000001000000"
+    );
+    assert_eq!(
+        to_hex(&MatchStatus::RoundOver(result)),
+        "030000000101000000000000000200000005002a000000000000000065cd1d"
+    );
+    assert_eq!(to_hex(&MatchStatus::SuddenDeath), "04000000");
+}
+
+#[test]
+fn entity_layout() {
+    assert_eq!(to_hex(&Action::Left), "00000000");
+    assert_eq!(to_hex(&Action::Right), "01000000");
+    assert_eq!(
+        to_hex(&EntityActions::new(Action::Left)),
+        "00000000000001000000"
+    );
+    assert_eq!(
+        to_hex(&ServerAnimator::with_frame(2, 1)),
+        "02000000000000000100000000"
+    );
+    assert_eq!(to_hex(&Clock::new()), "0000");
+    assert_eq!(
+        to_hex(&sample_entity()),
+        concat!(
+            "0100000000000000030000000001000100000002000000000000000100000000",
+            "0000803f00000040000000bf000000000000803f000000400000404000008040",
+            "008000440080e443000011440060014402000000000000000000000064000000",
+            "000000ffff0000",
+        )
+    );
+}
+
+#[test]
+fn game_match_layout() {
+    let mut game_match = ServerGameMatch::new();
+    game_match.server_entities[1] = sample_entity();
+    game_match.match_status = MatchStatus::Over(MatchResult {
+        winner: Some(0),
+        reason: EndReason::TimeUp,
+        final_hp: [5, 3],
+        duration: Duration::from_secs(60),
+    });
+
+    assert_eq!(
+        to_hex(&game_match),
+        concat!(
+            "3c00020000000000000000000000000000000501000000000001000000000000",
+            "000000000000000000000000dc420000d44300000000000000000000dc420000",
+            "d44300008f430000164400005c430080e44300008f4300600144000000000000",
+            "000064000000000000ffff000001000000000000000300000000010001000000",
+            "020000000000000001000000000000803f00000040000000bf00000000000080",
+            "3f000000400000404000008040008000440080e4430000114400600144020000",
+            "00000000000000000064000000000000ffff0000010000000100000000000000",
+            "000100000005033c000000000000000000000002000000000000000000",
+        )
+    );
+}
+
+#[test]
+fn lobby_layout() {
+    assert_eq!(to_hex(&LobbyRequest::ListRooms), "00000000");
+    assert_eq!(
+        to_hex(&LobbyRequest::CreateRoom {
+            name: String::from("dojo"),
+            password: Some(String::from("pw")),
+            rules: Some(MatchRules::new()),
+        }),
+        "010000000400000000000000646f6a6f0102000000000000007077013c00050103"
+    );
+    assert_eq!(
+        to_hex(&LobbyRequest::CreateRoom {
+            name: String::from("dojo"),
+            password: None,
+            rules: None,
+        }),
+        "010000000400000000000000646f6a6f0000"
+    );
+    assert_eq!(
+        to_hex(&LobbyRequest::JoinRoom {
+            room_id: 3,
+            password: None,
+        }),
+        "020000000300000000"
+    );
+    assert_eq!(
+        to_hex(&LobbyRequest::Spectate {
+            room_id: 3,
+            password: None,
+        }),
+        "030000000300000000"
+    );
+    assert_eq!(to_hex(&LobbyRequest::QuickMatch), "04000000");
+    assert_eq!(to_hex(&LobbyRequest::LeaveRoom), "05000000");
+
+    let summary = RoomSummary {
+        room_id: 3,
+        name: String::from("dojo"),
+        players: 1,
+        max_players: 2,
+        spectators: 4,
+        locked: true,
+        status: MatchStatus::InProgress,
+    };
+    assert_eq!(
+        to_hex(&LobbyResponse::Rooms(vec![summary])),
+        concat!(
+            "000000000100000000000000030000000400000000000000646f6a6f01000000",
+            "00000000020000000000000004000000000000000100000000",
+        )
+    );
+
+    let welcome = Welcome {
+        protocol_version: 1,
+        room_id: 3,
+        role: Role::Spectator,
+        player_id: 0,
+        session_token: 42,
+        rules: MatchRules::new(),
+        tick_rate: 60,
+    };
+    assert_eq!(
+        to_hex(&LobbyResponse::Joined(welcome)),
+        concat!(
+            "010000000100030000000100000000000000000000002a000000000000003c00",
+            "0501033c000000",
+        )
+    );
+    assert_eq!(to_hex(&LobbyResponse::Waiting(2)), "0200000002000000");
+    assert_eq!(
+        to_hex(&LobbyResponse::Rejected(String::from("no"))),
+        "0300000002000000000000006e6f"
+    );
+}
+
+#[test]
+fn client_message_layout() {
+    let mut input = InputFrame::new(7);
+    input.right = true;
+    input.attack_pressed = true;
+
+    assert_eq!(to_hex(&input), "0700000000010100");
+    assert_eq!(
+        to_hex(&ClientMessage::Input(input)),
+        "000000000700000000010100"
+    );
+    assert_eq!(
+        to_hex(&ClientMessage::Rematch(RematchStatus::Yes)),
+        "0100000000000000"
+    );
+    assert_eq!(
+        to_hex(&ClientMessage::Lobby(LobbyRequest::LeaveRoom)),
+        "0200000005000000"
+    );
+}
+
+#[test]
+fn server_message_layout() {
+    let snapshot = Snapshot {
+        tick: 9,
+        ack: 7,
+        game_match: ServerGameMatch::new(),
+    };
+
+    assert_eq!(
+        to_hex(&ServerMessage::Snapshot(snapshot)),
+        concat!(
+            "0000000009000000070000003c00020000000000000000000000000000000501",
+            "000000000001000000000000000000000000000000000000dc420000d4430000",
+            "0000000000000000dc420000d44300008f430000164400005c430080e4430000",
+            "8f4300600144000000000000000064000000000000ffff000001000000000000",
+            "0005000000000000010000000000000000000000000000000000802c440000d4",
+            "430000000000000000008000440000d44300802c4400001644008000440080e4",
+            "430000114400600144000000000000000064000000000000ffff000000000000",
+            "02000000000000000000",
+        )
+    );
+    assert_eq!(
+        to_hex(&ServerMessage::Lobby(LobbyResponse::Waiting(1))),
+        "010000000200000001000000"
+    );
+    assert_eq!(
+        to_hex(&ServerMessage::Notice(String::from("hi"))),
+        "0200000002000000000000006869"
+    );
+}
+
+#[test]
+fn packet_layout() {
+    let packet = Packet {
+        sequence: 2,
+        ack: 1,
+        ack_bits: 1,
+        body: PacketBody::Frames {
+            reliable: vec![ReliableFrame {
+                id: 0,
+                frame: vec![0xab],
+            }],
+            sequenced: Some(vec![0xcd]),
+        },
+    };
+
+    assert_eq!(
+        to_hex(&packet),
+        concat!(
+            "0200000001000000010000000000000001000000000000000000000001000000",
+            "00000000ab010100000000000000cd",
+        )
+    );
+    let close = Packet {
+        sequence: 3,
+        ack: 0,
+        ack_bits: 0,
+        body: PacketBody::Close,
+    };
+    assert_eq!(to_hex(&close), "03000000000000000000000001000000");
+}
@@ -0,0 +1,15 @@
+// everything that both the client and the server need to agree on lives in here
+pub mod constants;
+pub mod frame;
+pub mod geometry;
+pub mod handshake;
+pub mod input;
+pub mod lobby;
+pub mod message;
+pub mod packet;
+pub mod server_data;
+pub mod udp;
+
+// guards the bincode layout of every type that goes over the wire
+#[cfg(test)]
+mod layout_tests;
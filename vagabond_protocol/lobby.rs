@@ -0,0 +1,49 @@
+use serde::{Deserialize, Serialize};
+
+use crate::handshake::{MatchRules, Welcome};
+use crate::server_data::MatchStatus;
+
+// longest room name the server accepts, anything longer does not fit in the room list
+pub const MAX_ROOM_NAME_LENGTH: usize = 24;
+
+// what a client in the lobby can ask the server for
+#[derive(Serialize, Deserialize, PartialEq, Clone, Debug)]
+pub enum LobbyRequest {
+    ListRooms,
+    CreateRoom {
+        name: String,
+        password: Option<String>,  // only players that know it can join
+        rules: Option<MatchRules>, // None plays by the rules the server was started with
+    },
+    JoinRoom {
+        room_id: u32,
+        password: Option<String>,
+    },
+    Spectate {
+        room_id: u32,
+        password: Option<String>, // locked rooms can only be watched with the password
+    },
+    QuickMatch, // wait in the queue to be paired with whoever comes along
+    LeaveRoom,  // go back to the lobby from a room or the queue
+}
+
+// how the server answers a lobby request
+#[derive(Serialize, Deserialize, PartialEq, Clone, Debug)]
+pub enum LobbyResponse {
+    Rooms(Vec<RoomSummary>),
+    Joined(Welcome), // in a room as a player or spectator, the match snapshots follow
+    Waiting(u32),    // place in the queue for an opponent, 1 is next in line
+    Rejected(String), // reason the request failed, the player is still in the lobby
+}
+
+// what the lobby shows about a room
+#[derive(Serialize, Deserialize, PartialEq, Clone, Debug)]
+pub struct RoomSummary {
+    pub room_id: u32,
+    pub name: String,
+    pub players: usize,
+    pub max_players: usize,
+    pub spectators: usize,
+    pub locked: bool, // a password is needed to join
+    pub status: MatchStatus,
+}
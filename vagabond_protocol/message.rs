@@ -0,0 +1,29 @@
+use serde::{Deserialize, Serialize};
+
+use crate::input::InputFrame;
+use crate::lobby::{LobbyRequest, LobbyResponse};
+use crate::server_data::{RematchStatus, ServerGameMatch};
+
+// everything a client can send once the handshake is done
+#[derive(Serialize, Deserialize, PartialEq, Clone, Debug)]
+pub enum ClientMessage {
+    Input(InputFrame),
+    Rematch(RematchStatus), // vote cast with the buttons after a match is over
+    Lobby(LobbyRequest),
+}
+
+// everything the server can send once the handshake is done
+#[derive(Serialize, Deserialize, Debug)]
+pub enum ServerMessage {
+    Snapshot(Snapshot),
+    Lobby(LobbyResponse),
+    Notice(String), // from whoever runs the server, shown to the player for a while
+}
+
+// the state of the match after a tick of the simulation
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Snapshot {
+    pub tick: u32, // server tick the snapshot was taken on
+    pub ack: u32,  // tick of the last input from the receiving client that has been simulated
+    pub game_match: ServerGameMatch,
+}
@@ -0,0 +1,450 @@
+use std::collections::{BTreeMap, VecDeque};
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+
+// transports that can lose, duplicate or reorder packets (like UDP) send frames inside
+// packets that look like this. every packet says which of the other side's packets have
+// arrived, so frames that have to get there can be sent again until they are acknowledged.
+//
+// frames go one of two ways:
+// - reliable: resent until acknowledged and handed over in the order they were sent, for
+//   control messages like the handshake, lobby requests and rematch votes
+// - sequenced: sent once, and dropped if a newer one already arrived, for inputs and
+//   snapshots that are outdated by the next one anyway
+
+// a reliable frame that has not been acknowledged is sent again after this long
+pub const RESEND_TIME: Duration = Duration::from_millis(100);
+
+// packets that arrived are acknowledged at least this often, even with nothing else to send
+pub const ACK_TIME: Duration = Duration::from_millis(30);
+
+// a quiet connection still sends an empty packet this often so the other side knows it is there
+pub const KEEPALIVE_TIME: Duration = Duration::from_secs(1);
+
+// most bytes of reliable frames that go into one packet, anything past that waits for the next
+// one so packets stay small enough to not be split up along the way
+pub const MAX_RELIABLE_BYTES: usize = 1024;
+
+// how many packets before the newest one an ack can still tell the other side about
+const ACK_WINDOW: u32 = 32;
+
+// packets we keep track of that have not been acknowledged, the frames in the ones we forget
+// about are sent again once their time is up
+const MAX_IN_FLIGHT: usize = 256;
+
+#[derive(Serialize, Deserialize, PartialEq, Clone, Debug)]
+pub struct Packet {
+    pub sequence: u32, // counts up from 1 with every packet sent
+    pub ack: u32,      // newest packet received from the other side, 0 before any arrived
+    pub ack_bits: u32, // bit n is set if packet ack - 1 - n arrived as well
+    pub body: PacketBody,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Clone, Debug)]
+pub enum PacketBody {
+    Frames {
+        reliable: Vec<ReliableFrame>,
+        sequenced: Option<Vec<u8>>,
+    },
+    Close, // the other side is done with the connection
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Clone, Debug)]
+pub struct ReliableFrame {
+    pub id: u32, // counts up from 0, the receiver hands the frames over in this order
+    pub frame: Vec<u8>,
+}
+
+struct Unacked {
+    frame: Vec<u8>,
+    sent: Option<Instant>, // None until it has gone out for the first time
+}
+
+// one side of a connection, keeps track of what has been sent and received. it never touches
+// a socket itself and is told the time, the transport decides when packets go out
+pub struct PacketChannel {
+    next_sequence: u32,
+    in_flight: VecDeque<(u32, Vec<u32>)>, // packets sent and the reliable frames they carried
+    next_reliable_id: u32,
+    unacked: BTreeMap<u32, Unacked>,
+
+    newest_received: u32,  // 0 until the first packet arrives
+    received_bits: u32,    // which packets before the newest one arrived, like ack_bits
+    newest_sequenced: u32, // packet the last sequenced frame we handed over came in
+    next_delivery: u32,    // id of the next reliable frame to hand over
+    // reliable frames that arrived before the ones in front of them
+    early: BTreeMap<u32, Vec<u8>>,
+
+    last_sent: Option<Instant>,
+    ack_owed_since: Option<Instant>, // a packet arrived that we have not acknowledged yet
+}
+
+impl Default for PacketChannel {
+    fn default() -> PacketChannel {
+        PacketChannel::new()
+    }
+}
+
+impl PacketChannel {
+    pub fn new() -> PacketChannel {
+        PacketChannel {
+            next_sequence: 1,
+            in_flight: VecDeque::new(),
+            next_reliable_id: 0,
+            unacked: BTreeMap::new(),
+            newest_received: 0,
+            received_bits: 0,
+            newest_sequenced: 0,
+            next_delivery: 0,
+            early: BTreeMap::new(),
+            last_sent: None,
+            ack_owed_since: None,
+        }
+    }
+
+    // the frame goes out with the next packet and keeps going until it is acknowledged
+    pub fn queue_reliable(&mut self, frame: Vec<u8>) {
+        self.unacked
+            .insert(self.next_reliable_id, Unacked { frame, sent: None });
+        self.next_reliable_id += 1;
+    }
+
+    // whether a packet has to go out even though there is nothing new to send
+    pub fn needs_packet(&self, now: Instant) -> bool {
+        let resend_due = self.unacked.values().any(|unacked| match unacked.sent {
+            Some(sent) => now.duration_since(sent) >= RESEND_TIME,
+            None => true,
+        });
+        let ack_due = match self.ack_owed_since {
+            Some(since) => now.duration_since(since) >= ACK_TIME,
+            None => false,
+        };
+        let keepalive_due = match self.last_sent {
+            Some(sent) => now.duration_since(sent) >= KEEPALIVE_TIME,
+            None => true,
+        };
+
+        resend_due || ack_due || keepalive_due
+    }
+
+    // the next packet to send, with the sequenced frame if there is one and any reliable
+    // frames that are due
+    pub fn next_packet(&mut self, sequenced: Option<Vec<u8>>, now: Instant) -> Packet {
+        let mut reliable = Vec::new();
+        let mut reliable_bytes = 0;
+        for (id, unacked) in self.unacked.iter_mut() {
+            let due = match unacked.sent {
+                Some(sent) => now.duration_since(sent) >= RESEND_TIME,
+                None => true,
+            };
+            if !due {
+                continue;
+            }
+            // a frame bigger than the limit still has to go out on its own
+            if !reliable.is_empty() && reliable_bytes + unacked.frame.len() > MAX_RELIABLE_BYTES {
+                break;
+            }
+
+            reliable_bytes += unacked.frame.len();
+            unacked.sent = Some(now);
+            reliable.push(ReliableFrame {
+                id: *id,
+                frame: unacked.frame.clone(),
+            });
+        }
+
+        let sequence = self.next_sequence;
+        self.next_sequence += 1;
+
+        let ids = reliable.iter().map(|reliable| reliable.id).collect();
+        self.in_flight.push_back((sequence, ids));
+        if self.in_flight.len() > MAX_IN_FLIGHT {
+            self.in_flight.pop_front();
+        }
+        self.last_sent = Some(now);
+
+        self.packet(
+            sequence,
+            PacketBody::Frames {
+                reliable,
+                sequenced,
+            },
+        )
+    }
+
+    // tells the other side we are going away
+    pub fn close_packet(&mut self) -> Packet {
+        let sequence = self.next_sequence;
+        self.next_sequence += 1;
+
+        self.packet(sequence, PacketBody::Close)
+    }
+
+    fn packet(&mut self, sequence: u32, body: PacketBody) -> Packet {
+        self.ack_owed_since = None;
+
+        Packet {
+            sequence,
+            ack: self.newest_received,
+            ack_bits: self.received_bits,
+            body,
+        }
+    }
+
+    // every reliable frame that was queued has been acknowledged
+    pub fn all_acknowledged(&self) -> bool {
+        self.unacked.is_empty()
+    }
+
+    // takes in a packet from the other side and returns the frames it lets us hand over,
+    // in the order they should be read
+    pub fn receive(&mut self, packet: Packet, now: Instant) -> Vec<Vec<u8>> {
+        self.acknowledged(packet.ack, packet.ack_bits);
+
+        if !self.mark_received(packet.sequence) {
+            return Vec::new(); // a duplicate, everything in it was handled the first time
+        }
+        if self.ack_owed_since.is_none() {
+            self.ack_owed_since = Some(now);
+        }
+
+        let (reliable, sequenced) = match packet.body {
+            PacketBody::Frames {
+                reliable,
+                sequenced,
+            } => (reliable, sequenced),
+            PacketBody::Close => return Vec::new(),
+        };
+
+        let mut frames = Vec::new();
+        for reliable in reliable {
+            if reliable.id >= self.next_delivery {
+                self.early.insert(reliable.id, reliable.frame);
+            }
+        }
+        while let Some(frame) = self.early.remove(&self.next_delivery) {
+            frames.push(frame);
+            self.next_delivery += 1;
+        }
+
+        // a sequenced frame older than one we already handed over is out of date
+        if let Some(sequenced) = sequenced {
+            if packet.sequence > self.newest_sequenced {
+                self.newest_sequenced = packet.sequence;
+                frames.push(sequenced);
+            }
+        }
+
+        frames
+    }
+
+    // returns false if the packet arrived before
+    fn mark_received(&mut self, sequence: u32) -> bool {
+        if sequence > self.newest_received {
+            let shift = sequence - self.newest_received;
+            // the old newest packet is now one of the ones before it
+            self.received_bits = if self.newest_received == 0 || shift > ACK_WINDOW {
+                0
+            } else if shift == ACK_WINDOW {
+                1 << (ACK_WINDOW - 1)
+            } else {
+                (self.received_bits << shift) | (1 << (shift - 1))
+            };
+            self.newest_received = sequence;
+            return true;
+        }
+
+        let distance = self.newest_received - sequence;
+        if distance == 0 {
+            return false;
+        }
+        // too old to tell apart from a duplicate, the frames in it are safe to look at again
+        // since reliable ones are only handed over once and sequenced ones are out of date
+        if distance > ACK_WINDOW {
+            return true;
+        }
+
+        let bit = 1 << (distance - 1);
+        if self.received_bits & bit != 0 {
+            return false;
+        }
+        self.received_bits |= bit;
+        true
+    }
+
+    // the other side has our packets up to ack, and the ones before it in ack_bits
+    fn acknowledged(&mut self, ack: u32, ack_bits: u32) {
+        if ack == 0 {
+            return;
+        }
+
+        let is_acked = |sequence: u32| {
+            if sequence == ack {
+                return true;
+            }
+            if sequence > ack || ack - sequence > ACK_WINDOW {
+                return false;
+            }
+            ack_bits & (1 << (ack - sequence - 1)) != 0
+        };
+
+        let unacked = &mut self.unacked;
+        self.in_flight.retain(|(sequence, ids)| {
+            if !is_acked(*sequence) {
+                return true;
+            }
+            for id in ids {
+                unacked.remove(id);
+            }
+            false
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // carries packets between two channels, losing every few and swapping the order of others
+    struct LossyLink {
+        sent: usize,
+        drop_every: usize,
+        held: Option<Packet>,
+    }
+
+    impl LossyLink {
+        fn new(drop_every: usize) -> LossyLink {
+            LossyLink {
+                sent: 0,
+                drop_every,
+                held: None,
+            }
+        }
+
+        // the packets that make it to the other side right now
+        fn carry(&mut self, packet: Packet) -> Vec<Packet> {
+            self.sent += 1;
+            if self.sent % self.drop_every == 0 {
+                return Vec::new();
+            }
+
+            // every other packet that makes it is held back until the one after it
+            match self.held.take() {
+                Some(held) => vec![packet, held],
+                None => {
+                    self.held = Some(packet);
+                    Vec::new()
+                }
+            }
+        }
+    }
+
+    fn frame(id: u8) -> Vec<u8> {
+        vec![id; 3]
+    }
+
+    #[test]
+    fn reliable_frames_arrive_once_and_in_order() {
+        let mut sender = PacketChannel::new();
+        let mut receiver = PacketChannel::new();
+        let mut there = LossyLink::new(3);
+        let mut back = LossyLink::new(4);
+        let mut now = Instant::now();
+
+        for id in 0..40 {
+            sender.queue_reliable(frame(id));
+        }
+
+        let mut received = Vec::new();
+        for _ in 0..200 {
+            if sender.needs_packet(now) {
+                for packet in there.carry(sender.next_packet(None, now)) {
+                    received.extend(receiver.receive(packet, now));
+                }
+            }
+            if receiver.needs_packet(now) {
+                for packet in back.carry(receiver.next_packet(None, now)) {
+                    sender.receive(packet, now);
+                }
+            }
+            now += ACK_TIME;
+        }
+
+        let expected: Vec<Vec<u8>> = (0..40).map(frame).collect();
+        assert_eq!(received, expected);
+        assert!(sender.all_acknowledged());
+    }
+
+    #[test]
+    fn sequenced_frames_older_than_the_newest_are_dropped() {
+        let mut sender = PacketChannel::new();
+        let mut receiver = PacketChannel::new();
+        let now = Instant::now();
+
+        let first = sender.next_packet(Some(frame(1)), now);
+        let second = sender.next_packet(Some(frame(2)), now);
+        let third = sender.next_packet(Some(frame(3)), now);
+
+        assert_eq!(receiver.receive(second, now), vec![frame(2)]);
+        assert!(receiver.receive(first, now).is_empty());
+        assert_eq!(receiver.receive(third, now), vec![frame(3)]);
+    }
+
+    #[test]
+    fn duplicate_packets_are_ignored() {
+        let mut sender = PacketChannel::new();
+        let mut receiver = PacketChannel::new();
+        let now = Instant::now();
+
+        sender.queue_reliable(frame(1));
+        let packet = sender.next_packet(Some(frame(2)), now);
+
+        assert_eq!(
+            receiver.receive(packet.clone(), now),
+            vec![frame(1), frame(2)]
+        );
+        assert!(receiver.receive(packet, now).is_empty());
+    }
+
+    #[test]
+    fn acknowledged_frames_are_not_sent_again() {
+        let mut sender = PacketChannel::new();
+        let mut receiver = PacketChannel::new();
+        let now = Instant::now();
+
+        sender.queue_reliable(frame(1));
+        let _lost = sender.next_packet(None, now);
+
+        // the frame goes out again once its time is up and this time it arrives
+        let later = now + RESEND_TIME;
+        let resent = sender.next_packet(None, later);
+        assert_eq!(receiver.receive(resent, later), vec![frame(1)]);
+        sender.receive(receiver.next_packet(None, later), later);
+
+        assert!(sender.all_acknowledged());
+        match sender.next_packet(None, later + RESEND_TIME * 2).body {
+            PacketBody::Frames { reliable, .. } => assert!(reliable.is_empty()),
+            body => panic!("expected frames, got {:?}", body),
+        }
+    }
+
+    #[test]
+    fn acks_cover_the_packets_before_the_newest() {
+        let mut sender = PacketChannel::new();
+        let mut receiver = PacketChannel::new();
+        let now = Instant::now();
+
+        let packets: Vec<Packet> = (0..5).map(|_| sender.next_packet(None, now)).collect();
+        for (index, packet) in packets.into_iter().enumerate() {
+            // the third one is lost
+            if index != 2 {
+                receiver.receive(packet, now);
+            }
+        }
+
+        let ack = receiver.next_packet(None, now);
+        assert_eq!(ack.ack, 5);
+        assert_eq!(ack.ack_bits, 0b1101);
+    }
+}
@@ -0,0 +1,1295 @@
+use serde::{Deserialize, Serialize};
+
+use std::cmp::Ordering;
+use std::collections::VecDeque;
+use std::time::Duration;
+
+use crate::constants::{
+    Timing, ATTACK_FRAMES, ATTACK_REPEATS, GUARD_DAMAGE, GUARD_REGEN, MAX_GUARD, MAX_HP, SCALE,
+    SCREEN_HEIGHT, SCREEN_WIDTH, TICK_RATE, TILE_SIZE,
+};
+use crate::geometry::{Point2, Rect};
+use crate::handshake::MatchRules;
+use crate::input::{is_acknowledged, InputFrame};
+
+// most inputs we keep queued for a player, a client that gets further ahead than this has its
+// oldest inputs dropped so it can not build up lag on the server
+pub const MAX_QUEUED_INPUTS: usize = 8;
+
+#[derive(Serialize, Deserialize, PartialEq, Copy, Clone, Debug)]
+pub enum RematchStatus {
+    Yes,
+    No,
+    Maybe,
+}
+
+// seat in the room, 0 or 1. only ever shown to players with 1 added
+pub type PlayerId = usize;
+
+// why a match ended
+#[derive(Serialize, Deserialize, PartialEq, Copy, Clone, Debug)]
+pub enum EndReason {
+    Knockout,       // a player ran out of health
+    TimeUp,         // the clock ran out so whoever had more health won
+    Forfeit,        // the other player left the match
+    Disconnect,     // the other player lost their connection and did not come back in time
+    DoubleKnockout, // both players ran out of health on the same tick so nobody won
+    Rounds,         // every round was played and the winner took more of them
+}
+
+// how a match, or a single round of it, ended
+#[derive(Serialize, Deserialize, PartialEq, Copy, Clone, Debug)]
+pub struct MatchResult {
+    pub winner: Option<PlayerId>, // None is a draw
+    pub reason: EndReason,
+    pub final_hp: [i8; 2],  // health each player was left with
+    pub duration: Duration, // from the start of the match, or of the round, to the end
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Copy, Clone, Debug)]
+pub enum MatchStatus {
+    InProgress,
+    Over(MatchResult),
+    Rematch(RematchStatus),
+    RoundOver(MatchResult), // how the last round went, the next one starts shortly
+    SuddenDeath,            // the round was tied on time, there is no clock and one hit wins
+}
+
+// the state of a whole match, this is what gets sent back and forth between the client and server
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub struct ServerGameMatch {
+    pub clock: Clock,
+    pub server_entities: Vec<ServerEntity>,
+    pub match_status: MatchStatus,
+    pub round_wins: Vec<u8>, // rounds each player has won so far this match
+    // only the server needs the following so they never go over the wire
+    #[serde(skip)]
+    tick: u32, // how many times the match has been stepped
+    #[serde(skip)]
+    queued_inputs: Vec<VecDeque<InputFrame>>, // inputs that have not been simulated yet
+    #[serde(skip)]
+    inputs: Vec<InputFrame>, // input each player is currently holding
+    #[serde(skip)]
+    acks: Vec<u32>, // tick of the last input simulated for each player
+    #[serde(skip)]
+    break_ticks: u32, // ticks left until the next round starts
+    #[serde(skip)]
+    rounds_played: u8, // drawn rounds count too, a match never goes past rules.rounds
+    #[serde(skip)]
+    match_started: Option<u32>, // tick the first round started on, None until both players came
+    #[serde(skip)]
+    round_started: u32, // tick the current round started on
+    #[serde(skip)]
+    timing: Timing, // how many ticks things take at the rate the match is stepped
+    #[serde(skip, default = "MatchRules::new")]
+    rules: MatchRules, // round time, health, damage and rounds the room was opened with
+}
+
+impl Default for ServerGameMatch {
+    fn default() -> ServerGameMatch {
+        ServerGameMatch::new()
+    }
+}
+
+impl ServerGameMatch {
+    pub fn new() -> ServerGameMatch {
+        ServerGameMatch::with_rules(MatchRules::new(), TICK_RATE)
+    }
+
+    pub fn with_rules(rules: MatchRules, tick_rate: u32) -> ServerGameMatch {
+        let ent = ServerEntity::with_hp(0, rules.max_hp);
+        let ent1 = ServerEntity::with_hp(1, rules.max_hp);
+        let entity_vector = vec![ent, ent1];
+
+        ServerGameMatch {
+            clock: Clock {
+                current: rules.round_time,
+            },
+            server_entities: entity_vector,
+            match_status: MatchStatus::InProgress,
+            round_wins: vec![0; 2],
+            tick: 0,
+            queued_inputs: vec![VecDeque::new(), VecDeque::new()],
+            inputs: vec![InputFrame::new(0); 2],
+            acks: vec![0; 2],
+            break_ticks: 0,
+            rounds_played: 0,
+            match_started: None,
+            round_started: 0,
+            timing: Timing::new(tick_rate),
+            rules,
+        }
+    }
+
+    // queue what the player wants to do, every tick simulates one input from each player so
+    // the client can replay exactly what the server did
+    pub fn update_entity(&mut self, id: usize, input: &InputFrame) {
+        // inputs that show up late or twice were already simulated
+        if is_acknowledged(input.tick, self.acks[id]) {
+            return;
+        }
+
+        let queue = &mut self.queued_inputs[id];
+        if queue.len() == MAX_QUEUED_INPUTS {
+            queue.pop_front();
+        }
+        queue.push_back(*input);
+    }
+
+    // the player can only vote for a rematch once the match is over
+    pub fn set_redo_status(&mut self, id: usize, status: MatchStatus) {
+        if let MatchStatus::Over(_) = self.match_status {
+            self.server_entities[id].redo_status = status;
+        }
+    }
+
+    // advance the match by one fixed tick, the server is the only one allowed to move entities
+    pub fn step(&mut self) {
+        self.tick = self.tick.wrapping_add(1);
+
+        // inputs are used up even when the match is over so the acks keep moving
+        for id in 0..self.inputs.len() {
+            match self.queued_inputs[id].pop_front() {
+                Some(input) => {
+                    self.inputs[id] = input;
+                    self.acks[id] = input.tick;
+                }
+                // nothing new arrived so the player keeps holding the same buttons
+                // but an attack press only lasts a single tick
+                None => self.inputs[id].attack_pressed = false,
+            }
+        }
+
+        if self.is_live() {
+            for (entity, input) in self.server_entities.iter_mut().zip(self.inputs.iter()) {
+                entity.apply_input(input);
+                entity.step(&self.timing);
+            }
+
+            // check if there is a collision
+            for id in 0..self.server_entities.len() {
+                for index in 0..self.server_entities.len() {
+                    if id == index {
+                        continue;
+                    }
+
+                    self.attack_bound_check(id, index);
+                }
+            }
+        } else {
+            match self.match_status {
+                MatchStatus::InProgress | MatchStatus::SuddenDeath => self.score_round(),
+                MatchStatus::RoundOver(_) => {
+                    self.break_ticks = self.break_ticks.saturating_sub(1);
+                    if self.break_ticks == 0 {
+                        self.round_started = self.tick;
+                        self.reset_round();
+                    }
+                }
+                _ => (),
+            };
+        }
+    }
+
+    // the round just stopped being live so we need to say who won the fight
+    fn score_round(&mut self) {
+        let knocked_out = self
+            .server_entities
+            .iter()
+            .filter(|entity| entity.hp <= 0)
+            .count();
+
+        if knocked_out == 2 {
+            self.end_round(None, EndReason::DoubleKnockout);
+        } else if knocked_out == 1 {
+            self.end_round(Some(self.get_player_id_most_hp()), EndReason::Knockout);
+        } else if self.is_tied() {
+            // sudden death can not run out of time so this only happens on the clock
+            self.start_sudden_death();
+        } else {
+            self.end_round(Some(self.get_player_id_most_hp()), EndReason::TimeUp);
+        }
+    }
+
+    // the round goes to the winner, and the match too once they have won enough rounds or
+    // there are no rounds left to play
+    fn end_round(&mut self, winner: Option<PlayerId>, reason: EndReason) {
+        self.rounds_played += 1;
+
+        if let Some(id) = winner {
+            self.round_wins[id] += 1;
+            if self.round_wins[id] >= self.rules.rounds_to_win() {
+                self.end_match(winner, reason);
+                return;
+            }
+        }
+
+        if self.rounds_played >= self.rules.rounds {
+            // drawn rounds used up the set, whoever took more of the rest wins
+            match self.round_wins[0].cmp(&self.round_wins[1]) {
+                Ordering::Greater => self.end_match(Some(0), EndReason::Rounds),
+                Ordering::Less => self.end_match(Some(1), EndReason::Rounds),
+                Ordering::Equal => self.end_match(None, reason),
+            }
+        } else {
+            let result = self.result(winner, reason, self.round_started);
+            self.match_status = MatchStatus::RoundOver(result);
+            self.break_ticks = self.timing.round_break_ticks;
+        }
+    }
+
+    // everyone goes back to their corner with full health and the clock is stopped
+    fn start_sudden_death(&mut self) {
+        self.reset_round();
+        self.clock.current = 0;
+        self.match_status = MatchStatus::SuddenDeath;
+    }
+
+    fn end_match(&mut self, winner: Option<PlayerId>, reason: EndReason) {
+        // a match given up before it started took no time at all
+        let started = self.match_started.unwrap_or(self.tick);
+        self.match_status = MatchStatus::Over(self.result(winner, reason, started));
+
+        // match has just finished so we need to change the redo status to Rematch
+        for entity in &mut self.server_entities {
+            entity.redo_status = MatchStatus::Rematch(RematchStatus::Maybe);
+        }
+    }
+
+    // the player lost their connection, they stop doing whatever they were doing
+    pub fn release_input(&mut self, id: usize) {
+        self.queued_inputs[id].clear();
+        self.inputs[id] = InputFrame::new(self.acks[id]);
+    }
+
+    // someone new took the slot, their client counts its inputs up from 0 again so nothing
+    // the last player sent or was acknowledged for can be held against them
+    pub fn reset_input(&mut self, id: usize) {
+        self.queued_inputs[id].clear();
+        self.inputs[id] = InputFrame::new(0);
+        self.acks[id] = 0;
+    }
+
+    // the player left for good so the one still here wins, and whoever takes the slot next
+    // starts out undecided about a rematch. reason is Forfeit or Disconnect
+    pub fn remove_player(&mut self, id: usize, reason: EndReason) {
+        self.release_input(id);
+
+        // leaving between rounds gives up the rest of the match as well
+        let playing = matches!(
+            self.match_status,
+            MatchStatus::InProgress | MatchStatus::RoundOver(_) | MatchStatus::SuddenDeath
+        );
+        if playing {
+            let winner_id = (id + 1) % self.server_entities.len();
+            self.end_match(Some(winner_id), reason);
+        } else {
+            self.server_entities[id].redo_status = MatchStatus::Rematch(RematchStatus::Maybe);
+        }
+    }
+
+    fn is_live(&self) -> bool {
+        // sudden death never runs out of time
+        let time_left = self.clock.current > 0 || self.match_status == MatchStatus::SuddenDeath;
+        self.server_entities[0].hp > 0 && self.server_entities[1].hp > 0 && time_left
+    }
+
+    fn is_tied(&self) -> bool {
+        self.server_entities[0].hp == self.server_entities[1].hp
+    }
+
+    // everything the players are shown once a round or the match is over
+    fn result(&self, winner: Option<PlayerId>, reason: EndReason, started: u32) -> MatchResult {
+        let ticks = self.tick.wrapping_sub(started) as u64;
+
+        MatchResult {
+            winner,
+            reason,
+            final_hp: [
+                self.server_entities[0].hp.max(0),
+                self.server_entities[1].hp.max(0),
+            ],
+            duration: Duration::from_millis(ticks * 1000 / self.timing.tick_rate as u64),
+        }
+    }
+
+    fn get_player_id_most_hp(&self) -> usize {
+        if self.server_entities[0].hp > self.server_entities[1].hp {
+            return 0;
+        }
+
+        1
+    }
+
+    // the server's round timer decides how much time is left, once it reads 0 the next step
+    // ends the match
+    pub fn set_clock(&mut self, remaining: u16) {
+        self.clock.current = remaining;
+    }
+
+    fn attack_bound_check(&mut self, first_entity_id: usize, second_entity_id: usize) {
+        if self.server_entities[first_entity_id]
+            .get_entity_actions_as_ref()
+            .damage_check
+            && !self.server_entities[first_entity_id]
+                .get_entity_actions_as_ref()
+                .blocking
+            && self.server_entities[first_entity_id]
+                .get_attack_bound()
+                .check_bounds(&self.server_entities[second_entity_id].get_bound())
+        {
+            let blocked = self.server_entities[second_entity_id]
+                .get_entity_actions_as_ref()
+                .blocking;
+
+            if blocked && self.server_entities[second_entity_id].is_parrying(&self.timing) {
+                self.server_entities[second_entity_id].parry_ticks = self.timing.parry_flash_ticks;
+                self.server_entities[first_entity_id].stun(self.timing.parry_stun_ticks);
+            } else if blocked {
+                self.server_entities[second_entity_id]
+                    .take_guard_damage(GUARD_DAMAGE, &self.timing);
+            } else {
+                // in sudden death any hit is enough
+                let damage = if self.match_status == MatchStatus::SuddenDeath {
+                    self.server_entities[second_entity_id].hp
+                } else {
+                    self.rules.damage
+                };
+                self.server_entities[second_entity_id].take_damage(damage);
+            }
+        }
+        self.server_entities[first_entity_id]
+            .get_entity_actions_as_mut_ref()
+            .damage_check = false;
+    }
+
+    // used from the next restart on, the clock itself is set by the server every tick
+    pub fn set_round_time(&mut self, round_time: u16) {
+        self.rules.round_time = round_time;
+    }
+
+    // a new match from the first round
+    pub fn restart_match(&mut self) {
+        for wins in &mut self.round_wins {
+            *wins = 0;
+        }
+        self.rounds_played = 0;
+        self.match_started = Some(self.tick);
+        self.round_started = self.tick;
+        self.reset_round();
+    }
+
+    // the match is stepped while the room waits for the second player, the time it takes
+    // counts from when both are here. coming back after a dropped connection is not a new start
+    pub fn start(&mut self) {
+        if self.match_started.is_none() {
+            self.match_started = Some(self.tick);
+            self.round_started = self.tick;
+        }
+    }
+
+    // everyone back where they started with full health, round wins carry over
+    fn reset_round(&mut self) {
+        for id in 0..self.server_entities.len() {
+            let resets = self.server_entities[id].resets.wrapping_add(1);
+            self.server_entities[id] = ServerEntity::with_hp(id, self.rules.max_hp);
+            self.server_entities[id].resets = resets;
+            self.inputs[id] = InputFrame::new(self.acks[id]);
+        }
+
+        // the simulation could step before the clock is updated again so the round can not
+        // start out of time
+        self.clock.current = self.rules.round_time;
+        self.match_status = MatchStatus::InProgress;
+    }
+}
+
+// accessors
+impl ServerGameMatch {
+    pub fn get_clock(&self) -> Clock {
+        self.clock.clone()
+    }
+    pub fn get_match_status(&self) -> MatchStatus {
+        self.match_status
+    }
+    pub fn get_server_entities(&self) -> Vec<ServerEntity> {
+        self.server_entities.clone()
+    }
+    pub fn get_tick(&self) -> u32 {
+        self.tick
+    }
+    // tick of the last input from this player that has been simulated
+    pub fn get_ack(&self, id: usize) -> u32 {
+        self.acks[id]
+    }
+}
+
+#[derive(Clone, Deserialize, Serialize, Debug)]
+pub struct ServerEntity {
+    pub id: usize,
+    pub hp: i8, // health of entity
+    pub entity_actions: EntityActions,
+    pub attack_animator: ServerAnimator,
+    pub pos: Point2,
+    pub vel: Point2,
+    pub bound: Rect,
+    pub attack_bound: Rect,
+    pub redo_status: MatchStatus,
+    pub resets: u32, // times the entity was sent back to its spawn, only ever counts up
+    pub guard: u8,   // blocked hits take this down instead of health
+    pub guard_regen: u16, // regen carried over until it adds up to a whole point of guard
+    pub stun_ticks: u16, // left until a broken guard or a parried attack lets the player move
+    pub block_ticks: u16, // how long block has been held, 1 on the tick it was pressed
+    pub release_ticks: u16, // how long block was let go before it last went up
+    pub parry_ticks: u16, // left of the parry being shown after one lands
+}
+
+impl ServerEntity {
+    pub fn new(id: usize) -> ServerEntity {
+        // entity location
+        let position = match id {
+            1 => Point2::new(
+                SCREEN_WIDTH - (20.0 * SCALE),
+                SCREEN_HEIGHT - (TILE_SIZE * SCALE),
+            ),
+            _ => Point2::new(20.0 * SCALE, SCREEN_HEIGHT - (TILE_SIZE * SCALE)),
+        };
+
+        // Rect.top_left location
+        let bound_top_left_position = match id {
+            1 => {
+                let mut pos = position;
+                pos.x -= TILE_SIZE * SCALE;
+                pos
+            }
+            _ => position,
+        };
+
+        // Rect.bottom_right location
+        let bound_bottom_right_position = Point2::new(
+            bound_top_left_position.x + (TILE_SIZE * SCALE),
+            bound_top_left_position.y + (TILE_SIZE * SCALE),
+        );
+
+        // Attack Rect.top_left location
+        let attack_top_left_position = match id {
+            1 => Point2::new(
+                bound_top_left_position.x,
+                bound_top_left_position.y + (6.0 * SCALE),
+            ),
+            _ => Point2::new(
+                bound_top_left_position.x + (20.0 * SCALE),
+                bound_top_left_position.y + (6.0 * SCALE),
+            ),
+        };
+
+        // Attack Rect.bottom_right location
+        let attack_bottom_right_position = match id {
+            1 => Point2::new(
+                bound_top_left_position.x + (12.0 * SCALE),
+                bound_top_left_position.y + (17.0 * SCALE),
+            ),
+            _ => Point2::new(
+                bound_bottom_right_position.x,
+                bound_top_left_position.y + (17.0 * SCALE),
+            ),
+        };
+
+        let facing = match id {
+            1 => Action::Left,
+            _ => Action::Right,
+        };
+
+        ServerEntity {
+            id,
+            hp: MAX_HP,
+            entity_actions: EntityActions::new(facing),
+            attack_animator: ServerAnimator::new(),
+            pos: position,
+            vel: Point2::new(0.0, 0.0),
+            bound: Rect::new(bound_top_left_position, bound_bottom_right_position),
+            attack_bound: Rect::new(attack_top_left_position, attack_bottom_right_position),
+            redo_status: MatchStatus::InProgress,
+            resets: 0,
+            guard: MAX_GUARD,
+            guard_regen: 0,
+            stun_ticks: 0,
+            block_ticks: 0,
+            release_ticks: u16::MAX,
+            parry_ticks: 0,
+        }
+    }
+
+    // an entity for a room that starts players out with a different amount of health
+    pub fn with_hp(id: usize, hp: i8) -> ServerEntity {
+        let mut entity = ServerEntity::new(id);
+        entity.hp = hp;
+        entity
+    }
+
+    // turn the buttons the player is holding into actions
+    pub fn apply_input(&mut self, input: &InputFrame) {
+        // a stunned player can not do anything until it wears off
+        if self.is_stunned() {
+            self.entity_actions.moving_left = false;
+            self.entity_actions.moving_right = false;
+            self.entity_actions.blocking = false;
+            self.hold_block(false);
+            return;
+        }
+
+        self.entity_actions.moving_left = input.left;
+        self.entity_actions.moving_right = input.right;
+        self.entity_actions.blocking = input.block;
+        self.hold_block(input.block);
+
+        if input.left {
+            self.entity_actions.facing = Action::Left;
+        }
+        if input.right {
+            self.entity_actions.facing = Action::Right;
+        }
+
+        if input.attack_pressed && self.entity_actions.can_attack {
+            self.entity_actions.attacking = true;
+            self.entity_actions.can_attack = false;
+            self.attack_animator.end();
+        }
+    }
+
+    // counts how long block has been held or let go for, whatever the release lasted is kept
+    // for as long as the block that follows it stays up
+    fn hold_block(&mut self, block: bool) {
+        if block {
+            self.block_ticks = self.block_ticks.saturating_add(1);
+        } else {
+            self.drop_block();
+            self.release_ticks = self.release_ticks.saturating_add(1);
+        }
+    }
+
+    fn drop_block(&mut self) {
+        if self.block_ticks > 0 {
+            self.release_ticks = 0;
+        }
+        self.block_ticks = 0;
+    }
+
+    // move the entity and advance its attack by one tick
+    pub fn step(&mut self, timing: &Timing) {
+        self.parry_ticks = self.parry_ticks.saturating_sub(1);
+
+        // the guard only comes back once the player stops blocking and is not stunned.
+        // GUARD_REGEN is per tick at TICK_RATE, at other rates the points are spread out
+        // over the ticks so a second of rest gives back the same amount of guard
+        if self.is_stunned() {
+            self.stun_ticks -= 1;
+            self.guard_regen = 0;
+        } else if self.entity_actions.blocking {
+            self.guard_regen = 0;
+        } else {
+            self.guard_regen += GUARD_REGEN as u16 * TICK_RATE as u16;
+            let points = self.guard_regen / timing.tick_rate as u16;
+            self.guard_regen %= timing.tick_rate as u16;
+            self.guard = self.guard.saturating_add(points as u8).min(MAX_GUARD);
+        }
+
+        // update velocity
+        if !self.entity_actions.attacking && !self.entity_actions.blocking {
+            self.vel.x = if self.entity_actions.moving_right {
+                timing.move_speed
+            } else if self.entity_actions.moving_left {
+                -timing.move_speed
+            } else {
+                0.0
+            };
+        } else {
+            if self.entity_actions.attacking {
+                if self.attack_animator.current_repeat() > ATTACK_REPEATS {
+                    // sword is all the way down so this is when the hit lands
+                    self.entity_actions.damage_check = true;
+                    self.entity_actions.attacking = false;
+                    self.entity_actions.can_attack = true;
+                } else {
+                    self.attack_animator.tick(
+                        ATTACK_FRAMES,
+                        timing.attack_frame_ticks,
+                        ATTACK_REPEATS,
+                    );
+                }
+            }
+
+            self.vel.x = 0.0;
+        }
+
+        // update position
+        self.pos.x += self.vel.x;
+        self.pos.y += self.vel.y;
+
+        self.bound.translate(&self.vel);
+        self.attack_bound.translate(&self.vel);
+    }
+}
+
+// all possible action states for an entity to be in
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub enum Action {
+    Left,
+    Right,
+}
+
+// helper struct for cleaning up Entity struct
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct EntityActions {
+    pub facing: Action, // Left or Right
+    pub moving_left: bool,
+    pub moving_right: bool,
+    pub can_attack: bool,
+    pub attacking: bool,
+    pub damage_check: bool,
+    pub blocking: bool,
+}
+
+impl EntityActions {
+    pub fn new(facing: Action) -> EntityActions {
+        EntityActions {
+            facing,
+            moving_left: false,
+            moving_right: false,
+            can_attack: true,
+            attacking: false,
+            damage_check: false,
+            blocking: false,
+        }
+    }
+}
+
+impl ServerEntity {
+    pub fn take_damage(&mut self, dmg: i8) {
+        if self.hp > 0 {
+            self.hp -= dmg;
+        }
+    }
+
+    // a blocked hit, once the guard is gone the player drops it and any attack they started
+    pub fn take_guard_damage(&mut self, amount: u8, timing: &Timing) {
+        self.guard = self.guard.saturating_sub(amount);
+
+        if self.guard == 0 {
+            self.stun(timing.guard_break_ticks);
+        }
+    }
+
+    // the player drops their block and any attack they started and can not act for a while
+    pub fn stun(&mut self, ticks: u16) {
+        self.stun_ticks = ticks;
+        self.drop_block();
+        self.entity_actions.blocking = false;
+        self.entity_actions.attacking = false;
+        self.entity_actions.damage_check = false;
+        self.entity_actions.can_attack = true;
+        self.attack_animator.end();
+    }
+
+    pub fn is_stunned(&self) -> bool {
+        self.stun_ticks > 0
+    }
+
+    // a block that has only just gone up turns the next hit back on the attacker, as long as
+    // the player was not blocking for a while before
+    pub fn is_parrying(&self, timing: &Timing) -> bool {
+        self.entity_actions.blocking
+            && self.block_ticks <= timing.parry_window_ticks
+            && self.release_ticks >= timing.parry_cooldown_ticks
+    }
+
+    pub fn get_bound(&self) -> Rect {
+        self.bound
+    }
+
+    pub fn get_attack_bound(&self) -> Rect {
+        self.attack_bound
+    }
+
+    pub fn get_entity_actions_as_ref(&mut self) -> &EntityActions {
+        &self.entity_actions
+    }
+
+    pub fn get_entity_actions_as_mut_ref(&mut self) -> &mut EntityActions {
+        &mut self.entity_actions
+    }
+
+    pub fn get_redo_status_as_ref(&self) -> &MatchStatus {
+        &self.redo_status
+    }
+
+    pub fn get_redo_status(&self) -> MatchStatus {
+        self.redo_status
+    }
+}
+
+// accessors used by the client when copying server data into its own entities
+impl ServerEntity {
+    pub fn get_id(&self) -> usize {
+        self.id
+    }
+
+    pub fn get_hp(&self) -> i8 {
+        self.hp
+    }
+
+    pub fn get_pos(&self) -> Point2 {
+        self.pos
+    }
+
+    pub fn get_vel(&self) -> Point2 {
+        self.vel
+    }
+
+    pub fn get_attack_animator(&self) -> ServerAnimator {
+        self.attack_animator.clone()
+    }
+
+    pub fn get_entity_actions(&self) -> EntityActions {
+        self.entity_actions.clone()
+    }
+
+    pub fn get_resets(&self) -> u32 {
+        self.resets
+    }
+
+    pub fn get_guard(&self) -> u8 {
+        self.guard
+    }
+
+    // true while the client should be showing a parry that just landed
+    pub fn get_parried(&self) -> bool {
+        self.parry_ticks > 0
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug)]
+pub struct Clock {
+    current: u16,
+}
+impl Default for Clock {
+    fn default() -> Clock {
+        Clock::new()
+    }
+}
+
+impl Clock {
+    pub fn new() -> Clock {
+        Clock { current: 0 }
+    }
+
+    pub fn current(&self) -> u16 {
+        self.current
+    }
+}
+
+// server will not handle animation timing
+// rather it will facillitate the syncing
+// of frames
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct ServerAnimator {
+    current_frame: u64,
+    current_repeat: i8,
+    ticks: u32, // ticks spent on the current frame
+}
+
+impl Default for ServerAnimator {
+    fn default() -> ServerAnimator {
+        ServerAnimator::new()
+    }
+}
+
+impl ServerAnimator {
+    pub fn new() -> ServerAnimator {
+        ServerAnimator {
+            current_frame: 0,
+            current_repeat: 0,
+            ticks: 0,
+        }
+    }
+
+    pub fn with_frame(current_frame: usize, current_repeat: i8) -> ServerAnimator {
+        ServerAnimator {
+            current_frame: current_frame as u64,
+            current_repeat,
+            ticks: 0,
+        }
+    }
+
+    // same frame stepping as the client's Animator but counted in ticks instead of wall time
+    // any negative repeat means to repeat indefinitely
+    pub fn tick(&mut self, total_frames: usize, frame_ticks: u32, repeat: i8) {
+        self.ticks += 1;
+
+        // is it time to change frames? if not return out of fn
+        if self.ticks < frame_ticks {
+            return;
+        }
+
+        self.ticks = 0;
+        let total_frames = total_frames as u64;
+        if self.current_frame != total_frames && (self.current_repeat <= repeat || repeat < 0) {
+            self.current_frame += 1;
+        }
+
+        if self.current_frame == total_frames {
+            if repeat >= 0 {
+                self.current_repeat += 1;
+            }
+
+            if self.current_repeat <= repeat || repeat < 0 {
+                self.current_frame = 0;
+            }
+        }
+    }
+
+    // ending animation and resetting
+    pub fn end(&mut self) {
+        self.current_frame = 0;
+        self.current_repeat = 0;
+        self.ticks = 0;
+    }
+
+    pub fn current_frame(&self) -> usize {
+        self.current_frame as usize
+    }
+
+    pub fn current_repeat(&self) -> i8 {
+        self.current_repeat
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::constants::{
+        GUARD_BREAK_TICKS, MOVE_SPEED, PARRY_COOLDOWN_TICKS, PARRY_FLASH_TICKS, PARRY_STUN_TICKS,
+        PARRY_WINDOW_TICKS, ROUND_BREAK_TICKS,
+    };
+
+    fn best_of(rounds: u8) -> ServerGameMatch {
+        let mut rules = MatchRules::new();
+        rules.rounds = rounds;
+        let mut game_match = ServerGameMatch::with_rules(rules, TICK_RATE);
+        game_match.start();
+        game_match
+    }
+
+    // knocks out the loser and steps once so the round is scored
+    fn knock_out(game_match: &mut ServerGameMatch, loser: usize) {
+        game_match.server_entities[loser].hp = 0;
+        game_match.step();
+    }
+
+    fn wait_out_the_break(game_match: &mut ServerGameMatch) {
+        for _ in 0..ROUND_BREAK_TICKS {
+            game_match.step();
+        }
+    }
+
+    fn result(game_match: &ServerGameMatch) -> MatchResult {
+        match game_match.match_status {
+            MatchStatus::Over(result) => result,
+            status => panic!("the match should be over, got {:?}", status),
+        }
+    }
+
+    #[test]
+    fn a_knockout_wins_the_match() {
+        let mut game_match = best_of(1);
+        game_match.server_entities[0].hp = 2;
+        for _ in 0..TICK_RATE {
+            game_match.step();
+        }
+        knock_out(&mut game_match, 1);
+
+        assert_eq!(
+            result(&game_match),
+            MatchResult {
+                winner: Some(0),
+                reason: EndReason::Knockout,
+                final_hp: [2, 0],
+                duration: Duration::from_secs(1) + Duration::from_millis(1000 / 60),
+            }
+        );
+    }
+
+    #[test]
+    fn waiting_for_the_second_player_is_not_part_of_the_match() {
+        let mut rules = MatchRules::new();
+        rules.rounds = 1;
+        let mut game_match = ServerGameMatch::with_rules(rules, TICK_RATE);
+        for _ in 0..TICK_RATE * 10 {
+            game_match.step();
+        }
+        game_match.start();
+        for _ in 0..TICK_RATE {
+            game_match.step();
+        }
+        // a player dropping out and coming back starts nothing over
+        game_match.start();
+        knock_out(&mut game_match, 1);
+
+        let duration = Duration::from_secs(1) + Duration::from_millis(1000 / 60);
+        assert_eq!(result(&game_match).duration, duration);
+    }
+
+    #[test]
+    fn more_health_wins_on_time() {
+        let mut game_match = best_of(1);
+        game_match.server_entities[1].hp -= 1;
+        game_match.set_clock(0);
+        game_match.step();
+
+        let result = result(&game_match);
+        assert_eq!(result.winner, Some(0));
+        assert_eq!(result.reason, EndReason::TimeUp);
+        assert_eq!(result.final_hp, [MAX_HP, MAX_HP - 1]);
+    }
+
+    #[test]
+    fn a_double_knockout_is_a_draw() {
+        let mut game_match = best_of(1);
+        game_match.server_entities[0].hp = 0;
+        // a big hit can take a player below zero but nobody is shown negative health
+        game_match.server_entities[1].hp = -1;
+        game_match.step();
+
+        let result = result(&game_match);
+        assert_eq!(result.winner, None);
+        assert_eq!(result.reason, EndReason::DoubleKnockout);
+        assert_eq!(result.final_hp, [0, 0]);
+    }
+
+    #[test]
+    fn leaving_forfeits_and_dropping_out_disconnects() {
+        let mut game_match = best_of(3);
+        game_match.remove_player(0, EndReason::Forfeit);
+        assert_eq!(result(&game_match).winner, Some(1));
+        assert_eq!(result(&game_match).reason, EndReason::Forfeit);
+
+        let mut game_match = best_of(3);
+        game_match.remove_player(1, EndReason::Disconnect);
+        assert_eq!(result(&game_match).winner, Some(0));
+        assert_eq!(result(&game_match).reason, EndReason::Disconnect);
+
+        // once the match is over leaving changes nothing
+        game_match.remove_player(0, EndReason::Forfeit);
+        assert_eq!(result(&game_match).winner, Some(0));
+    }
+
+    #[test]
+    fn rounds_are_played_until_someone_clinches_the_match() {
+        let mut game_match = ServerGameMatch::new();
+        assert_eq!(game_match.rules.rounds, 3);
+
+        knock_out(&mut game_match, 1);
+        match game_match.match_status {
+            MatchStatus::RoundOver(result) => {
+                assert_eq!(result.winner, Some(0));
+                assert_eq!(result.reason, EndReason::Knockout);
+            }
+            status => panic!("the round should be over, got {:?}", status),
+        }
+        assert_eq!(game_match.round_wins, vec![1, 0]);
+
+        // the next round starts by itself with everyone back at full health
+        wait_out_the_break(&mut game_match);
+        assert_eq!(game_match.match_status, MatchStatus::InProgress);
+        for entity in &game_match.server_entities {
+            assert!(entity.hp == MAX_HP && entity.resets == 1);
+        }
+
+        knock_out(&mut game_match, 0);
+        assert_eq!(game_match.round_wins, vec![1, 1]);
+        wait_out_the_break(&mut game_match);
+
+        game_match.set_clock(0);
+        game_match.server_entities[0].hp -= 1;
+        game_match.step();
+        assert_eq!(result(&game_match).winner, Some(1));
+        assert_eq!(result(&game_match).reason, EndReason::TimeUp);
+        assert_eq!(game_match.round_wins, vec![1, 2]);
+    }
+
+    #[test]
+    fn running_out_of_rounds_goes_to_whoever_won_more() {
+        let mut game_match = best_of(3);
+        knock_out(&mut game_match, 1);
+        wait_out_the_break(&mut game_match);
+        for _ in 0..2 {
+            game_match.server_entities[0].hp = 0;
+            game_match.server_entities[1].hp = 0;
+            game_match.step();
+            wait_out_the_break(&mut game_match);
+        }
+
+        assert_eq!(result(&game_match).winner, Some(0));
+        assert_eq!(result(&game_match).reason, EndReason::Rounds);
+        assert_eq!(game_match.round_wins, vec![1, 0]);
+
+        // and with the rounds split evenly nobody does
+        let mut game_match = best_of(3);
+        knock_out(&mut game_match, 1);
+        wait_out_the_break(&mut game_match);
+        knock_out(&mut game_match, 0);
+        wait_out_the_break(&mut game_match);
+        game_match.server_entities[0].hp = 0;
+        game_match.server_entities[1].hp = 0;
+        game_match.step();
+
+        assert_eq!(result(&game_match).winner, None);
+        assert_eq!(result(&game_match).reason, EndReason::DoubleKnockout);
+    }
+
+    #[test]
+    fn a_tie_on_time_goes_to_sudden_death() {
+        let mut game_match = ServerGameMatch::new();
+        game_match.set_clock(0);
+        game_match.step();
+        assert_eq!(game_match.match_status, MatchStatus::SuddenDeath);
+        assert_eq!(game_match.round_wins, vec![0, 0]);
+
+        // there is no clock to run out
+        for _ in 0..ROUND_BREAK_TICKS {
+            game_match.step();
+        }
+        assert_eq!(game_match.match_status, MatchStatus::SuddenDeath);
+
+        knock_out(&mut game_match, 0);
+        match game_match.match_status {
+            MatchStatus::RoundOver(result) => {
+                assert_eq!(result.winner, Some(1));
+                assert_eq!(result.reason, EndReason::Knockout);
+            }
+            status => panic!("the round should be over, got {:?}", status),
+        }
+        assert_eq!(game_match.round_wins, vec![0, 1]);
+    }
+
+    #[test]
+    fn leaving_between_rounds_forfeits_the_match() {
+        let mut game_match = ServerGameMatch::new();
+        knock_out(&mut game_match, 1);
+
+        game_match.remove_player(0, EndReason::Forfeit);
+        assert_eq!(result(&game_match).winner, Some(1));
+        assert_eq!(result(&game_match).reason, EndReason::Forfeit);
+    }
+
+    // player 0's sword comes down on player 1
+    fn land_hit(game_match: &mut ServerGameMatch) {
+        game_match.server_entities[1].bound = game_match.server_entities[0].attack_bound;
+        game_match.server_entities[0].entity_actions.damage_check = true;
+        game_match.attack_bound_check(0, 1);
+    }
+
+    // player 1 has been holding block for this many ticks, counting the one the hit lands on
+    fn hold_block(game_match: &mut ServerGameMatch, ticks: u16) {
+        let mut input = InputFrame::new(1);
+        input.block = true;
+        for _ in 0..ticks {
+            game_match.server_entities[1].apply_input(&input);
+            game_match.server_entities[1].step(&game_match.timing);
+        }
+    }
+
+    #[test]
+    fn blocked_hits_wear_down_the_guard_until_it_breaks() {
+        let mut game_match = ServerGameMatch::new();
+        hold_block(&mut game_match, PARRY_WINDOW_TICKS + 1);
+
+        land_hit(&mut game_match);
+        land_hit(&mut game_match);
+        assert_eq!(game_match.server_entities[1].hp, MAX_HP);
+        let guard_left = MAX_GUARD - 2 * GUARD_DAMAGE;
+        assert_eq!(game_match.server_entities[1].guard, guard_left);
+        assert!(!game_match.server_entities[1].is_stunned());
+
+        land_hit(&mut game_match);
+        let defender = &game_match.server_entities[1];
+        assert_eq!(defender.hp, MAX_HP);
+        assert_eq!(defender.guard, 0);
+        assert!(defender.is_stunned() && !defender.entity_actions.blocking);
+
+        // with the guard down the next hit goes through
+        land_hit(&mut game_match);
+        assert_eq!(game_match.server_entities[1].hp, MAX_HP - 1);
+    }
+
+    #[test]
+    fn a_broken_guard_stuns_and_then_comes_back() {
+        let mut entity = ServerEntity::new(0);
+        entity.take_guard_damage(MAX_GUARD, &Timing::default());
+
+        let mut input = InputFrame::new(1);
+        input.right = true;
+        input.block = true;
+        input.attack_pressed = true;
+        for _ in 0..GUARD_BREAK_TICKS {
+            entity.apply_input(&input);
+            entity.step(&Timing::default());
+            assert!(!entity.entity_actions.attacking);
+            assert!(!entity.entity_actions.blocking);
+            assert_eq!(entity.vel.x, 0.0);
+        }
+        assert!(!entity.is_stunned());
+        assert_eq!(entity.guard, 0);
+
+        // it only fills back up while the player is not blocking
+        input.attack_pressed = false;
+        entity.apply_input(&input);
+        entity.step(&Timing::default());
+        assert_eq!(entity.guard, 0);
+
+        input.block = false;
+        entity.apply_input(&input);
+        entity.step(&Timing::default());
+        assert_eq!(entity.guard, GUARD_REGEN);
+        assert_eq!(entity.vel.x, MOVE_SPEED);
+    }
+
+    #[test]
+    fn a_block_pressed_as_the_hit_lands_parries_it() {
+        let mut game_match = ServerGameMatch::new();
+        hold_block(&mut game_match, 1);
+        land_hit(&mut game_match);
+
+        let defender = &game_match.server_entities[1];
+        assert_eq!(defender.hp, MAX_HP);
+        assert_eq!(defender.guard, MAX_GUARD);
+        assert_eq!(defender.parry_ticks, PARRY_FLASH_TICKS);
+        assert!(defender.get_parried());
+
+        let attacker = &game_match.server_entities[0];
+        assert_eq!(attacker.stun_ticks, PARRY_STUN_TICKS);
+        assert!(!attacker.entity_actions.damage_check);
+    }
+
+    #[test]
+    fn the_last_tick_of_the_window_still_parries() {
+        let mut game_match = ServerGameMatch::new();
+        hold_block(&mut game_match, PARRY_WINDOW_TICKS);
+        land_hit(&mut game_match);
+
+        assert!(game_match.server_entities[0].is_stunned());
+        assert_eq!(game_match.server_entities[1].guard, MAX_GUARD);
+    }
+
+    #[test]
+    fn one_tick_late_is_an_ordinary_block() {
+        let mut game_match = ServerGameMatch::new();
+        hold_block(&mut game_match, PARRY_WINDOW_TICKS + 1);
+        land_hit(&mut game_match);
+
+        assert!(!game_match.server_entities[0].is_stunned());
+        let defender = &game_match.server_entities[1];
+        assert_eq!(defender.guard, MAX_GUARD - GUARD_DAMAGE);
+        assert!(!defender.get_parried());
+
+        // letting go for long enough and blocking again opens a new window
+        for _ in 0..PARRY_COOLDOWN_TICKS {
+            game_match.server_entities[1].apply_input(&InputFrame::new(2));
+        }
+        hold_block(&mut game_match, 1);
+        land_hit(&mut game_match);
+        assert!(game_match.server_entities[0].is_stunned());
+    }
+
+    #[test]
+    fn tapping_block_over_and_over_does_not_parry() {
+        let mut game_match = ServerGameMatch::new();
+        let mut guard = MAX_GUARD;
+        for _ in 0..2 {
+            // released for a tick short of the cooldown every time
+            hold_block(&mut game_match, 1);
+            for _ in 1..PARRY_COOLDOWN_TICKS {
+                game_match.server_entities[1].apply_input(&InputFrame::new(2));
+            }
+            hold_block(&mut game_match, 1);
+            land_hit(&mut game_match);
+
+            guard -= GUARD_DAMAGE;
+            assert!(!game_match.server_entities[0].is_stunned());
+            assert_eq!(game_match.server_entities[1].guard, guard);
+        }
+    }
+
+    #[test]
+    fn a_parried_attacker_is_open_until_they_recover() {
+        let mut game_match = ServerGameMatch::new();
+        hold_block(&mut game_match, 1);
+        land_hit(&mut game_match);
+
+        // the attacker can not block the counter
+        let mut input = InputFrame::new(1);
+        input.block = true;
+        game_match.server_entities[0].apply_input(&input);
+        game_match.server_entities[0].step(&game_match.timing);
+        assert!(!game_match.server_entities[0].entity_actions.blocking);
+
+        // and the parrying player lets go of block to hit back
+        game_match.server_entities[1].apply_input(&InputFrame::new(2));
+        game_match.server_entities[0].bound = game_match.server_entities[1].attack_bound;
+        game_match.server_entities[1].entity_actions.damage_check = true;
+        game_match.attack_bound_check(1, 0);
+        assert_eq!(game_match.server_entities[0].hp, MAX_HP - 1);
+
+        for _ in 1..PARRY_STUN_TICKS {
+            game_match.server_entities[0].step(&game_match.timing);
+        }
+        assert!(!game_match.server_entities[0].is_stunned());
+    }
+
+    #[test]
+    fn a_rematch_starts_from_the_first_round() {
+        let mut game_match = best_of(1);
+        knock_out(&mut game_match, 0);
+        assert_eq!(result(&game_match).winner, Some(1));
+
+        game_match.restart_match();
+        assert_eq!(game_match.match_status, MatchStatus::InProgress);
+        assert_eq!(game_match.round_wins, vec![0, 0]);
+        assert_eq!(game_match.rounds_played, 0);
+    }
+
+    // how far player 0 gets walking right for a second at the given tick rate
+    fn walk_for_a_second(tick_rate: u32) -> f32 {
+        let mut game_match = ServerGameMatch::with_rules(MatchRules::new(), tick_rate);
+        let start = game_match.server_entities[0].pos.x;
+        for tick in 1..=tick_rate {
+            let mut input = InputFrame::new(tick);
+            input.right = true;
+            game_match.update_entity(0, &input);
+            game_match.step();
+        }
+
+        game_match.server_entities[0].pos.x - start
+    }
+
+    #[test]
+    fn a_second_of_walking_goes_as_far_at_any_tick_rate() {
+        let distance = walk_for_a_second(TICK_RATE);
+        assert!((distance - MOVE_SPEED * TICK_RATE as f32).abs() < 0.01);
+        assert!((walk_for_a_second(240) - distance).abs() < 0.01);
+        assert!((walk_for_a_second(10) - distance).abs() < 0.01);
+    }
+
+    #[test]
+    fn a_second_of_rest_gives_back_as_much_guard_at_any_tick_rate() {
+        for &tick_rate in &[TICK_RATE, 240, 10] {
+            let timing = Timing::new(tick_rate);
+            let mut entity = ServerEntity::new(0);
+            entity.guard = 0;
+            for _ in 0..tick_rate {
+                entity.step(&timing);
+            }
+            assert_eq!(entity.guard, GUARD_REGEN * TICK_RATE as u8);
+        }
+    }
+}
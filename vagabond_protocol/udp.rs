@@ -0,0 +1,540 @@
+use std::collections::HashMap;
+use std::io::{self, ErrorKind, Read, Write};
+use std::net::{SocketAddr, ToSocketAddrs, UdpSocket};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{channel, Receiver, RecvTimeoutError, Sender};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use serde::Serialize;
+
+use crate::frame::{complete_frame_len, encode, FrameError};
+use crate::packet::{Packet, PacketBody, PacketChannel};
+
+// how long the thread reading a socket waits for a packet before it checks whether anything
+// has to be sent again
+const POLL_TIME: Duration = Duration::from_millis(10);
+
+// a connection that has heard nothing for this long is gone, the other side sends at least
+// a keepalive every second while it is there
+pub const CONNECTION_TIMEOUT: Duration = Duration::from_secs(10);
+
+// once a connection is closed, reliable frames that have not been acknowledged yet get this
+// long to make it to the other side
+const CLOSE_LINGER_TIME: Duration = Duration::from_secs(1);
+
+// the biggest datagram UDP can carry
+const MAX_PACKET_SIZE: usize = 65507;
+
+// a connection to one peer over UDP. reading and writing work like a stream of frames, every
+// frame written goes out reliably and in order, frames written with write_sequenced are sent
+// once and only if nothing newer arrived first
+pub struct UdpConnection {
+    socket: Arc<UdpSocket>, // shared with every other connection on the same port
+    peer: SocketAddr,
+    state: Mutex<State>,
+    arrived: Condvar, // woken up when frames arrive or the connection ends
+    read_timeout: Mutex<Option<Duration>>,
+}
+
+struct State {
+    channel: PacketChannel,
+    incoming: Vec<u8>, // bytes of frames that arrived and have not been read yet
+    pending: Vec<u8>,  // the start of a frame that has not been written completely yet
+    last_received: Instant,
+    closed_at: Option<Instant>, // when we closed our end
+    closed_by_peer: bool,
+    timed_out: bool,
+}
+
+impl UdpConnection {
+    fn new(socket: Arc<UdpSocket>, peer: SocketAddr) -> UdpConnection {
+        UdpConnection {
+            socket,
+            peer,
+            state: Mutex::new(State {
+                channel: PacketChannel::new(),
+                incoming: Vec::new(),
+                pending: Vec::new(),
+                last_received: Instant::now(),
+                closed_at: None,
+                closed_by_peer: false,
+                timed_out: false,
+            }),
+            arrived: Condvar::new(),
+            read_timeout: Mutex::new(None),
+        }
+    }
+
+    // UDP has no handshake of its own, the connection is there as soon as the first frame is
+    // written and the other side finds out whether anyone is listening by waiting for an answer
+    pub fn connect<A: ToSocketAddrs>(addr: A) -> io::Result<Arc<UdpConnection>> {
+        let peer = addr
+            .to_socket_addrs()?
+            .next()
+            .ok_or_else(|| io::Error::new(ErrorKind::InvalidInput, "no address to connect to"))?;
+        let local = if peer.is_ipv4() {
+            "0.0.0.0:0"
+        } else {
+            "[::]:0"
+        };
+
+        let socket = Arc::new(UdpSocket::bind(local)?);
+        socket.set_read_timeout(Some(POLL_TIME))?;
+
+        let connection = Arc::new(UdpConnection::new(socket.clone(), peer));
+        let mut connections = HashMap::new();
+        connections.insert(peer, connection.clone());
+        thread::spawn(move || drive(socket, connections, None));
+
+        Ok(connection)
+    }
+
+    pub fn peer_addr(&self) -> io::Result<SocketAddr> {
+        Ok(self.peer)
+    }
+
+    pub fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        *self.read_timeout.lock().unwrap() = timeout;
+        Ok(())
+    }
+
+    // for messages that are outdated by the next one, if this one is lost it is not sent again
+    pub fn write_sequenced<T: Serialize>(&self, message: &T) -> Result<(), FrameError> {
+        let frame = encode(message)?;
+
+        let mut state = self.state.lock().unwrap();
+        state.check_open()?;
+        let packet = state.channel.next_packet(Some(frame), Instant::now());
+        self.send(&packet)?;
+
+        Ok(())
+    }
+
+    // reading stops right away, anything already written keeps being sent for a moment
+    // before the other side is told we are gone
+    pub fn close(&self) {
+        let mut state = self.state.lock().unwrap();
+        if state.closed_at.is_none() {
+            state.closed_at = Some(Instant::now());
+        }
+        self.arrived.notify_all();
+    }
+
+    fn send(&self, packet: &Packet) -> io::Result<()> {
+        let bytes =
+            bincode::serialize(packet).map_err(|e| io::Error::new(ErrorKind::InvalidData, e))?;
+        self.socket.send_to(&bytes, self.peer)?;
+        Ok(())
+    }
+
+    fn read(&self, buffer: &mut [u8]) -> io::Result<usize> {
+        let deadline = self
+            .read_timeout
+            .lock()
+            .unwrap()
+            .map(|timeout| Instant::now() + timeout);
+        let mut state = self.state.lock().unwrap();
+
+        loop {
+            if !state.incoming.is_empty() {
+                let size = buffer.len().min(state.incoming.len());
+                buffer[..size].copy_from_slice(&state.incoming[..size]);
+                state.incoming.drain(..size);
+                return Ok(size);
+            }
+            if state.closed_at.is_some() || state.closed_by_peer {
+                return Ok(0);
+            }
+            if state.timed_out {
+                return Err(io::Error::new(
+                    ErrorKind::ConnectionAborted,
+                    "the other side stopped answering",
+                ));
+            }
+
+            state = match deadline {
+                Some(deadline) => {
+                    let now = Instant::now();
+                    if now >= deadline {
+                        return Err(io::Error::new(ErrorKind::TimedOut, "read timed out"));
+                    }
+                    self.arrived.wait_timeout(state, deadline - now).unwrap().0
+                }
+                None => self.arrived.wait(state).unwrap(),
+            };
+        }
+    }
+
+    // every complete frame is queued to go out reliably, and is sent right away
+    fn write(&self, buffer: &[u8]) -> io::Result<usize> {
+        let mut state = self.state.lock().unwrap();
+        state.check_open()?;
+        state.pending.extend_from_slice(buffer);
+
+        let mut queued = false;
+        loop {
+            let frame_len = match complete_frame_len(&state.pending) {
+                Ok(Some(frame_len)) => frame_len,
+                Ok(None) => break,
+                Err(e) => return Err(io::Error::new(ErrorKind::InvalidData, e)),
+            };
+            let frame: Vec<u8> = state.pending.drain(..frame_len).collect();
+            state.channel.queue_reliable(frame);
+            queued = true;
+        }
+
+        if queued {
+            let packet = state.channel.next_packet(None, Instant::now());
+            self.send(&packet)?;
+        }
+
+        Ok(buffer.len())
+    }
+
+    // a packet from the peer arrived
+    fn handle(&self, packet: Packet, now: Instant) {
+        let mut state = self.state.lock().unwrap();
+        state.last_received = now;
+
+        if packet.body == PacketBody::Close {
+            state.closed_by_peer = true;
+        } else {
+            for frame in state.channel.receive(packet, now) {
+                state.incoming.extend(frame);
+            }
+        }
+        self.arrived.notify_all();
+    }
+
+    // sends whatever is due, returns false once the connection is over and can be forgotten
+    fn service(&self, now: Instant) -> bool {
+        let mut state = self.state.lock().unwrap();
+        if state.closed_by_peer || state.timed_out {
+            return false;
+        }
+
+        if let Some(closed_at) = state.closed_at {
+            if state.channel.all_acknowledged()
+                || now.duration_since(closed_at) >= CLOSE_LINGER_TIME
+            {
+                let packet = state.channel.close_packet();
+                let _ = self.send(&packet);
+                return false;
+            }
+        }
+
+        if now.duration_since(state.last_received) >= CONNECTION_TIMEOUT {
+            state.timed_out = true;
+            self.arrived.notify_all();
+            return false;
+        }
+
+        if state.channel.needs_packet(now) {
+            let packet = state.channel.next_packet(None, now);
+            // a packet that did not make it is no different from one lost along the way
+            let _ = self.send(&packet);
+        }
+        true
+    }
+}
+
+impl State {
+    fn check_open(&self) -> io::Result<()> {
+        if self.closed_at.is_some() {
+            Err(io::Error::new(
+                ErrorKind::NotConnected,
+                "the connection was closed",
+            ))
+        } else if self.closed_by_peer {
+            Err(io::Error::new(
+                ErrorKind::BrokenPipe,
+                "the other side closed the connection",
+            ))
+        } else if self.timed_out {
+            Err(io::Error::new(
+                ErrorKind::ConnectionAborted,
+                "the other side stopped answering",
+            ))
+        } else {
+            Ok(())
+        }
+    }
+}
+
+// reading and writing only need a shared reference, like with a TcpStream
+impl Read for &UdpConnection {
+    fn read(&mut self, buffer: &mut [u8]) -> io::Result<usize> {
+        UdpConnection::read(self, buffer)
+    }
+}
+
+impl Write for &UdpConnection {
+    fn write(&mut self, buffer: &[u8]) -> io::Result<usize> {
+        UdpConnection::write(self, buffer)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+// hands out a connection for every new peer that sends to the port, like a TcpListener
+pub struct UdpListener {
+    local_addr: SocketAddr,
+    accepted: Receiver<Arc<UdpConnection>>,
+    listening: Arc<AtomicBool>, // new peers are ignored once the listener is gone
+}
+
+impl UdpListener {
+    pub fn bind<A: ToSocketAddrs>(addr: A) -> io::Result<UdpListener> {
+        let socket = Arc::new(UdpSocket::bind(addr)?);
+        socket.set_read_timeout(Some(POLL_TIME))?;
+        let local_addr = socket.local_addr()?;
+
+        let (accepted_sender, accepted) = channel();
+        let listening = Arc::new(AtomicBool::new(true));
+        let accepting = Accepting {
+            sender: accepted_sender,
+            listening: listening.clone(),
+        };
+        thread::spawn(move || drive(socket, HashMap::new(), Some(accepting)));
+
+        Ok(UdpListener {
+            local_addr,
+            accepted,
+            listening,
+        })
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        Ok(self.local_addr)
+    }
+
+    pub fn accept(&self) -> io::Result<Arc<UdpConnection>> {
+        self.accepted
+            .recv()
+            .map_err(|_| io::Error::new(ErrorKind::BrokenPipe, "the socket is gone"))
+    }
+
+    // gives up after the timeout when no new peer turned up
+    pub fn accept_timeout(
+        &self,
+        timeout: Duration,
+    ) -> Result<Arc<UdpConnection>, RecvTimeoutError> {
+        self.accepted.recv_timeout(timeout)
+    }
+
+    pub fn incoming(&self) -> impl Iterator<Item = Arc<UdpConnection>> + '_ {
+        self.accepted.iter()
+    }
+}
+
+impl Drop for UdpListener {
+    fn drop(&mut self) {
+        self.listening.store(false, Ordering::Relaxed);
+    }
+}
+
+struct Accepting {
+    sender: Sender<Arc<UdpConnection>>,
+    listening: Arc<AtomicBool>,
+}
+
+// the only thing reading the socket, hands every packet to the connection it is for and
+// keeps them all sending what is due. runs until nothing is left to listen for
+fn drive(
+    socket: Arc<UdpSocket>,
+    mut connections: HashMap<SocketAddr, Arc<UdpConnection>>,
+    mut accepting: Option<Accepting>,
+) {
+    let mut buffer = vec![0u8; MAX_PACKET_SIZE];
+    let mut last_service = Instant::now();
+
+    loop {
+        if let Some(listener) = &accepting {
+            if !listener.listening.load(Ordering::Relaxed) {
+                accepting = None;
+            }
+        }
+        if accepting.is_none() && connections.is_empty() {
+            break;
+        }
+
+        // errors are left for the timeouts to sort out, on some systems a peer that went away
+        // shows up here as well
+        if let Ok((size, from)) = socket.recv_from(&mut buffer) {
+            // anything that is not one of our packets is noise
+            if let Ok(packet) = bincode::deserialize::<Packet>(&buffer[..size]) {
+                let now = Instant::now();
+                match connections.get(&from) {
+                    Some(connection) => connection.handle(packet, now),
+                    None => match &accepting {
+                        Some(listener) if opens_connection(&packet) => {
+                            let connection = Arc::new(UdpConnection::new(socket.clone(), from));
+                            connection.handle(packet, now);
+                            if listener.sender.send(connection.clone()).is_ok() {
+                                connections.insert(from, connection);
+                            }
+                        }
+                        _ => forget(&socket, from, &packet),
+                    },
+                }
+            }
+        }
+
+        let now = Instant::now();
+        if now.duration_since(last_service) >= POLL_TIME {
+            connections.retain(|_, connection| connection.service(now));
+            last_service = now;
+        }
+    }
+}
+
+// a new peer starts with the first reliable frame, anything else is from a connection we
+// already forgot about
+fn opens_connection(packet: &Packet) -> bool {
+    match &packet.body {
+        PacketBody::Frames { reliable, .. } => match reliable.first() {
+            Some(first) => first.id == 0,
+            None => false,
+        },
+        PacketBody::Close => false,
+    }
+}
+
+// lets a peer we do not know know that, so it does not wait for the timeout to find out
+fn forget(socket: &UdpSocket, peer: SocketAddr, packet: &Packet) {
+    if packet.body == PacketBody::Close {
+        return;
+    }
+
+    let close = PacketChannel::new().close_packet();
+    if let Ok(bytes) = bincode::serialize(&close) {
+        let _ = socket.send_to(&bytes, peer);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::frame::{write_frame, FrameReader};
+
+    // sits between a client and the server and loses every few datagrams going either way
+    fn lossy_proxy(server: SocketAddr, drop_every: usize) -> SocketAddr {
+        let front = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let back = UdpSocket::bind("127.0.0.1:0").unwrap();
+        back.connect(server).unwrap();
+        let addr = front.local_addr().unwrap();
+
+        let client = Arc::new(Mutex::new(None));
+        let front_back = front.try_clone().unwrap();
+        let back_back = back.try_clone().unwrap();
+        let client_back = client.clone();
+
+        // client to server
+        thread::spawn(move || {
+            let mut buffer = vec![0u8; MAX_PACKET_SIZE];
+            for count in 1.. {
+                let (size, from) = front.recv_from(&mut buffer).unwrap();
+                *client.lock().unwrap() = Some(from);
+                if count % drop_every != 0 {
+                    back.send(&buffer[..size]).unwrap();
+                }
+            }
+        });
+
+        // server to client
+        thread::spawn(move || {
+            let mut buffer = vec![0u8; MAX_PACKET_SIZE];
+            for count in 1.. {
+                let size = back_back.recv(&mut buffer).unwrap();
+                let client = client_back.lock().unwrap().unwrap();
+                if count % drop_every != 0 {
+                    front_back.send_to(&buffer[..size], client).unwrap();
+                }
+            }
+        });
+
+        addr
+    }
+
+    fn connect(drop_every: Option<usize>) -> (Arc<UdpConnection>, Arc<UdpConnection>) {
+        let listener = UdpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let addr = match drop_every {
+            Some(drop_every) => lossy_proxy(addr, drop_every),
+            None => addr,
+        };
+
+        // the server only hears about the client once it says something
+        let client = UdpConnection::connect(addr).unwrap();
+        write_frame(&mut &*client, &String::from("hello")).unwrap();
+        let server = listener.accept().unwrap();
+        assert_eq!(
+            FrameReader::new(&*server).read_frame::<String>().unwrap(),
+            "hello"
+        );
+
+        (client, server)
+    }
+
+    #[test]
+    fn frames_make_it_over_a_lossy_link() {
+        let (client, server) = connect(Some(3));
+
+        for number in 0..30u32 {
+            write_frame(&mut &*client, &number).unwrap();
+        }
+        let mut server_reader = FrameReader::new(&*server);
+        for number in 0..30u32 {
+            assert_eq!(server_reader.read_frame::<u32>().unwrap(), number);
+        }
+
+        // sequenced frames can go missing but never arrive out of order
+        for number in 0..100u32 {
+            server.write_sequenced(&number).unwrap();
+            thread::sleep(Duration::from_millis(1));
+        }
+        write_frame(&mut &*server, &u32::MAX).unwrap();
+
+        let mut client_reader = FrameReader::new(&*client);
+        let mut received = Vec::new();
+        loop {
+            let number = client_reader.read_frame::<u32>().unwrap();
+            if number == u32::MAX {
+                break;
+            }
+            received.push(number);
+        }
+        assert!(received.len() < 100);
+        assert!(received.windows(2).all(|pair| pair[0] < pair[1]));
+    }
+
+    #[test]
+    fn read_timeout_keeps_the_connection_usable() {
+        let (client, server) = connect(None);
+        server
+            .set_read_timeout(Some(Duration::from_millis(50)))
+            .unwrap();
+
+        let mut reader = FrameReader::new(&*server);
+        assert!(reader.read_frame::<u32>().unwrap_err().is_timeout());
+
+        write_frame(&mut &*client, &7u32).unwrap();
+        assert_eq!(reader.read_frame::<u32>().unwrap(), 7);
+    }
+
+    #[test]
+    fn closing_one_end_closes_the_other() {
+        let (client, server) = connect(None);
+        client.close();
+
+        match FrameReader::new(&*server).read_frame::<u32>() {
+            Err(FrameError::Closed) => (),
+            other => panic!("expected a closed connection, got {:?}", other),
+        }
+        assert!(write_frame(&mut &*server, &1u32).is_err());
+    }
+}
@@ -0,0 +1,433 @@
+use std::io::BufRead;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use vagabond_protocol::server_data::{EndReason, MatchStatus};
+
+use crate::rooms::Rooms;
+use crate::simulation::Broadcast;
+use crate::slots::SlotState;
+
+// long enough for every client to be told before the server stops letting them in, the
+// lobby only hears from us when it asks for the rooms which it does every second
+const SHUTDOWN_DELAY: Duration = Duration::from_secs(2);
+
+const HELP: &str = "commands:
+  status                   rooms and how their matches are going
+  players                  everyone with a slot, by player id
+  kick <id>                take a player out of their match, ids look like 1.2
+  restart [room]           start the match over in one room, or in all of them
+  set round_time <s>       change the round length everywhere, running rounds included
+  say <message>            show a message to everyone on the server
+  shutdown                 tell everyone and stop the server";
+
+// what the operator can ask the server to do
+#[derive(PartialEq, Debug)]
+pub enum Command {
+    Status,
+    Players,
+    Kick { room_id: u32, player: usize }, // player is numbered from 1 like everywhere else
+    Restart(Option<u32>),                 // None restarts every room
+    SetRoundTime(u16),
+    Say(String),
+    Shutdown,
+    Help,
+}
+
+pub fn parse(line: &str) -> Result<Command, String> {
+    let line = line.trim();
+    let (name, rest) = match line.find(char::is_whitespace) {
+        Some(index) => (&line[..index], line[index..].trim()),
+        None => (line, ""),
+    };
+
+    let command = match name {
+        "status" => Command::Status,
+        "players" => Command::Players,
+        "shutdown" => Command::Shutdown,
+        "help" => Command::Help,
+        "kick" => {
+            let (room_id, player) = parse_player_id(rest)?;
+            return Ok(Command::Kick { room_id, player });
+        }
+        "restart" if rest.is_empty() => return Ok(Command::Restart(None)),
+        "restart" => match rest.parse::<u32>() {
+            Ok(room_id) => return Ok(Command::Restart(Some(room_id))),
+            Err(_) => return Err(format!("restart takes a room id, got {}", rest)),
+        },
+        "set" => return parse_setting(rest),
+        "say" if rest.is_empty() => return Err(String::from("say needs a message")),
+        "say" => return Ok(Command::Say(rest.to_string())),
+        _ => return Err(format!("Unknown command {}, try help", name)),
+    };
+
+    if !rest.is_empty() {
+        return Err(format!("{} does not take anything after it", name));
+    }
+    Ok(command)
+}
+
+// ids are the room and the player in it, 1.2 is the second player in room 1
+fn parse_player_id(id: &str) -> Result<(u32, usize), String> {
+    let mut parts = id.split('.');
+    let parsed = match (parts.next(), parts.next(), parts.next()) {
+        (Some(room_id), Some(player), None) => room_id.parse().ok().zip(player.parse().ok()),
+        _ => None,
+    };
+
+    match parsed {
+        Some((room_id, player)) if player > 0 => Ok((room_id, player)),
+        _ => Err(format!(
+            "Player ids look like 1.2 for player 2 in room 1, got \"{}\"",
+            id
+        )),
+    }
+}
+
+fn parse_setting(setting: &str) -> Result<Command, String> {
+    let mut parts = setting.split_whitespace();
+    match (parts.next(), parts.next(), parts.next()) {
+        (Some("round_time"), Some(seconds), None) => match seconds.parse() {
+            Ok(seconds) => Ok(Command::SetRoundTime(seconds)),
+            Err(_) => Err(format!(
+                "round_time needs a number of seconds, got {}",
+                seconds
+            )),
+        },
+        (Some("round_time"), _, _) => Err(String::from("usage: set round_time <seconds>")),
+        (Some(name), _, _) => Err(format!("{} can not be changed, only round_time can", name)),
+        (None, _, _) => Err(String::from("usage: set round_time <seconds>")),
+    }
+}
+
+// carries out commands against the rooms that are running right now
+pub struct Console {
+    rooms: Arc<Rooms>,
+    notices: Arc<Broadcast<String>>, // every connection is listening
+    shutdown: Arc<AtomicBool>,       // the listeners stop once this is set
+    started: Instant,
+}
+
+impl Console {
+    pub fn new(
+        rooms: Arc<Rooms>,
+        notices: Arc<Broadcast<String>>,
+        shutdown: Arc<AtomicBool>,
+    ) -> Console {
+        Console {
+            rooms,
+            notices,
+            shutdown,
+            started: Instant::now(),
+        }
+    }
+
+    // returns what to tell the operator
+    pub fn execute(&self, command: &Command) -> String {
+        match command {
+            Command::Status => self.status(),
+            Command::Players => self.players(),
+            Command::Kick { room_id, player } => self.kick(*room_id, *player),
+            Command::Restart(room_id) => self.restart(*room_id),
+            Command::SetRoundTime(seconds) => self.set_round_time(*seconds),
+            Command::Say(message) => {
+                let listeners = self.notices.send(message);
+                format!("Sent to {} connections", listeners)
+            }
+            Command::Shutdown => {
+                self.notices
+                    .send(&String::from("The server is shutting down."));
+                String::from("Shutting down")
+            }
+            Command::Help => String::from(HELP),
+        }
+    }
+
+    fn status(&self) -> String {
+        let rules = self.rooms.rules();
+        let mut lines = vec![format!(
+            "up for {}s, new rooms play best of {} {}s rounds with {} health and {} damage",
+            self.started.elapsed().as_secs(),
+            rules.rounds,
+            rules.round_time,
+            rules.max_hp,
+            rules.damage
+        )];
+
+        let summaries = self.rooms.summaries();
+        if summaries.is_empty() {
+            lines.push(String::from("no rooms are open"));
+        }
+        for summary in summaries {
+            let status = match summary.status {
+                MatchStatus::InProgress => String::from("in progress"),
+                MatchStatus::Over(result) => match result.winner {
+                    Some(id) => format!("won by player {}", id + 1),
+                    None => String::from("drawn"),
+                },
+                MatchStatus::Rematch(_) => String::from("voting on a rematch"),
+                MatchStatus::SuddenDeath => String::from("in sudden death"),
+                MatchStatus::RoundOver(_) => String::from("between rounds"),
+            };
+            lines.push(format!(
+                "room {} ({}): {}/{} players, {} spectators, {}",
+                summary.room_id,
+                summary.name,
+                summary.players,
+                summary.max_players,
+                summary.spectators,
+                status
+            ));
+        }
+
+        lines.join("\n")
+    }
+
+    fn players(&self) -> String {
+        let mut lines = Vec::new();
+
+        for room in self.rooms.all() {
+            let slots = room.slots.lock().unwrap();
+            let entities = room.game_match.read().unwrap().get_server_entities();
+            for id in 0..slots.capacity() {
+                let state = match slots.state(id) {
+                    SlotState::Open => continue,
+                    SlotState::Connected => String::from("connected"),
+                    SlotState::Disconnected(since) => {
+                        format!("reconnecting for {}s", since.elapsed().as_secs())
+                    }
+                };
+                let hp = entities.get(id).map_or(0, |entity| entity.get_hp());
+                lines.push(format!(
+                    "{}.{} in {}: {}, {} health",
+                    room.id,
+                    id + 1,
+                    room.name,
+                    state,
+                    hp
+                ));
+            }
+        }
+
+        if lines.is_empty() {
+            return String::from("nobody is playing");
+        }
+        lines.join("\n")
+    }
+
+    // the other player wins, the kicked one is sent back to the lobby
+    fn kick(&self, room_id: u32, player: usize) -> String {
+        let room = match self.rooms.get(room_id) {
+            Some(room) => room,
+            None => return format!("There is no room {}", room_id),
+        };
+
+        let id = player - 1;
+        if !room.slots.lock().unwrap().kick(id) {
+            return format!("Nobody is player {} in room {}", player, room_id);
+        }
+        room.game_match
+            .write()
+            .unwrap()
+            .remove_player(id, EndReason::Forfeit);
+        room.round_timer.lock().unwrap().pause();
+        self.rooms.close_if_empty(room_id);
+
+        format!("Kicked player {} from room {}", player, room_id)
+    }
+
+    fn restart(&self, room_id: Option<u32>) -> String {
+        match room_id {
+            Some(room_id) => match self.rooms.get(room_id) {
+                Some(room) => {
+                    room.restart();
+                    format!("Restarted the match in room {}", room_id)
+                }
+                None => format!("There is no room {}", room_id),
+            },
+            None => {
+                let rooms = self.rooms.all();
+                for room in &rooms {
+                    room.restart();
+                }
+                format!("Restarted {} matches", rooms.len())
+            }
+        }
+    }
+
+    fn set_round_time(&self, seconds: u16) -> String {
+        let mut rules = self.rooms.rules();
+        rules.round_time = seconds;
+        if let Err(reason) = rules.check() {
+            return reason;
+        }
+
+        self.rooms.set_rules(rules);
+        for room in self.rooms.all() {
+            room.set_round_time(seconds);
+        }
+
+        format!("Rounds last {} seconds now", seconds)
+    }
+}
+
+// reads commands from the terminal the server was started in, a server without one just
+// never gets any
+pub fn run<R: BufRead>(console: &Console, input: R) {
+    for line in input.lines() {
+        let line = match line {
+            Ok(line) => line,
+            Err(_) => break,
+        };
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        match parse(&line) {
+            Ok(command) => {
+                println!("{}", console.execute(&command));
+                // nothing is read after this, the server stops once the last player is done
+                if command == Command::Shutdown {
+                    thread::sleep(SHUTDOWN_DELAY);
+                    console.shutdown.store(true, Ordering::Relaxed);
+                    break;
+                }
+            }
+            Err(e) => println!("{}", e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rooms::PLAYERS_PER_ROOM;
+    use vagabond_protocol::constants::TICK_RATE;
+    use vagabond_protocol::handshake::MatchRules;
+
+    fn console() -> (Console, Arc<Rooms>, Arc<Broadcast<String>>) {
+        let rooms = Arc::new(Rooms::new(
+            MatchRules::new(),
+            TICK_RATE,
+            Duration::from_secs(10),
+            PLAYERS_PER_ROOM,
+        ));
+        let notices = Arc::new(Broadcast::new());
+        let shutdown = Arc::new(AtomicBool::new(false));
+        let console = Console::new(rooms.clone(), notices.clone(), shutdown);
+        (console, rooms, notices)
+    }
+
+    #[test]
+    fn commands_are_parsed() {
+        assert_eq!(parse("status"), Ok(Command::Status));
+        assert_eq!(parse("  players  "), Ok(Command::Players));
+        assert_eq!(
+            parse("kick 3.2"),
+            Ok(Command::Kick {
+                room_id: 3,
+                player: 2
+            })
+        );
+        assert_eq!(parse("restart"), Ok(Command::Restart(None)));
+        assert_eq!(parse("restart 4"), Ok(Command::Restart(Some(4))));
+        assert_eq!(parse("set round_time 90"), Ok(Command::SetRoundTime(90)));
+        assert_eq!(
+            parse("say back in  5 minutes"),
+            Ok(Command::Say(String::from("back in  5 minutes")))
+        );
+        assert_eq!(parse("shutdown"), Ok(Command::Shutdown));
+    }
+
+    #[test]
+    fn mistakes_are_explained() {
+        assert!(parse("dance").is_err());
+        assert!(parse("status now").is_err());
+        assert!(parse("kick 3").is_err());
+        assert!(parse("kick 3.0").is_err());
+        assert!(parse("kick a.b").is_err());
+        assert!(parse("restart everything").is_err());
+        assert!(parse("set round_time").is_err());
+        assert!(parse("set round_time long").is_err());
+        assert!(parse("set max_hp 3").is_err());
+        assert!(parse("say").is_err());
+    }
+
+    #[test]
+    fn kicking_frees_the_slot_and_hands_over_the_match() {
+        let (console, rooms, _) = console();
+        let (room, first) = rooms.create(String::from("dojo"), None, None).unwrap();
+        let second = rooms.join(room.id, None).unwrap().1;
+
+        assert!(console.players().contains("1.2 in dojo: connected"));
+        assert_eq!(
+            console.execute(&Command::Kick {
+                room_id: 1,
+                player: 2
+            }),
+            "Kicked player 2 from room 1"
+        );
+
+        let slots = room.slots.lock().unwrap();
+        assert!(!slots.is_current(&second) && slots.was_kicked(&second));
+        assert!(slots.is_current(&first));
+        assert_eq!(slots.players(), 1);
+        drop(slots);
+        match room.game_match.read().unwrap().get_match_status() {
+            MatchStatus::Over(result) if result.winner == Some(0) => (),
+            status => panic!("player 1 should have won, got {:?}", status),
+        }
+
+        // the slot is open now so there is nobody to kick
+        assert_eq!(
+            console.execute(&Command::Kick {
+                room_id: 1,
+                player: 2
+            }),
+            "Nobody is player 2 in room 1"
+        );
+    }
+
+    #[test]
+    fn round_time_changes_every_room() {
+        let (console, rooms, _) = console();
+        let (room, _) = rooms.create(String::from("dojo"), None, None).unwrap();
+
+        assert!(console
+            .execute(&Command::SetRoundTime(5))
+            .contains("between"));
+        assert_eq!(room.rules().round_time, MatchRules::new().round_time);
+
+        console.execute(&Command::SetRoundTime(90));
+        assert_eq!(room.rules().round_time, 90);
+        assert_eq!(room.round_timer.lock().unwrap().remaining_seconds(), 90);
+        assert_eq!(rooms.rules().round_time, 90);
+    }
+
+    #[test]
+    fn notices_reach_every_listener() {
+        let (console, _, notices) = console();
+        let first = notices.subscribe();
+        let second = notices.subscribe();
+        drop(second);
+
+        let reply = console.execute(&Command::Say(String::from("hello")));
+        assert_eq!(reply, "Sent to 1 connections");
+        assert_eq!(first.try_recv(), Ok(String::from("hello")));
+    }
+
+    #[test]
+    fn shutdown_stops_the_listeners_and_the_console() {
+        let (console, _, notices) = console();
+        let listener = notices.subscribe();
+
+        run(&console, "shutdown\nsay still here\n".as_bytes());
+        assert!(console.shutdown.load(Ordering::Relaxed));
+        assert_eq!(
+            listener.try_iter().collect::<Vec<String>>(),
+            vec![String::from("The server is shutting down.")]
+        );
+    }
+}
@@ -0,0 +1,301 @@
+use std::fs;
+use std::net::ToSocketAddrs;
+use std::time::Duration;
+
+use log::LevelFilter;
+use serde::Deserialize;
+
+use vagabond_protocol::constants::{DAMAGE, MAX_HP, ROUNDS, ROUND_TIME, TICK_RATE};
+use vagabond_protocol::handshake::MatchRules;
+
+use crate::rooms::PLAYERS_PER_ROOM;
+
+pub const USAGE: &str = "usage: server [ip_address] [--bind <ip_address>] [--config <file>]
+              [--ws <ip_address>] [--udp] [--tick-rate <ticks per second>]
+              [--round-time <seconds>] [--hp <health>] [--damage <health>] [--rounds <best of>]
+              [--player-slots <count>] [--max-connections <count>] [--grace <seconds>]
+              [--log-level <off|error|warn|info|debug|trace>] [--motd <message>]";
+
+// clients are told the tick rate when they join a room and send their inputs just as often
+pub const TICK_RATE_LIMITS: (u32, u32) = (10, 240);
+
+// the lobby shows the message of the day on a single line
+pub const MAX_MOTD_LENGTH: usize = 80;
+
+// everything the server can be set up with. values come from the config file if there is one,
+// flags on the command line win over the file, and anything left out keeps its default
+#[derive(Deserialize, PartialEq, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub bind: String, // players connect here, given as --bind or just the address
+    pub websocket_address: String, // web clients connect here
+    pub udp: bool,    // also listen for UDP on the bind address
+    pub tick_rate: u32,
+    pub round_time: u16, // in seconds
+    pub max_hp: i8,
+    pub damage: i8,
+    pub rounds: u8,             // a match is best of this many rounds
+    pub player_slots: usize,    // players in matches at once across every room
+    pub max_connections: usize, // anyone connected at once, past this they have to wait
+    pub grace_period: u64,      // seconds a dropped player has to come back before they forfeit
+    pub log_level: String,
+    pub motd: Option<String>,
+}
+
+impl Default for Config {
+    fn default() -> Config {
+        Config {
+            bind: String::from("127.0.0.1:1337"),
+            websocket_address: String::from("127.0.0.1:1338"),
+            udp: false,
+            tick_rate: TICK_RATE,
+            round_time: ROUND_TIME,
+            max_hp: MAX_HP,
+            damage: DAMAGE,
+            rounds: ROUNDS,
+            player_slots: 32,
+            max_connections: 64,
+            grace_period: 10,
+            log_level: String::from("info"),
+            motd: None,
+        }
+    }
+}
+
+impl Config {
+    // reads the config file named with --config, then applies the rest of the flags on top
+    pub fn from_args<I: Iterator<Item = String>>(args: I) -> Result<Config, String> {
+        let args: Vec<String> = args.collect();
+
+        let mut config = match args.iter().position(|arg| arg == "--config") {
+            Some(index) => match args.get(index + 1) {
+                Some(path) => Config::load(path)?,
+                None => return Err(String::from("--config needs the path to a config file")),
+            },
+            None => Config::default(),
+        };
+
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--config" => {
+                    args.next();
+                }
+                "--bind" => config.bind = value(&arg, args.next())?,
+                "--ws" => config.websocket_address = value(&arg, args.next())?,
+                "--udp" => config.udp = true,
+                "--tick-rate" => config.tick_rate = number(&arg, args.next())?,
+                "--round-time" => config.round_time = number(&arg, args.next())?,
+                "--hp" => config.max_hp = number(&arg, args.next())?,
+                "--damage" => config.damage = number(&arg, args.next())?,
+                "--rounds" => config.rounds = number(&arg, args.next())?,
+                "--player-slots" => config.player_slots = number(&arg, args.next())?,
+                "--max-connections" => config.max_connections = number(&arg, args.next())?,
+                "--grace" => config.grace_period = number(&arg, args.next())?,
+                "--log-level" => config.log_level = value(&arg, args.next())?,
+                "--motd" => config.motd = Some(value(&arg, args.next())?),
+                _ if arg.starts_with("--") => return Err(format!("Unknown option {}", arg)),
+                _ => config.bind = arg,
+            }
+        }
+
+        config.check()?;
+        Ok(config)
+    }
+
+    pub fn load(path: &str) -> Result<Config, String> {
+        let text = match fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(e) => return Err(format!("Could not open the config file {}: {}", path, e)),
+        };
+
+        Config::parse(&text).map_err(|e| format!("{} is not a valid config file: {}", path, e))
+    }
+
+    pub fn parse(text: &str) -> Result<Config, toml::de::Error> {
+        toml::from_str(text)
+    }
+
+    // the server would rather not start than run with settings that make no sense
+    pub fn check(&self) -> Result<(), String> {
+        for (name, address) in &[
+            ("bind", &self.bind),
+            ("websocket_address", &self.websocket_address),
+        ] {
+            if address.to_socket_addrs().is_err() {
+                return Err(format!(
+                    "{} has to be an ip address and port like 127.0.0.1:1337, got {}",
+                    name, address
+                ));
+            }
+        }
+
+        let (min_rate, max_rate) = TICK_RATE_LIMITS;
+        if self.tick_rate < min_rate || self.tick_rate > max_rate {
+            return Err(format!(
+                "tick_rate has to be between {} and {} ticks per second, got {}",
+                min_rate, max_rate, self.tick_rate
+            ));
+        }
+
+        if let Err(reason) = self.rules().check() {
+            return Err(format!("Bad match rules: {}", reason));
+        }
+
+        // a match can not start until a room is full
+        if self.player_slots < PLAYERS_PER_ROOM {
+            return Err(format!(
+                "player_slots has to be at least {} so a match can start, got {}",
+                PLAYERS_PER_ROOM, self.player_slots
+            ));
+        }
+        // every player is a connection of their own
+        if self.max_connections < self.player_slots {
+            return Err(format!(
+                "max_connections has to be at least player_slots ({}) so every slot can be \
+                 used, got {}",
+                self.player_slots, self.max_connections
+            ));
+        }
+
+        if self.log_level.parse::<LevelFilter>().is_err() {
+            return Err(format!(
+                "log_level has to be one of off, error, warn, info, debug or trace, got {}",
+                self.log_level
+            ));
+        }
+
+        if let Some(motd) = &self.motd {
+            if motd.chars().count() > MAX_MOTD_LENGTH {
+                return Err(format!(
+                    "motd can be at most {} characters long",
+                    MAX_MOTD_LENGTH
+                ));
+            }
+        }
+
+        Ok(())
+    }
+
+    // what every room plays by unless the player opening it picked their own rules
+    pub fn rules(&self) -> MatchRules {
+        MatchRules {
+            round_time: self.round_time,
+            max_hp: self.max_hp,
+            damage: self.damage,
+            rounds: self.rounds,
+        }
+    }
+
+    pub fn grace_period(&self) -> Duration {
+        Duration::from_secs(self.grace_period)
+    }
+
+    // check makes sure this parses
+    pub fn log_level(&self) -> LevelFilter {
+        self.log_level.parse().unwrap_or(LevelFilter::Info)
+    }
+}
+
+fn value(flag: &str, value: Option<String>) -> Result<String, String> {
+    value.ok_or_else(|| format!("{} needs a value", flag))
+}
+
+fn number<T: std::str::FromStr>(flag: &str, value: Option<String>) -> Result<T, String> {
+    match value.as_ref().map(|value| value.parse::<T>()) {
+        Some(Ok(number)) => Ok(number),
+        Some(Err(_)) => Err(format!("{} needs a number, got {}", flag, value.unwrap())),
+        None => Err(format!("{} needs a number", flag)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(args: &[&str]) -> Result<Config, String> {
+        Config::from_args(args.iter().map(|arg| arg.to_string()))
+    }
+
+    #[test]
+    fn no_args_gives_the_defaults() {
+        assert_eq!(args(&[]).unwrap(), Config::default());
+    }
+
+    #[test]
+    fn config_file_fills_in_what_it_sets() {
+        let config = Config::parse(
+            r#"
+            bind = "0.0.0.0:4000"
+            round_time = 90
+            damage = 2
+            motd = "Welcome to the dojo"
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(config.bind, "0.0.0.0:4000");
+        assert_eq!(config.rules().round_time, 90);
+        assert_eq!(config.rules().damage, 2);
+        assert_eq!(config.motd, Some(String::from("Welcome to the dojo")));
+        // everything else keeps its default
+        assert_eq!(config.max_hp, MAX_HP);
+        assert_eq!(config.tick_rate, TICK_RATE);
+    }
+
+    #[test]
+    fn config_file_mistakes_are_reported() {
+        assert!(Config::parse("round_tme = 90").is_err());
+        assert!(Config::parse("round_time = \"long\"").is_err());
+    }
+
+    #[test]
+    fn flags_win_over_the_config_file() {
+        // tests run in parallel, and so can more than one cargo test
+        let path = std::env::temp_dir().join(format!(
+            "vagabond_flags_win_{}_{:?}.toml",
+            std::process::id(),
+            std::thread::current().id()
+        ));
+        fs::write(&path, "round_time = 90\nmax_hp = 8\n").unwrap();
+
+        let config = args(&[
+            "--round-time",
+            "30",
+            "--config",
+            path.to_str().unwrap(),
+            "10.0.0.1:1337",
+        ])
+        .unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(config.round_time, 30);
+        assert_eq!(config.max_hp, 8);
+        assert_eq!(config.bind, "10.0.0.1:1337");
+    }
+
+    #[test]
+    fn bind_address_can_be_a_flag_too() {
+        let config = args(&["--bind", "0.0.0.0:4000", "--player-slots", "8"]).unwrap();
+        assert_eq!(config.bind, "0.0.0.0:4000");
+        assert_eq!(config.player_slots, 8);
+        assert_eq!(config.max_connections, Config::default().max_connections);
+    }
+
+    #[test]
+    fn bad_values_are_refused() {
+        assert!(args(&["--round-time", "5"]).is_err());
+        assert!(args(&["--hp", "0"]).is_err());
+        assert!(args(&["--damage", "-1"]).is_err());
+        assert!(args(&["--rounds", "4"]).is_err());
+        assert!(args(&["--tick-rate", "1000"]).is_err());
+        assert!(args(&["--player-slots", "1"]).is_err());
+        assert!(args(&["--player-slots", "100", "--max-connections", "50"]).is_err());
+        assert!(args(&["--bind"]).is_err());
+        assert!(args(&["--log-level", "loud"]).is_err());
+        assert!(args(&["not an address"]).is_err());
+        assert!(args(&["--grace"]).is_err());
+        assert!(args(&["--hp", "lots"]).is_err());
+        assert!(args(&["--colour"]).is_err());
+    }
+}
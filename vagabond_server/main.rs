@@ -1,158 +1,1084 @@
 use std::env;
-use std::io::{Read, Write};
+use std::io;
 use std::net::{Shutdown, TcpListener, TcpStream};
-use std::sync::{Arc, RwLock};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{Receiver, RecvTimeoutError};
+use std::sync::{Arc, Mutex};
 use std::thread;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
-use bincode;
+use log::{info, warn};
 
-mod animate;
-mod geometry;
-mod server_data;
+use vagabond_protocol::frame::{write_frame, FrameError, FrameReader};
+use vagabond_protocol::handshake::{
+    check_version, Hello, HelloResponse, Role, ServerInfo, Welcome, PROTOCOL_VERSION,
+};
+use vagabond_protocol::lobby::{LobbyRequest, LobbyResponse};
+use vagabond_protocol::message::{ClientMessage, ServerMessage, Snapshot};
+use vagabond_protocol::server_data::{EndReason, MatchStatus, RematchStatus, ServerGameMatch};
+use vagabond_protocol::udp::UdpListener;
 
-use animate::Animator;
-use server_data::{MatchStatus, RematchStatus, ServerGameMatch};
+mod admin;
+mod config;
+mod logger;
+mod matchmaking;
+mod rooms;
+mod round_timer;
+mod simulation;
+mod slots;
+mod thread_pool;
+mod transport;
 
-pub struct ThreadPool {
-    threads: Vec<thread::JoinHandle<()>>,
-}
+use admin::Console;
+use config::Config;
+use matchmaking::{Matchmaker, QueueEvent};
+use rooms::{Room, Rooms};
+use simulation::Broadcast;
+use slots::Seat;
+use thread_pool::ThreadPool;
+use transport::ClientSocket;
+
+const SERVER_NAME: &str = "Vagabond Dojo";
+
+// how long a client can go without sending anything before we consider it gone,
+// clients send input every tick so this only happens when something is wrong
+const CLIENT_TIMEOUT: Duration = Duration::from_secs(5);
+
+// how often a player in the queue is checked on, and told their place in it
+const QUEUE_POLL_TIME: Duration = Duration::from_millis(100);
+const QUEUE_UPDATE_TIME: Duration = Duration::from_secs(1);
 
-pub struct Worker; // will implement later
+// how often the listeners check whether the server is shutting down
+const ACCEPT_POLL_TIME: Duration = Duration::from_millis(100);
+
+// every connection has a thread to itself for as long as it stays, past max_connections new ones
+// wait for a thread to free up, and past the backlog as well they are turned away
+const CONNECTION_BACKLOG: usize = 8;
 
 fn main() {
-    // command line args
-    let args: Vec<String> = env::args().collect();
-    let ip_address = match args.len() {
-        2 => args[1].clone(),
-        _ => String::from("127.0.0.1:1337"),
+    // command line args, see config::USAGE
+    let config = match Config::from_args(env::args().skip(1)) {
+        Ok(config) => config,
+        Err(e) => {
+            println!("{}", e);
+            println!("{}", config::USAGE);
+            return;
+        }
     };
+    logger::init(config.log_level());
 
-    // initialize ThreadPool
-    let mut thread_pool = ThreadPool {
-        threads: Vec::new(),
-    };
+    if let Some(motd) = &config.motd {
+        info!("Message of the day: {}", motd);
+    }
 
-    // create initial match struct and id counter
-    let game_match = Arc::new(RwLock::new(ServerGameMatch::new()));
-    // let id_counter = Arc::new(RwLock::new(0usize));
-    let clock_timer = Arc::new(RwLock::new(Animator::new(60, Duration::from_secs(1))));
+    // initialize ThreadPool, shared by both listeners so the limit covers every connection
+    let thread_pool = Arc::new(Mutex::new(ThreadPool::new(
+        config.max_connections,
+        CONNECTION_BACKLOG,
+    )));
 
-    // clone so we can move into closure
-    let game_match_inner = game_match.clone();
+    // every match on the server lives in its own room, players that did not ask for
+    // a room wait in the queue for someone to play against. notices from the console
+    // go out to every connection
+    let server = Arc::new(Server {
+        rooms: Arc::new(Rooms::new(
+            config.rules(),
+            config.tick_rate,
+            config.grace_period(),
+            config.player_slots,
+        )),
+        matchmaker: Matchmaker::new(),
+        info: ServerInfo {
+            name: String::from(SERVER_NAME),
+            motd: config.motd.clone(),
+        },
+        notices: Arc::new(Broadcast::new()),
+        shutdown: Arc::new(AtomicBool::new(false)),
+    });
+
+    // whoever started the server can look after it from the same terminal
+    {
+        let console = Console::new(
+            server.rooms.clone(),
+            server.notices.clone(),
+            server.shutdown.clone(),
+        );
+        thread::spawn(move || admin::run(&console, io::stdin().lock()));
+    }
 
     // bind ip address to server listener
-    let addr = ip_address;
-    let listener = TcpListener::bind(addr).unwrap();
+    let addr = config.bind.clone();
+    let listener = TcpListener::bind(&addr).unwrap();
+
+    // browsers can not open a raw TCP connection, so they get a listener of their own that
+    // leads to the same rooms
+    let websocket_listener = TcpListener::bind(&config.websocket_address).unwrap();
+    info!(
+        "Accepting websocket connections on {}",
+        config.websocket_address
+    );
+    let mut listeners = Vec::new();
+    {
+        let thread_pool = thread_pool.clone();
+        let server = server.clone();
+        listeners.push(thread::spawn(move || {
+            accept_connections(
+                websocket_listener,
+                Transport::WebSocket,
+                &thread_pool,
+                &server,
+            )
+        }));
+    }
+
+    // clients that would rather lose a snapshot than wait for it can use UDP, on the same
+    // port as TCP
+    if config.udp {
+        let udp_listener = UdpListener::bind(&addr).unwrap();
+        info!("Accepting UDP connections on {}", addr);
+
+        let thread_pool = thread_pool.clone();
+        let server = server.clone();
+        listeners.push(thread::spawn(move || {
+            accept_udp_connections(udp_listener, &thread_pool, &server)
+        }));
+    }
+
+    info!("Accepting connections on {}", addr);
+    accept_connections(listener, Transport::Tcp, &thread_pool, &server);
+
+    // every listener stops once the console shuts the server down
+    for listener in listeners {
+        let _ = listener.join();
+    }
+    shut_down(&server, thread_pool);
+}
+
+// nobody new gets in by now. closing every room sends the players still in a match back with
+// the reason, and everyone on their way out of the lobby or the queue is let go, so the pool
+// only has to wait for them to hang up
+fn shut_down(server: &Server, thread_pool: Arc<Mutex<ThreadPool>>) {
+    info!("No longer accepting connections, closing every room");
+    server.rooms.close_all();
+    drop(thread_pool);
+    info!("Every connection is closed");
+}
+
+// what every connection shares with the rest of the server
+struct Server {
+    rooms: Arc<Rooms>,
+    matchmaker: Matchmaker,
+    info: ServerInfo,
+    notices: Arc<Broadcast<String>>, // from the console, every connection is listening
+    shutdown: Arc<AtomicBool>,       // set by the console, nobody new gets in after
+}
+
+impl Server {
+    fn is_shutting_down(&self) -> bool {
+        self.shutdown.load(Ordering::Relaxed)
+    }
+}
+
+// what the clients on a listener talk over
+#[derive(Clone, Copy)]
+enum Transport {
+    Tcp,
+    WebSocket,
+}
+
+fn accept_connections(
+    listener: TcpListener,
+    transport: Transport,
+    thread_pool: &Mutex<ThreadPool>,
+    server: &Arc<Server>,
+) {
+    // the listener never blocks so it can notice the server shutting down
+    listener.set_nonblocking(true).unwrap();
 
     // handle each connection to server
     for stream in listener.incoming() {
+        if server.is_shutting_down() {
+            break;
+        }
+
         match stream {
             Ok(stream) => {
-                println!("New connection: {}", stream.peer_addr().unwrap());
+                // connections only get taken out of blocking mode with the listener on
+                // some systems
+                if let Err(e) = stream.set_nonblocking(false) {
+                    warn!("Could not accept a connection: {}", e);
+                    continue;
+                }
+                let server = server.clone();
 
-                let game_match = game_match_inner.clone();
-                // let id_counter_inner = id_counter.clone();
-                let clock_timer_inner = clock_timer.clone();
+                // keep a handle to the socket so the client can be told when there is no room
+                let full_stream = stream.try_clone();
 
-                let connection_id = thread_pool.threads.len();
-                println!("{}", &connection_id);
-                if connection_id == 2 {
-                    stream.shutdown(Shutdown::Both).unwrap();
-                } else {
-                    // spawn thread so we can accept more connections
-                    thread_pool.threads.push(thread::spawn(move || {
-                        // connection succeeded
-                        handle_client(stream, game_match, clock_timer_inner, connection_id)
-                    }));
+                // hand the connection to the pool so we can accept more connections
+                let handled = thread_pool.lock().unwrap().execute(move || {
+                    // connection succeeded
+                    if let Some(socket) = open_socket(stream, transport) {
+                        handle_client(socket, &server)
+                    }
+                });
+                if handled.is_err() {
+                    if let Some(socket) = full_stream
+                        .ok()
+                        .and_then(|stream| open_socket(stream, transport))
+                    {
+                        turn_away(socket);
+                    }
                 }
             }
+            Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => {
+                thread::sleep(ACCEPT_POLL_TIME);
+            }
             Err(e) => {
-                println!("Error: {}", e);
+                warn!("Could not accept a connection: {}", e);
                 /* connection failed */
             }
         }
     }
 }
 
-fn handle_client(
-    mut socket: TcpStream,
-    game_match: Arc<RwLock<ServerGameMatch>>,
-    clock_timer: Arc<RwLock<Animator>>,
-    id: usize,
+// UDP has no connections of its own, the listener hands us one for every new client
+fn accept_udp_connections(
+    listener: UdpListener,
+    thread_pool: &Mutex<ThreadPool>,
+    server: &Arc<Server>,
 ) {
-    let mut data = [0u8; 1024];
+    while !server.is_shutting_down() {
+        let connection = match listener.accept_timeout(ACCEPT_POLL_TIME) {
+            Ok(connection) => connection,
+            Err(RecvTimeoutError::Timeout) => continue,
+            Err(RecvTimeoutError::Disconnected) => break,
+        };
+        let server = server.clone();
+        let full_connection = connection.clone();
 
-    socket
-        .write_all(&id.to_string().as_bytes())
-        .expect("Unable to write id value to stream");
-    socket.flush().expect("Unable to flush stream");
+        let handled = thread_pool
+            .lock()
+            .unwrap()
+            .execute(move || handle_client(ClientSocket::Udp(connection), &server));
+        if handled.is_err() {
+            turn_away(ClientSocket::Udp(full_connection));
+        }
+    }
+}
 
-    if id == 1 {
-        clock_timer.write().unwrap().paused = false;
+// a websocket has to be set up before anything can be said over it
+fn open_socket(stream: TcpStream, transport: Transport) -> Option<ClientSocket> {
+    match transport {
+        Transport::Tcp => Some(ClientSocket::Tcp(stream)),
+        Transport::WebSocket => match ClientSocket::accept_websocket(stream) {
+            Ok(socket) => Some(socket),
+            Err(e) => {
+                warn!("{}", e);
+                None
+            }
+        },
     }
+}
 
-    // establish connection loop
-    'socket_loop: while match socket.read(&mut data) {
-        Ok(_) => true,
-        Err(_) => {
-            println!(
-                "An error occurred, terminating connection with {}",
-                socket.peer_addr().unwrap()
+// the hello is never read, the client finds the answer when it waits for one
+fn turn_away(socket: ClientSocket) {
+    if let Ok(peer) = socket.peer_addr() {
+        warn!("Turning away {}, every connection is taken", peer);
+    }
+    let mut writer = &socket;
+
+    let reason = String::from("The server is full, try again later.");
+    let _ = write_frame(&mut writer, &HelloResponse::Rejected(reason));
+    let _ = socket.shutdown(Shutdown::Both);
+}
+
+fn handle_client(socket: ClientSocket, server: &Server) {
+    // the address is only used for logging so a socket that is already gone is not a problem
+    let peer = match socket.peer_addr() {
+        Ok(addr) => addr.to_string(),
+        Err(_) => String::from("unknown address"),
+    };
+    info!("New connection: {}", peer);
+
+    // a client that stops talking to us counts as disconnected
+    if let Err(e) = socket.set_read_timeout(Some(CLIENT_TIMEOUT)) {
+        warn!("Could not set a timeout for {}: {}", peer, e);
+        return;
+    }
+
+    // reading and writing only need a shared reference to the socket
+    let mut reader = FrameReader::new(&socket);
+    let mut writer = &socket;
+
+    // the client has to introduce itself before anything else happens
+    let hello: Hello = match reader.read_frame() {
+        Ok(hello) => hello,
+        Err(e) => {
+            warn!("{} did not send a valid hello: {}", peer, e);
+            if let FrameError::Serialize(_) = e {
+                // a hello we can not read most likely comes from a different version of the game
+                let reason = format!(
+                    "Could not read the hello message, the server uses protocol version {}.",
+                    PROTOCOL_VERSION
+                );
+                let _ = write_frame(&mut writer, &HelloResponse::Rejected(reason));
+            }
+            let _ = socket.shutdown(Shutdown::Both);
+            return;
+        }
+    };
+
+    if let Err(reason) = check_version(&hello) {
+        warn!("Rejecting {}: {}", peer, reason);
+        // the client is going away either way so a failed write does not matter
+        let _ = write_frame(&mut writer, &HelloResponse::Rejected(reason));
+        let _ = socket.shutdown(Shutdown::Both);
+        return;
+    }
+
+    // a player coming back from a dropped connection goes straight back into their match,
+    // everyone else starts out in the lobby
+    let mut in_room = None;
+    match hello.resume {
+        Some(resume) => match server.rooms.resume(resume.room_id, resume.session_token) {
+            Ok((room, seat)) => {
+                info!(
+                    "{} is back as player {} in room {}",
+                    peer,
+                    seat.id + 1,
+                    room.id
+                );
+                // a failed write shows up as soon as the match starts reading
+                let response = HelloResponse::Welcome(welcome(&room, Some(&seat)));
+                let _ = write_frame(&mut writer, &response);
+                in_room = Some(Place::Player(room, seat));
+            }
+            Err(reason) => {
+                warn!("Rejecting {}: {}", peer, reason);
+                let _ = write_frame(&mut writer, &HelloResponse::Rejected(reason));
+                let _ = socket.shutdown(Shutdown::Both);
+                return;
+            }
+        },
+        None => {
+            let response = HelloResponse::Lobby(server.info.clone());
+            if let Err(e) = write_frame(&mut writer, &response) {
+                warn!("Could not let {} into the lobby: {}", peer, e);
+                let _ = socket.shutdown(Shutdown::Both);
+                return;
+            }
+        }
+    }
+
+    // players can go from the lobby to a room and back as often as they like
+    loop {
+        let place = match in_room.take() {
+            Some(in_room) => in_room,
+            None => match lobby(&socket, &mut reader, server, &peer) {
+                Some(joined) => joined,
+                None => break,
+            },
+        };
+
+        match place {
+            Place::Player(room, seat) => {
+                // the clock starts once both players are here, and picks up where it left off
+                // when one of them comes back
+                if room.slots.lock().unwrap().all_connected() {
+                    room.round_timer.lock().unwrap().resume();
+                    room.game_match.write().unwrap().start();
+                }
+
+                match play(&socket, &mut reader, server, &room, &seat) {
+                    Exit::LeftRoom => leave_room(&server.rooms, &room, &seat),
+                    Exit::Disconnected => {
+                        let _ = socket.shutdown(Shutdown::Both);
+                        disconnect(&room, &seat);
+                        return;
+                    }
+                }
+            }
+            Place::Spectator(room) => {
+                let exit = watch(&socket, &mut reader, &room, &server.notices, &peer);
+                room.stop_spectating();
+                info!("{} stopped watching room {}", peer, room.id);
+                if let Exit::Disconnected = exit {
+                    break;
+                }
+            }
+        }
+    }
+
+    let _ = socket.shutdown(Shutdown::Both);
+}
+
+// where a connection ended up after the lobby
+enum Place {
+    Player(Arc<Room>, Seat),
+    Spectator(Arc<Room>),
+}
+
+// everything the client needs to know about the room they are now in, spectators have no seat
+fn welcome(room: &Room, seat: Option<&Seat>) -> Welcome {
+    let (role, player_id, session_token) = match seat {
+        Some(seat) => (Role::Player, seat.id, seat.token),
+        None => (Role::Spectator, 0, 0),
+    };
+
+    Welcome {
+        protocol_version: PROTOCOL_VERSION,
+        room_id: room.id,
+        role,
+        player_id,
+        session_token,
+        rules: room.rules(),
+        tick_rate: room.tick_rate,
+    }
+}
+
+// the player looks around until they create or join a room, find an opponent in the queue
+// or start watching a match, returns None once their connection is gone
+fn lobby(
+    socket: &ClientSocket,
+    reader: &mut FrameReader<&ClientSocket>,
+    server: &Server,
+    peer: &str,
+) -> Option<Place> {
+    let mut writer = socket;
+    let rooms = &server.rooms;
+    let notices = server.notices.subscribe();
+
+    // a player sent back from a room closed for the shutdown was told why on the way out
+    if server.is_shutting_down() {
+        return None;
+    }
+
+    loop {
+        let request = match reader.read_frame() {
+            Ok(ClientMessage::Lobby(request)) => request,
+            Ok(_) => continue, // inputs still on their way from a match the player just left
+            Err(e) => {
+                if e.is_timeout() {
+                    warn!("{} timed out in the lobby", peer);
+                } else {
+                    info!("{} left the lobby ({})", peer, e);
+                }
+                return None;
+            }
+        };
+
+        // the lobby asks for the rooms every second, which is soon enough for a notice
+        if let Err(e) = send_notices(socket, &notices) {
+            warn!("Could not send a notice to {}: {}", peer, e);
+            return None;
+        }
+        // nobody new gets into a match once the server is shutting down, they have been
+        // told why by now
+        if server.is_shutting_down() {
+            info!(
+                "Closing the connection to {}, the server is shutting down",
+                peer
             );
-            socket.shutdown(Shutdown::Both).unwrap();
-            false
+            return None;
         }
-    } {
-        // Deserialize the data to a ServerGameMatch struct
-        let match_details: ServerGameMatch = bincode::deserialize(&data).unwrap();
 
-        // update the player's data on the server
+        let joined = match request {
+            LobbyRequest::ListRooms => {
+                let response = ServerMessage::Lobby(LobbyResponse::Rooms(rooms.summaries()));
+                if let Err(e) = write_frame(&mut writer, &response) {
+                    warn!("Could not send the rooms to {}: {}", peer, e);
+                    return None;
+                }
+                continue;
+            }
+            LobbyRequest::CreateRoom {
+                name,
+                password,
+                rules,
+            } => rooms.create(name, password, rules).map(as_player),
+            LobbyRequest::JoinRoom { room_id, password } => {
+                rooms.join(room_id, password).map(as_player)
+            }
+            LobbyRequest::Spectate { room_id, password } => {
+                rooms.spectate(room_id, password).map(Place::Spectator)
+            }
+            LobbyRequest::QuickMatch => {
+                match wait_for_match(socket, reader, server, &notices, peer) {
+                    Queued::Matched(room, seat) => Ok(Place::Player(room, seat)),
+                    Queued::Cancelled => continue,
+                    Queued::Gone => return None,
+                }
+            }
+            LobbyRequest::LeaveRoom => continue, // already out of every room
+        };
+
+        match joined {
+            Ok(place) => {
+                let welcome = match &place {
+                    Place::Player(room, seat) => {
+                        info!("{} is player {} in room {}", peer, seat.id + 1, room.id);
+                        welcome(room, Some(seat))
+                    }
+                    Place::Spectator(room) => {
+                        info!("{} is watching room {}", peer, room.id);
+                        welcome(room, None)
+                    }
+                };
+                // a failed write shows up as soon as the match starts reading
+                let response = LobbyResponse::Joined(welcome);
+                let _ = write_frame(&mut writer, &ServerMessage::Lobby(response));
+                return Some(place);
+            }
+            Err(reason) => {
+                info!("Turning down {}: {}", peer, reason);
+                let response = ServerMessage::Lobby(LobbyResponse::Rejected(reason));
+                if let Err(e) = write_frame(&mut writer, &response) {
+                    warn!("Could not answer {}: {}", peer, e);
+                    return None;
+                }
+            }
+        }
+    }
+}
+
+fn as_player((room, seat): (Arc<Room>, Seat)) -> Place {
+    Place::Player(room, seat)
+}
+
+enum Queued {
+    Matched(Arc<Room>, Seat),
+    Cancelled, // back in the lobby
+    Gone,      // the connection dropped
+}
+
+// keeps the player posted on their place in the queue until they are paired or give up
+fn wait_for_match(
+    socket: &ClientSocket,
+    reader: &mut FrameReader<&ClientSocket>,
+    server: &Server,
+    notices: &Receiver<String>,
+    peer: &str,
+) -> Queued {
+    let mut writer = socket;
+    let rooms = &server.rooms;
+    let matchmaker = &server.matchmaker;
+    let (ticket, events) = matchmaker.enter(rooms);
+    info!("{} is waiting for an opponent", peer);
+
+    // reading must not hold up telling the player about their place in the queue
+    let left = if let Err(e) = socket.set_read_timeout(Some(QUEUE_POLL_TIME)) {
+        warn!("Could not set a timeout for {}: {}", peer, e);
+        Queued::Gone
+    } else {
+        let mut position = 0;
+        let mut last_update: Option<Instant> = None;
+
+        'queue_loop: loop {
+            matchmaker.retry(rooms);
+            for event in events.try_iter() {
+                match event {
+                    QueueEvent::Position(new_position) => {
+                        position = new_position;
+                        last_update = None;
+                    }
+                    QueueEvent::Matched(room, seat) => {
+                        break 'queue_loop Queued::Matched(room, seat)
+                    }
+                }
+            }
+
+            // the update doubles as a sign of life so the client does not give up on us
+            let update_due = match last_update {
+                Some(sent) => sent.elapsed() >= QUEUE_UPDATE_TIME,
+                None => true,
+            };
+            if update_due {
+                let update = ServerMessage::Lobby(LobbyResponse::Waiting(position));
+                if let Err(e) = write_frame(&mut writer, &update) {
+                    warn!("{} could not be reached in the queue: {}", peer, e);
+                    break Queued::Gone;
+                }
+                last_update = Some(Instant::now());
+            }
+            if let Err(e) = send_notices(socket, notices) {
+                warn!("{} could not be reached in the queue: {}", peer, e);
+                break Queued::Gone;
+            }
+            // there will be no one new to play against
+            if server.is_shutting_down() {
+                info!(
+                    "Taking {} out of the queue, the server is shutting down",
+                    peer
+                );
+                break Queued::Cancelled;
+            }
+
+            match reader.read_frame() {
+                Ok(ClientMessage::Lobby(LobbyRequest::LeaveRoom)) => {
+                    info!("{} left the queue", peer);
+                    break Queued::Cancelled;
+                }
+                Ok(ClientMessage::Lobby(LobbyRequest::ListRooms)) => {
+                    let response = ServerMessage::Lobby(LobbyResponse::Rooms(rooms.summaries()));
+                    if let Err(e) = write_frame(&mut writer, &response) {
+                        warn!("Could not send the rooms to {}: {}", peer, e);
+                        break Queued::Gone;
+                    }
+                }
+                Ok(_) => (), // can not do anything else while waiting
+                Err(e) => {
+                    if !e.is_timeout() {
+                        info!("{} went away while waiting ({})", peer, e);
+                        break Queued::Gone;
+                    }
+                }
+            }
+        }
+    };
+
+    // from here on the player has to keep talking to us again
+    if let Err(e) = socket.set_read_timeout(Some(CLIENT_TIMEOUT)) {
+        warn!("Could not set a timeout for {}: {}", peer, e);
+    }
+
+    if let Queued::Matched(_, _) = left {
+        return left;
+    }
+    if matchmaker.leave(ticket) {
+        return left;
+    }
 
-        // clock updating
-        match &game_match.read().unwrap().match_status {
-            MatchStatus::InProgress => clock_timer.write().unwrap().update(),
-            _ => (),
+    // the matchmaker paired them before they could leave, the match sorts out the rest
+    for event in events.try_iter() {
+        if let QueueEvent::Matched(room, seat) = event {
+            return Queued::Matched(room, seat);
         }
+    }
+    left
+}
+
+// how the player got out of the match
+enum Exit {
+    LeftRoom,     // went back to the lobby
+    Disconnected, // quit or lost their connection
+}
 
-        let current_time = clock_timer.read().unwrap().current_frame();
-        game_match.write().unwrap().update_clock(current_time);
+// the player is in the match until they leave the room, quit or their connection drops
+fn play(
+    socket: &ClientSocket,
+    reader: &mut FrameReader<&ClientSocket>,
+    server: &Server,
+    room: &Room,
+    seat: &Seat,
+) -> Exit {
+    let mut writer = socket;
+    let id = seat.id;
+    let game_match = &room.game_match;
+    let round_timer = &room.round_timer;
+    let mut exit = Exit::Disconnected;
+    let mut kicked = false;
+    let mut declined = false; // this player turned down the rematch
+    let mut closed = false; // the other one did, or the room went away some other way
+
+    // snapshots go out between reads, which never wait longer than a tick so a client
+    // that sends nothing for a moment still gets every snapshot on time
+    let mut snapshots = SnapshotWriter::new(room, &server.notices, Some(id));
+    if let Err(e) = socket.set_read_timeout(Some(tick_duration(room))) {
+        warn!("Could not start sending to player {}: {}", id + 1, e);
+        return exit;
+    }
+    let mut last_heard = Instant::now();
+
+    // establish connection loop
+    loop {
+        if !room.is_open() {
+            closed = true;
+            exit = Exit::LeftRoom;
+            break;
+        }
+
+        if let Err(e) = snapshots.write_pending(socket) {
+            warn!("Could not send match to player {}: {}", id + 1, e);
+            break;
+        }
+
+        // read a whole frame and deserialize it to a ClientMessage
+        let message: ClientMessage = match reader.read_frame() {
+            Ok(message) => message,
+            Err(e) => {
+                if e.is_timeout() {
+                    if last_heard.elapsed() < CLIENT_TIMEOUT {
+                        continue;
+                    }
+                    warn!("Player {} timed out", id + 1);
+                } else {
+                    warn!(
+                        "An error occurred ({}), terminating connection with player {}",
+                        e,
+                        id + 1
+                    );
+                }
+                break;
+            }
+        };
+        last_heard = Instant::now();
+
+        // the player reconnected and this connection has been replaced, or the server
+        // took their slot away
+        let slots = room.slots.lock().unwrap();
+        if !slots.is_current(seat) {
+            if slots.was_kicked(seat) {
+                info!("Player {} was kicked from room {}", id + 1, room.id);
+                kicked = true;
+            } else {
+                info!("Player {} is using a newer connection", id + 1);
+            }
+            break;
+        }
+        drop(slots);
+
+        // update the player's data on the server
 
         // check if the players want to play again if the match is over
         {
             let mut g_match = game_match.write().unwrap();
             let mut num_players = 0;
             for entity in &g_match.server_entities {
-                match entity.get_redo_status_as_ref() {
-                    MatchStatus::Rematch(status) => {
-                        match status {
-                            RematchStatus::Yes => num_players += 1,
-                            RematchStatus::No => break 'socket_loop,
-                            _ => (),
-                        }
-                    },
-                    _ => (),
+                if let MatchStatus::Rematch(RematchStatus::Yes) = entity.get_redo_status_as_ref() {
+                    num_players += 1;
                 }
             }
 
             if num_players >= 2 {
-                &mut clock_timer.write().unwrap().end();
+                round_timer.lock().unwrap().restart();
                 g_match.restart_match();
             }
         }
 
-        // update player data
-        game_match
-            .write()
-            .unwrap()
-            .update_entity(id, match_details.server_entities[id].clone());
+        // update player data, the client only ever tells us which buttons it is pressing
+        match message {
+            ClientMessage::Input(input) => game_match.write().unwrap().update_entity(id, &input),
+            // there is nothing left to play once either player says no, only the room has to go
+            ClientMessage::Rematch(RematchStatus::No) => {
+                if let MatchStatus::Over(_) = game_match.read().unwrap().match_status {
+                    info!(
+                        "Player {} turned down a rematch in room {}",
+                        id + 1,
+                        room.id
+                    );
+                    declined = true;
+                    exit = Exit::LeftRoom;
+                    break;
+                }
+            }
+            ClientMessage::Rematch(status) => game_match
+                .write()
+                .unwrap()
+                .set_redo_status(id, MatchStatus::Rematch(status)),
+            ClientMessage::Lobby(LobbyRequest::LeaveRoom) => {
+                exit = Exit::LeftRoom;
+                break;
+            }
+            ClientMessage::Lobby(_) => (), // the player has to leave the room first
+        }
+    }
 
-        // Serialize the data on server and then send it back to the client
-        let serialized_data: Vec<u8> = bincode::serialize(&(*game_match.write().unwrap())).unwrap();
-        socket.write(&serialized_data).unwrap();
+    // the lobby waits on the player again
+    if let Err(e) = socket.set_read_timeout(Some(CLIENT_TIMEOUT)) {
+        warn!("Could not set a timeout for player {}: {}", id + 1, e);
+    }
 
-        socket.flush().expect("Could not flush stream");
+    // the other player is sent back to the lobby when the room closes
+    if declined {
+        server.rooms.close(room.id);
     }
 
-    socket.shutdown(Shutdown::Both).unwrap();
+    // a kicked player goes back to the lobby and is told why, so is everyone in a room that
+    // is not there anymore
+    let reason = if kicked {
+        Some(String::from(
+            "You were removed from the match by the server.",
+        ))
+    } else if declined {
+        Some(format!("The match is over, {} was closed.", room.name))
+    } else if closed && server.is_shutting_down() {
+        Some(format!(
+            "The server is shutting down, {} was closed.",
+            room.name
+        ))
+    } else if closed {
+        Some(format!(
+            "The match is over, the other player left and {} was closed.",
+            room.name
+        ))
+    } else {
+        None
+    };
+    if let Some(reason) = reason {
+        let response = ServerMessage::Lobby(LobbyResponse::Rejected(reason));
+        exit = match write_frame(&mut writer, &response) {
+            Ok(()) => Exit::LeftRoom,
+            Err(_) => Exit::Disconnected,
+        };
+    }
+
+    exit
+}
+
+// spectators are sent the match like the players, but nothing they send ever reaches it
+fn watch(
+    socket: &ClientSocket,
+    reader: &mut FrameReader<&ClientSocket>,
+    room: &Room,
+    notices: &Broadcast<String>,
+    peer: &str,
+) -> Exit {
+    let mut writer = socket;
+    let mut exit = Exit::Disconnected;
+
+    // spectators have nothing to send, so a quiet one is fine and a gone one is noticed
+    // when the snapshots can not be written anymore
+    let mut snapshots = SnapshotWriter::new(room, notices, None);
+    if let Err(e) = socket.set_read_timeout(Some(tick_duration(room))) {
+        warn!("Could not start sending to {}: {}", peer, e);
+        return exit;
+    }
+
+    let mut closed = false;
+    loop {
+        if !room.is_open() {
+            closed = true;
+            exit = Exit::LeftRoom;
+            break;
+        }
+
+        if let Err(e) = snapshots.write_pending(socket) {
+            warn!("Could not send match to a spectator: {}", e);
+            break;
+        }
+
+        match reader.read_frame() {
+            Ok(ClientMessage::Lobby(LobbyRequest::LeaveRoom)) => {
+                exit = Exit::LeftRoom;
+                break;
+            }
+            Ok(_) => (), // inputs and rematch votes are dropped, spectators can not play
+            Err(e) => {
+                if !e.is_timeout() {
+                    info!("{} stopped watching ({})", peer, e);
+                    break;
+                }
+            }
+        }
+    }
+
+    if let Err(e) = socket.set_read_timeout(Some(CLIENT_TIMEOUT)) {
+        warn!("Could not set a timeout for {}: {}", peer, e);
+    }
+
+    // the match is gone so the spectator is back in the lobby
+    if closed {
+        let reason = format!("{} was closed.", room.name);
+        let response = ServerMessage::Lobby(LobbyResponse::Rejected(reason));
+        if write_frame(&mut writer, &response).is_err() {
+            exit = Exit::Disconnected;
+        }
+    }
+
+    exit
+}
+
+// the player went back to the lobby, their slot is free for someone else right away
+fn leave_room(rooms: &Rooms, room: &Room, seat: &Seat) {
+    if !room.slots.lock().unwrap().leave(seat) {
+        return;
+    }
+
+    room.game_match
+        .write()
+        .unwrap()
+        .remove_player(seat.id, EndReason::Forfeit);
+    info!("Player {} left room {}", seat.id + 1, room.id);
+
+    rooms.close_if_empty(room.id);
+}
+
+// hold the slot for the grace period, the room's simulation gives the match to the other
+// player and opens the slot up for someone new if the player is not back by then
+fn disconnect(room: &Room, seat: &Seat) {
+    let id = seat.id;
+
+    // a newer connection already took over the slot
+    if !room.slots.lock().unwrap().disconnect(seat, Instant::now()) {
+        return;
+    }
+    room.game_match.write().unwrap().release_input(id);
+    // the round waits for them, it carries on once both players are back
+    room.round_timer.lock().unwrap().pause();
+    info!(
+        "Player {} left room {}, holding their slot for {} seconds",
+        id + 1,
+        room.id,
+        room.grace_period.as_secs()
+    );
+}
+
+fn tick_duration(room: &Room) -> Duration {
+    Duration::from_secs(1) / room.tick_rate
+}
+
+// writes every tick of the match to the client from the thread serving the connection,
+// spectators have no seat and no inputs to acknowledge
+struct SnapshotWriter {
+    snapshots: Receiver<ServerGameMatch>,
+    notices: Receiver<String>,
+    seat_id: Option<usize>,
+    over: bool, // the snapshot that ended the match has been sent
+}
+
+impl SnapshotWriter {
+    fn new(room: &Room, notices: &Broadcast<String>, seat_id: Option<usize>) -> SnapshotWriter {
+        SnapshotWriter {
+            snapshots: room.broadcast.subscribe(),
+            notices: notices.subscribe(),
+            seat_id,
+            over: false,
+        }
+    }
+
+    // sends whatever came in since the last call
+    fn write_pending(&mut self, socket: &ClientSocket) -> Result<(), FrameError> {
+        let mut writer = socket;
+        send_notices(socket, &self.notices)?;
+
+        // a client that can not keep up only gets the newest match
+        let game_match = match self.snapshots.try_iter().last() {
+            Some(game_match) => game_match,
+            None => return Ok(()),
+        };
+
+        let ended = matches!(game_match.match_status, MatchStatus::Over(_));
+
+        // Serialize the data on server and then send it to the client along with
+        // which of its inputs have been simulated so it can correct its prediction
+        let snapshot = ServerMessage::Snapshot(Snapshot {
+            tick: game_match.get_tick(),
+            ack: self.seat_id.map_or(0, |id| game_match.get_ack(id)),
+            game_match,
+        });
+
+        // a snapshot is outdated by the next one, except for the one that ends the match
+        // which the client can not be allowed to miss
+        let sent = if ended && !self.over {
+            write_frame(&mut writer, &snapshot)
+        } else {
+            socket.write_sequenced(&snapshot)
+        };
+        self.over = ended;
+
+        sent
+    }
+}
+
+// notices are written whenever the connection is written to anyway
+fn send_notices(socket: &ClientSocket, notices: &Receiver<String>) -> Result<(), FrameError> {
+    let mut writer = socket;
+    for notice in notices.try_iter() {
+        write_frame(&mut writer, &ServerMessage::Notice(notice))?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::mpsc::channel;
+
+    use vagabond_protocol::constants::TICK_RATE;
+    use vagabond_protocol::handshake::MatchRules;
+
+    use crate::rooms::PLAYERS_PER_ROOM;
+
+    pub(crate) fn server() -> Server {
+        Server {
+            rooms: Arc::new(Rooms::new(
+                MatchRules::new(),
+                TICK_RATE,
+                Duration::from_secs(1),
+                PLAYERS_PER_ROOM,
+            )),
+            matchmaker: Matchmaker::new(),
+            info: ServerInfo {
+                name: String::from("dojo"),
+                motd: None,
+            },
+            notices: Arc::new(Broadcast::new()),
+            shutdown: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    // the next message that is not a snapshot
+    fn read_message(reader: &mut FrameReader<&TcpStream>) -> ServerMessage {
+        loop {
+            match reader.read_frame().unwrap() {
+                ServerMessage::Snapshot(_) => (),
+                message => return message,
+            }
+        }
+    }
+
+    #[test]
+    fn shutting_down_closes_the_rooms_still_open() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        client
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+        let (stream, _) = listener.accept().unwrap();
+
+        let server = Arc::new(server());
+        let thread_pool = Arc::new(Mutex::new(ThreadPool::new(1, 1)));
+        let connection_server = Arc::clone(&server);
+        thread_pool
+            .lock()
+            .unwrap()
+            .execute(move || handle_client(ClientSocket::Tcp(stream), &connection_server))
+            .unwrap();
+
+        let mut reader = FrameReader::new(&client);
+        let mut writer = &client;
+        write_frame(&mut writer, &Hello::new()).unwrap();
+        match reader.read_frame::<HelloResponse>().unwrap() {
+            HelloResponse::Lobby(_) => (),
+            response => panic!("expected to be let into the lobby, got {:?}", response),
+        }
+        let create = ClientMessage::Lobby(LobbyRequest::CreateRoom {
+            name: String::from("Dojo"),
+            password: None,
+            rules: None,
+        });
+        write_frame(&mut writer, &create).unwrap();
+        match read_message(&mut reader) {
+            ServerMessage::Lobby(LobbyResponse::Joined(_)) => (),
+            message => panic!("expected to join the room, got {:?}", message),
+        }
+
+        // the room is still waiting for a second player when the console shuts the server down
+        server.shutdown.store(true, Ordering::Relaxed);
+        let (done, finished) = channel();
+        let shutting_down = Arc::clone(&server);
+        thread::spawn(move || {
+            shut_down(&shutting_down, thread_pool);
+            done.send(()).unwrap();
+        });
+
+        match read_message(&mut reader) {
+            ServerMessage::Lobby(LobbyResponse::Rejected(reason)) => {
+                assert_eq!(reason, "The server is shutting down, Dojo was closed.")
+            }
+            message => panic!("expected to be sent back, got {:?}", message),
+        }
+        assert!(finished.recv_timeout(Duration::from_secs(5)).is_ok());
+    }
 }
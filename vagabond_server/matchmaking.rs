@@ -0,0 +1,109 @@
+use std::collections::VecDeque;
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+
+use crate::rooms::{Room, Rooms, PLAYERS_PER_ROOM};
+use crate::slots::Seat;
+
+// what a player waiting for an opponent gets told
+pub enum QueueEvent {
+    Position(u32), // place in the queue, 1 is next in line
+    Matched(Arc<Room>, Seat),
+}
+
+struct Waiting {
+    ticket: u64, // used to find the player again when they leave
+    events: Sender<QueueEvent>,
+}
+
+struct Queue {
+    waiting: VecDeque<Waiting>,
+    tickets: u64, // how many players have entered the queue so far
+}
+
+// players that connect without asking for a room wait here, as soon as there are enough of
+// them they are put into a fresh room together
+pub struct Matchmaker {
+    queue: Mutex<Queue>,
+}
+
+impl Matchmaker {
+    pub fn new() -> Matchmaker {
+        Matchmaker {
+            queue: Mutex::new(Queue {
+                waiting: VecDeque::new(),
+                tickets: 0,
+            }),
+        }
+    }
+
+    // puts the player at the back of the queue, the ticket is needed to leave it again
+    pub fn enter(&self, rooms: &Rooms) -> (u64, Receiver<QueueEvent>) {
+        let (events, receiver) = channel();
+
+        let mut queue = self.queue.lock().unwrap();
+        queue.tickets += 1;
+        let ticket = queue.tickets;
+        queue.waiting.push_back(Waiting { ticket, events });
+
+        pair_players(&mut queue, rooms);
+        send_positions(&queue.waiting);
+        (ticket, receiver)
+    }
+
+    // pairs up whoever is still waiting for lack of player slots, once a match ends and frees
+    // some up. the players in the queue call this while they wait
+    pub fn retry(&self, rooms: &Rooms) {
+        let mut queue = self.queue.lock().unwrap();
+
+        if pair_players(&mut queue, rooms) {
+            send_positions(&queue.waiting);
+        }
+    }
+
+    // returns false if the player was already paired before they could leave
+    pub fn leave(&self, ticket: u64) -> bool {
+        let mut queue = self.queue.lock().unwrap();
+
+        match queue
+            .waiting
+            .iter()
+            .position(|player| player.ticket == ticket)
+        {
+            Some(index) => {
+                queue.waiting.remove(index);
+                send_positions(&queue.waiting);
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+// players that have waited the longest are paired first, as long as the server has the player
+// slots for them. returns true if anyone was paired
+fn pair_players(queue: &mut Queue, rooms: &Rooms) -> bool {
+    let mut paired = false;
+
+    while queue.waiting.len() >= PLAYERS_PER_ROOM {
+        let (room, seats) = match rooms.fill_new_room() {
+            Some(filled) => filled,
+            None => break,
+        };
+        let players: Vec<Waiting> = queue.waiting.drain(..PLAYERS_PER_ROOM).collect();
+        for (player, seat) in players.iter().zip(seats) {
+            // the player can only go away by leaving the queue, which they have not yet
+            let _ = player.events.send(QueueEvent::Matched(room.clone(), seat));
+        }
+        paired = true;
+    }
+
+    paired
+}
+
+// everyone behind a player that left or was paired moves up
+fn send_positions(waiting: &VecDeque<Waiting>) {
+    for (index, player) in waiting.iter().enumerate() {
+        let _ = player.events.send(QueueEvent::Position(index as u32 + 1));
+    }
+}
@@ -0,0 +1,541 @@
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use log::info;
+use vagabond_protocol::handshake::MatchRules;
+use vagabond_protocol::lobby::{RoomSummary, MAX_ROOM_NAME_LENGTH};
+use vagabond_protocol::server_data::{EndReason, ServerGameMatch};
+
+use crate::round_timer::RoundTimer;
+use crate::simulation::{self, Broadcast};
+use crate::slots::{Seat, Slots};
+
+// players in a single match, not configurable since the match and the clients only know
+// how to fight one on one
+pub const PLAYERS_PER_ROOM: usize = 2;
+
+// name given to the rooms matchmaking opens
+const QUICK_MATCH_NAME: &str = "Quick match";
+
+// every open room by id, the rooms close themselves once the last player's slot runs out
+type Registry = Arc<Mutex<BTreeMap<u32, Arc<Room>>>>;
+
+// one match along with everything needed to run it
+pub struct Room {
+    pub id: u32,
+    pub name: String,
+    rules: Mutex<MatchRules>,
+    password: Option<String>,
+    pub game_match: Arc<RwLock<ServerGameMatch>>,
+    pub round_timer: Arc<Mutex<RoundTimer>>, // the simulation reads it every tick
+    pub broadcast: Arc<Broadcast<ServerGameMatch>>,
+    pub slots: Arc<Mutex<Slots>>, // the simulation frees the slots nobody came back for
+    pub tick_rate: u32,           // clients are told to send their inputs this often
+    pub grace_period: Duration,   // a dropped player has this long to come back
+    spectators: AtomicUsize,      // connections watching the match without a slot
+    running: Arc<AtomicBool>,     // the simulation thread stops once this is false
+}
+
+impl Room {
+    // creates the room and starts simulating its match
+    fn open(
+        id: u32,
+        name: String,
+        password: Option<String>,
+        rules: MatchRules,
+        tick_rate: u32,
+        grace_period: Duration,
+        registry: &Registry,
+    ) -> Room {
+        let game_match = ServerGameMatch::with_rules(rules.clone(), tick_rate);
+        let game_match = Arc::new(RwLock::new(game_match));
+        let broadcast = Arc::new(Broadcast::new());
+        let slots = Arc::new(Mutex::new(Slots::new(PLAYERS_PER_ROOM)));
+        let running = Arc::new(AtomicBool::new(true));
+
+        // the round does not start running down until both players are here
+        let round_length = Duration::from_secs(rules.round_time as u64);
+        let round_timer = Arc::new(Mutex::new(RoundTimer::new(round_length)));
+
+        // clone so we can move into closure
+        let game_match_inner = game_match.clone();
+        let round_timer_inner = round_timer.clone();
+        let broadcast_inner = broadcast.clone();
+        let running_inner = running.clone();
+        let game_match_expiry = game_match.clone();
+        let slots_inner = slots.clone();
+        let registry = registry.clone();
+
+        // the server runs the match on its own clock, clients only tell it which buttons are
+        // pressed and are sent the match after every tick. players that dropped out are
+        // given up on once their grace period is over
+        thread::spawn(move || {
+            simulation::run(
+                game_match_inner,
+                round_timer_inner,
+                broadcast_inner,
+                running_inner,
+                tick_rate,
+                || {
+                    expire_slots(
+                        id,
+                        &slots_inner,
+                        &game_match_expiry,
+                        grace_period,
+                        &registry,
+                    )
+                },
+            )
+        });
+
+        Room {
+            id,
+            name,
+            rules: Mutex::new(rules),
+            password,
+            game_match,
+            round_timer,
+            broadcast,
+            slots,
+            tick_rate,
+            grace_period,
+            spectators: AtomicUsize::new(0),
+            running,
+        }
+    }
+
+    fn summary(&self) -> RoomSummary {
+        let slots = self.slots.lock().unwrap();
+
+        RoomSummary {
+            room_id: self.id,
+            name: self.name.clone(),
+            players: slots.players(),
+            max_players: slots.capacity(),
+            spectators: self.spectators.load(Ordering::Relaxed),
+            locked: self.password.is_some(),
+            status: self.game_match.read().unwrap().get_match_status(),
+        }
+    }
+
+    fn check_password(&self, password: &Option<String>) -> Result<(), String> {
+        if self.password.is_some() && &self.password != password {
+            return Err(format!("Wrong password for {}.", self.name));
+        }
+
+        Ok(())
+    }
+
+    pub fn rules(&self) -> MatchRules {
+        self.rules.lock().unwrap().clone()
+    }
+
+    // changes the length of the round being played right now as well as the ones after it
+    pub fn set_round_time(&self, round_time: u16) {
+        self.rules.lock().unwrap().round_time = round_time;
+        self.game_match.write().unwrap().set_round_time(round_time);
+        let round_length = Duration::from_secs(round_time as u64);
+        self.round_timer
+            .lock()
+            .unwrap()
+            .set_round_length(round_length);
+    }
+
+    // a fresh match with full health and a full clock
+    pub fn restart(&self) {
+        self.round_timer.lock().unwrap().restart();
+        self.game_match.write().unwrap().restart_match();
+    }
+
+    // false once the room has been closed, spectators are not told any other way
+    pub fn is_open(&self) -> bool {
+        self.running.load(Ordering::Relaxed)
+    }
+
+    // takes the first open slot for a new player, resumed slots keep their inputs going
+    fn claim_slot(&self) -> Option<Seat> {
+        let seat = self.slots.lock().unwrap().claim()?;
+        self.game_match.write().unwrap().reset_input(seat.id);
+
+        Some(seat)
+    }
+
+    pub fn stop_spectating(&self) {
+        self.spectators.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+// every match running on the server, looked up by room id
+pub struct Rooms {
+    rooms: Registry,
+    rules: Mutex<MatchRules>, // for rooms where nobody picked the rules
+    tick_rate: u32,
+    grace_period: Duration,
+    player_slots: usize, // players seated across every room at once, spectators do not count
+}
+
+impl Rooms {
+    pub fn new(
+        rules: MatchRules,
+        tick_rate: u32,
+        grace_period: Duration,
+        player_slots: usize,
+    ) -> Rooms {
+        Rooms {
+            rooms: Arc::new(Mutex::new(BTreeMap::new())),
+            rules: Mutex::new(rules),
+            tick_rate,
+            grace_period,
+            player_slots,
+        }
+    }
+
+    pub fn rules(&self) -> MatchRules {
+        self.rules.lock().unwrap().clone()
+    }
+
+    // rooms opened from now on use the new rules, the ones already open keep theirs
+    pub fn set_rules(&self, rules: MatchRules) {
+        *self.rules.lock().unwrap() = rules;
+    }
+
+    pub fn get(&self, room_id: u32) -> Option<Arc<Room>> {
+        self.rooms.lock().unwrap().get(&room_id).cloned()
+    }
+
+    // every open room, ordered by room id
+    pub fn all(&self) -> Vec<Arc<Room>> {
+        self.rooms.lock().unwrap().values().cloned().collect()
+    }
+
+    // what the lobby shows, ordered by room id
+    pub fn summaries(&self) -> Vec<RoomSummary> {
+        let rooms = self.rooms.lock().unwrap();
+
+        rooms.values().map(|room| room.summary()).collect()
+    }
+
+    // opens a room for the player and gives them the first slot in it
+    pub fn create(
+        &self,
+        name: String,
+        password: Option<String>,
+        rules: Option<MatchRules>,
+    ) -> Result<(Arc<Room>, Seat), String> {
+        let name = name.trim().to_string();
+        if name.is_empty() {
+            return Err(String::from("The room needs a name."));
+        }
+        if name.chars().count() > MAX_ROOM_NAME_LENGTH {
+            return Err(format!(
+                "Room names can be at most {} characters long.",
+                MAX_ROOM_NAME_LENGTH
+            ));
+        }
+        let rules = rules.unwrap_or_else(|| self.rules());
+        rules.check()?;
+
+        // an empty password is the same as not having one
+        let password = password.filter(|password| !password.is_empty());
+
+        let mut rooms = self.rooms.lock().unwrap();
+        self.check_free_slots(&rooms, 1)?;
+        let room_id = first_unused_id(&rooms);
+        info!("Opening room {} ({})", room_id, name);
+        let room = Arc::new(Room::open(
+            room_id,
+            name,
+            password,
+            rules,
+            self.tick_rate,
+            self.grace_period,
+            &self.rooms,
+        ));
+        rooms.insert(room_id, room.clone());
+
+        let seat = room.claim_slot();
+        match seat {
+            Some(seat) => Ok((room, seat)),
+            None => Err(String::from("Could not take a slot in the new room.")),
+        }
+    }
+
+    // takes a slot in a room someone else opened
+    pub fn join(
+        &self,
+        room_id: u32,
+        password: Option<String>,
+    ) -> Result<(Arc<Room>, Seat), String> {
+        // the registry stays locked until the slot is taken so the room can not be closed
+        // in between
+        let rooms = self.rooms.lock().unwrap();
+
+        let room = match rooms.get(&room_id) {
+            Some(room) => room.clone(),
+            None => return Err(format!("Room {} does not exist anymore.", room_id)),
+        };
+        room.check_password(&password)?;
+        self.check_free_slots(&rooms, 1)?;
+
+        let seat = room.claim_slot();
+        match seat {
+            Some(seat) => Ok((room, seat)),
+            None => Err(format!("{} is full, try another room.", room.name)),
+        }
+    }
+
+    // lets someone watch a room without taking a slot in it, there is no limit on how many
+    pub fn spectate(&self, room_id: u32, password: Option<String>) -> Result<Arc<Room>, String> {
+        let rooms = self.rooms.lock().unwrap();
+
+        let room = match rooms.get(&room_id) {
+            Some(room) => room.clone(),
+            None => return Err(format!("Room {} does not exist anymore.", room_id)),
+        };
+        room.check_password(&password)?;
+
+        room.spectators.fetch_add(1, Ordering::Relaxed);
+        Ok(room)
+    }
+
+    // opens a room nobody has asked for and fills every slot in it, used by matchmaking.
+    // None while the server does not have the player slots for a whole room
+    pub fn fill_new_room(&self) -> Option<(Arc<Room>, Vec<Seat>)> {
+        let mut rooms = self.rooms.lock().unwrap();
+        if self.check_free_slots(&rooms, PLAYERS_PER_ROOM).is_err() {
+            return None;
+        }
+
+        let room_id = first_unused_id(&rooms);
+        info!("Opening room {} ({})", room_id, QUICK_MATCH_NAME);
+        let room = Arc::new(Room::open(
+            room_id,
+            String::from(QUICK_MATCH_NAME),
+            None,
+            self.rules(),
+            self.tick_rate,
+            self.grace_period,
+            &self.rooms,
+        ));
+        rooms.insert(room_id, room.clone());
+
+        let seats = (0..PLAYERS_PER_ROOM)
+            .filter_map(|_| room.claim_slot())
+            .collect();
+
+        Some((room, seats))
+    }
+
+    // a player coming back to a slot they held already has it, everyone else needs a free one
+    fn check_free_slots(
+        &self,
+        rooms: &BTreeMap<u32, Arc<Room>>,
+        players: usize,
+    ) -> Result<(), String> {
+        let seated: usize = rooms
+            .values()
+            .map(|room| room.slots.lock().unwrap().players())
+            .sum();
+        if seated + players > self.player_slots {
+            return Err(String::from(
+                "Every player slot on the server is taken, try again later or watch a match.",
+            ));
+        }
+
+        Ok(())
+    }
+
+    // gives a player that lost their connection their old slot back
+    pub fn resume(&self, room_id: u32, token: u64) -> Result<(Arc<Room>, Seat), String> {
+        let rooms = self.rooms.lock().unwrap();
+
+        let resumed = rooms.get(&room_id).and_then(|room| {
+            let seat = room.slots.lock().unwrap().resume(token)?;
+            Some((room.clone(), seat))
+        });
+
+        resumed.ok_or_else(|| {
+            String::from("Your place in the match is gone, it was given to someone else.")
+        })
+    }
+
+    // a room nobody is using anymore is stopped and forgotten
+    pub fn close_if_empty(&self, room_id: u32) {
+        close_if_empty(&self.rooms, room_id);
+    }
+
+    // stops the room even with players in it, they notice it closed and go back to the lobby
+    pub fn close(&self, room_id: u32) {
+        let mut rooms = self.rooms.lock().unwrap();
+        close(&mut rooms, room_id);
+    }
+
+    // the server is going away, the players in every room are sent back and let go
+    pub fn close_all(&self) {
+        let mut rooms = self.rooms.lock().unwrap();
+
+        let room_ids: Vec<u32> = rooms.keys().cloned().collect();
+        for room_id in room_ids {
+            close(&mut rooms, room_id);
+        }
+    }
+}
+
+fn close(rooms: &mut BTreeMap<u32, Arc<Room>>, room_id: u32) {
+    if let Some(room) = rooms.remove(&room_id) {
+        room.running.store(false, Ordering::Relaxed);
+        info!("Closing room {}", room_id);
+    }
+}
+
+fn close_if_empty(registry: &Registry, room_id: u32) {
+    let mut rooms = registry.lock().unwrap();
+
+    let empty = match rooms.get(&room_id) {
+        Some(room) => room.slots.lock().unwrap().all_open(),
+        None => false,
+    };
+    if empty {
+        close(&mut rooms, room_id);
+    }
+}
+
+// runs after every tick, a player that has not come back in time loses the match and their
+// slot is opened up for someone new
+fn expire_slots(
+    room_id: u32,
+    slots: &Mutex<Slots>,
+    game_match: &RwLock<ServerGameMatch>,
+    grace_period: Duration,
+    registry: &Registry,
+) {
+    let now = Instant::now();
+    let expired: Vec<usize> = {
+        let mut slots = slots.lock().unwrap();
+        (0..slots.capacity())
+            .filter(|&id| slots.expire(id, grace_period, now))
+            .collect()
+    };
+    if expired.is_empty() {
+        return;
+    }
+
+    for id in expired {
+        game_match
+            .write()
+            .unwrap()
+            .remove_player(id, EndReason::Disconnect);
+        info!(
+            "Player {} did not come back to room {}, their slot is open",
+            id + 1,
+            room_id
+        );
+    }
+
+    // once both players are gone the room is not needed anymore
+    close_if_empty(registry, room_id);
+}
+
+fn first_unused_id(rooms: &BTreeMap<u32, Arc<Room>>) -> u32 {
+    let mut room_id = 1;
+    while rooms.contains_key(&room_id) {
+        room_id += 1;
+    }
+
+    room_id
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use vagabond_protocol::constants::TICK_RATE;
+    use vagabond_protocol::input::InputFrame;
+    use vagabond_protocol::server_data::MatchStatus;
+
+    fn walk_right(room: &Room, seat: &Seat, ticks: std::ops::RangeInclusive<u32>) {
+        for tick in ticks {
+            let mut input = InputFrame::new(tick);
+            input.right = true;
+            room.game_match
+                .write()
+                .unwrap()
+                .update_entity(seat.id, &input);
+        }
+        // give the simulation time to get through every queued input
+        thread::sleep(Duration::from_millis(300));
+    }
+
+    #[test]
+    fn dropped_players_are_given_up_on_by_the_simulation() {
+        let rooms = Rooms::new(MatchRules::new(), TICK_RATE, Duration::from_millis(50), 2);
+        let (room, seats) = rooms.fill_new_room().unwrap();
+
+        let dropped = Instant::now();
+        assert!(room.slots.lock().unwrap().disconnect(&seats[0], dropped));
+        thread::sleep(Duration::from_millis(20));
+        assert_eq!(room.slots.lock().unwrap().players(), 2);
+
+        thread::sleep(Duration::from_millis(100));
+        assert_eq!(room.slots.lock().unwrap().players(), 1);
+        match room.game_match.read().unwrap().match_status {
+            MatchStatus::Over(result) => assert_eq!(result.winner, Some(1)),
+            ref status => panic!("expected player 2 to win, got {:?}", status),
+        }
+
+        assert!(room
+            .slots
+            .lock()
+            .unwrap()
+            .disconnect(&seats[1], Instant::now()));
+        thread::sleep(Duration::from_millis(100));
+        assert!(rooms.get(room.id).is_none());
+        assert!(!room.is_open());
+    }
+
+    #[test]
+    fn a_new_player_in_a_slot_starts_their_inputs_over() {
+        let rooms = Rooms::new(MatchRules::new(), TICK_RATE, Duration::from_secs(1), 2);
+        let (room, first) = rooms.create(String::from("Dojo"), None, None).unwrap();
+        let (_, second) = rooms.join(room.id, None).unwrap();
+
+        // the first player gets well into their inputs before leaving
+        walk_right(&room, &first, 1..=10);
+        assert_eq!(room.game_match.read().unwrap().get_ack(first.id), 10);
+        crate::leave_room(&rooms, &room, &first);
+
+        // whoever takes the slot next counts from the start again, like every client does
+        let (_, next) = rooms.join(room.id, None).unwrap();
+        assert_eq!(next.id, first.id);
+        assert_eq!(room.game_match.read().unwrap().get_ack(next.id), 0);
+        room.restart();
+
+        let start = room.game_match.read().unwrap().server_entities[next.id].get_pos();
+        walk_right(&room, &next, 1..=5);
+        let game_match = room.game_match.read().unwrap();
+        assert_eq!(game_match.get_ack(next.id), 5);
+        assert!(game_match.server_entities[next.id].get_pos().x > start.x);
+        drop(game_match);
+
+        crate::leave_room(&rooms, &room, &next);
+        crate::leave_room(&rooms, &room, &second);
+    }
+
+    #[test]
+    fn player_slots_are_shared_by_every_room() {
+        let rooms = Rooms::new(MatchRules::new(), TICK_RATE, Duration::from_secs(1), 3);
+        let (first_room, seats) = rooms.fill_new_room().unwrap();
+        let (room, _) = rooms.create(String::from("Dojo"), None, None).unwrap();
+
+        // the second room has a free slot but the server does not
+        assert!(rooms.join(room.id, None).is_err());
+        assert!(rooms.create(String::from("Arena"), None, None).is_err());
+        assert!(rooms.fill_new_room().is_none());
+        assert!(rooms.spectate(room.id, None).is_ok());
+
+        crate::leave_room(&rooms, &first_room, &seats[0]);
+        assert!(rooms.join(room.id, None).is_ok());
+    }
+}
@@ -0,0 +1,207 @@
+use std::time::{Duration, Instant};
+
+// where the round timer gets the time from, tests use one they can move forward by hand
+pub trait TimeSource {
+    fn now(&self) -> Instant;
+}
+
+pub struct SystemClock;
+
+impl TimeSource for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+}
+
+// counts down the time left in a round. only time spent running counts, so it stands still
+// while paused, and it never goes past the round length no matter how late it is looked at
+pub struct RoundTimer<T: TimeSource = SystemClock> {
+    round_length: Duration,
+    elapsed: Duration, // time used up before the timer was last resumed
+    running_since: Option<Instant>, // None while paused
+    time: T,
+}
+
+impl RoundTimer {
+    // starts out paused, the round begins once the players are ready
+    pub fn new(round_length: Duration) -> RoundTimer {
+        RoundTimer::with_time_source(round_length, SystemClock)
+    }
+}
+
+impl<T: TimeSource> RoundTimer<T> {
+    pub fn with_time_source(round_length: Duration, time: T) -> RoundTimer<T> {
+        RoundTimer {
+            round_length,
+            elapsed: Duration::from_secs(0),
+            running_since: None,
+            time,
+        }
+    }
+
+    pub fn resume(&mut self) {
+        if self.running_since.is_none() {
+            self.running_since = Some(self.time.now());
+        }
+    }
+
+    pub fn pause(&mut self) {
+        if let Some(running_since) = self.running_since.take() {
+            self.elapsed += self.time.now().duration_since(running_since);
+        }
+    }
+
+    // takes effect right away, the time already used up counts against the new length
+    pub fn set_round_length(&mut self, round_length: Duration) {
+        self.round_length = round_length;
+    }
+
+    // a fresh round of the same length, running if the timer was running before
+    pub fn restart(&mut self) {
+        self.elapsed = Duration::from_secs(0);
+        if self.running_since.is_some() {
+            self.running_since = Some(self.time.now());
+        }
+    }
+
+    pub fn elapsed(&self) -> Duration {
+        let running = match self.running_since {
+            Some(running_since) => self.time.now().duration_since(running_since),
+            None => Duration::from_secs(0),
+        };
+
+        (self.elapsed + running).min(self.round_length)
+    }
+
+    pub fn remaining(&self) -> Duration {
+        self.round_length - self.elapsed()
+    }
+
+    // what the clock shows, it only reads 0 once the time is actually up which is how the
+    // match knows the round is over
+    pub fn remaining_seconds(&self) -> u16 {
+        let remaining = self.remaining();
+        let seconds = if remaining.subsec_nanos() > 0 {
+            remaining.as_secs() + 1
+        } else {
+            remaining.as_secs()
+        };
+
+        seconds as u16
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::Cell;
+    use std::rc::Rc;
+
+    // time only moves when the test says so
+    #[derive(Clone)]
+    struct ManualClock {
+        now: Rc<Cell<Instant>>,
+    }
+
+    impl ManualClock {
+        fn new() -> ManualClock {
+            ManualClock {
+                now: Rc::new(Cell::new(Instant::now())),
+            }
+        }
+
+        fn advance(&self, duration: Duration) {
+            self.now.set(self.now.get() + duration);
+        }
+    }
+
+    impl TimeSource for ManualClock {
+        fn now(&self) -> Instant {
+            self.now.get()
+        }
+    }
+
+    fn timer(seconds: u64) -> (RoundTimer<ManualClock>, ManualClock) {
+        let clock = ManualClock::new();
+        let timer = RoundTimer::with_time_source(Duration::from_secs(seconds), clock.clone());
+        (timer, clock)
+    }
+
+    #[test]
+    fn starts_paused_with_the_whole_round_left() {
+        let (timer, clock) = timer(60);
+        clock.advance(Duration::from_secs(5));
+
+        assert_eq!(timer.remaining_seconds(), 60);
+    }
+
+    #[test]
+    fn counts_down_while_running() {
+        let (mut timer, clock) = timer(60);
+        timer.resume();
+
+        clock.advance(Duration::from_millis(500));
+        assert_eq!(timer.remaining_seconds(), 60);
+
+        clock.advance(Duration::from_millis(500));
+        assert_eq!(timer.remaining_seconds(), 59);
+
+        clock.advance(Duration::from_secs(58) + Duration::from_millis(999));
+        assert_eq!(timer.remaining_seconds(), 1);
+    }
+
+    #[test]
+    fn stands_still_while_paused() {
+        let (mut timer, clock) = timer(60);
+        timer.resume();
+        clock.advance(Duration::from_secs(10));
+        timer.pause();
+
+        clock.advance(Duration::from_secs(30));
+        assert_eq!(timer.remaining_seconds(), 50);
+
+        timer.resume();
+        clock.advance(Duration::from_secs(5));
+        assert_eq!(timer.remaining_seconds(), 45);
+    }
+
+    #[test]
+    fn time_is_up_at_the_end_of_the_round() {
+        let (mut timer, clock) = timer(60);
+        timer.resume();
+        clock.advance(Duration::from_secs(60));
+
+        assert_eq!(timer.remaining_seconds(), 0);
+
+        // looking late does not take the clock below zero
+        clock.advance(Duration::from_secs(10));
+        assert_eq!(timer.remaining(), Duration::from_secs(0));
+    }
+
+    #[test]
+    fn changing_the_length_keeps_the_time_used_up() {
+        let (mut timer, clock) = timer(60);
+        timer.resume();
+        clock.advance(Duration::from_secs(20));
+
+        timer.set_round_length(Duration::from_secs(90));
+        assert_eq!(timer.remaining_seconds(), 70);
+
+        timer.set_round_length(Duration::from_secs(10));
+        assert_eq!(timer.remaining_seconds(), 0);
+    }
+
+    #[test]
+    fn restarting_gives_a_full_round() {
+        let (mut timer, clock) = timer(30);
+        timer.resume();
+        clock.advance(Duration::from_secs(45));
+        assert_eq!(timer.remaining_seconds(), 0);
+
+        // still running after the restart
+        timer.restart();
+        assert_eq!(timer.remaining_seconds(), 30);
+        clock.advance(Duration::from_secs(1));
+        assert_eq!(timer.remaining_seconds(), 29);
+    }
+}
@@ -0,0 +1,93 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::{Arc, Mutex, RwLock};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use vagabond_protocol::server_data::{MatchStatus, ServerGameMatch};
+
+use crate::round_timer::RoundTimer;
+
+// most ticks we will run back to back after a stall, anything beyond that is dropped
+// so the match does not fast forward
+const MAX_CATCH_UP_TICKS: u32 = 5;
+
+// hands every connection a copy of something, the match after it has been stepped or a
+// notice for everyone on the server
+pub struct Broadcast<T> {
+    senders: Mutex<Vec<Sender<T>>>,
+}
+
+impl<T: Clone> Broadcast<T> {
+    pub fn new() -> Broadcast<T> {
+        Broadcast {
+            senders: Mutex::new(Vec::new()),
+        }
+    }
+
+    pub fn subscribe(&self) -> Receiver<T> {
+        let (sender, receiver) = channel();
+        self.senders.lock().unwrap().push(sender);
+        receiver
+    }
+
+    // connections that went away dropped their receiver so they are forgotten here,
+    // returns how many are still listening
+    pub fn send(&self, value: &T) -> usize {
+        let mut senders = self.senders.lock().unwrap();
+        senders.retain(|sender| sender.send(value.clone()).is_ok());
+        senders.len()
+    }
+}
+
+// steps the match at a fixed rate no matter how often the clients send data,
+// until the room it belongs to is closed. the round clock is read every tick so it keeps
+// running down even when no client is sending anything, after_ticks is for everything
+// else the room has to check on as often
+pub fn run<F: FnMut()>(
+    game_match: Arc<RwLock<ServerGameMatch>>,
+    round_timer: Arc<Mutex<RoundTimer>>,
+    broadcast: Arc<Broadcast<ServerGameMatch>>,
+    running: Arc<AtomicBool>,
+    tick_rate: u32,
+    mut after_ticks: F,
+) {
+    let tick_duration = Duration::from_secs(1) / tick_rate;
+    let mut next_tick = Instant::now();
+
+    while running.load(Ordering::Relaxed) {
+        let mut ticks = 0;
+        while Instant::now() >= next_tick && ticks < MAX_CATCH_UP_TICKS {
+            let mut game_match = game_match.write().unwrap();
+            if game_match.match_status == MatchStatus::InProgress {
+                game_match.set_clock(round_timer.lock().unwrap().remaining_seconds());
+            }
+            let between_rounds = matches!(game_match.match_status, MatchStatus::RoundOver(_));
+            game_match.step();
+
+            // every round gets the whole clock
+            if between_rounds && game_match.match_status == MatchStatus::InProgress {
+                round_timer.lock().unwrap().restart();
+            }
+            drop(game_match);
+
+            next_tick += tick_duration;
+            ticks += 1;
+        }
+
+        // clients only need to see the match once we are caught up
+        if ticks > 0 {
+            let snapshot = game_match.read().unwrap().clone();
+            broadcast.send(&snapshot);
+            after_ticks();
+        }
+
+        let now = Instant::now();
+        if next_tick > now {
+            thread::sleep(next_tick - now);
+        } else {
+            // still behind after catching up as much as we allow
+            next_tick = now;
+        }
+    }
+}
@@ -0,0 +1,180 @@
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::time::{Duration, Instant, SystemTime};
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum SlotState {
+    Open,
+    Connected,
+    Disconnected(Instant), // when the player went away, the slot is held for them for a while
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Slot {
+    state: SlotState,
+    token: u64,      // what the player has to show to get the slot back
+    connection: u64, // which connection is currently using the slot
+}
+
+// what a connection gets when it takes a slot
+#[derive(Clone, Copy, Debug)]
+pub struct Seat {
+    pub id: usize,
+    pub token: u64,
+    pub connection: u64,
+}
+
+// keeps track of which player ids are in use and who they belong to
+pub struct Slots {
+    slots: Vec<Slot>,
+    connections: u64, // how many connections have taken a slot so far
+}
+
+impl Slots {
+    pub fn new(count: usize) -> Slots {
+        let slot = Slot {
+            state: SlotState::Open,
+            token: 0,
+            connection: 0,
+        };
+
+        Slots {
+            slots: vec![slot; count],
+            connections: 0,
+        }
+    }
+
+    // hands out the first free player id along with a new session token
+    pub fn claim(&mut self) -> Option<Seat> {
+        let id = self
+            .slots
+            .iter()
+            .position(|slot| slot.state == SlotState::Open)?;
+        self.slots[id].token = new_session_token();
+
+        Some(self.take(id))
+    }
+
+    // gives a player their slot back if it has not been given to someone else yet.
+    // the old connection might not have noticed it is dead yet, the new one replaces it
+    pub fn resume(&mut self, token: u64) -> Option<Seat> {
+        let id = self
+            .slots
+            .iter()
+            .position(|slot| slot.state != SlotState::Open && slot.token == token)?;
+
+        Some(self.take(id))
+    }
+
+    fn take(&mut self, id: usize) -> Seat {
+        self.connections += 1;
+
+        let slot = &mut self.slots[id];
+        slot.state = SlotState::Connected;
+        slot.connection = self.connections;
+
+        Seat {
+            id,
+            token: slot.token,
+            connection: slot.connection,
+        }
+    }
+
+    // false once someone else has resumed the slot
+    pub fn is_current(&self, seat: &Seat) -> bool {
+        self.slots[seat.id].connection == seat.connection
+    }
+
+    // returns false if the slot was already taken over by a newer connection
+    pub fn disconnect(&mut self, seat: &Seat, now: Instant) -> bool {
+        if !self.is_current(seat) {
+            return false;
+        }
+
+        self.slots[seat.id].state = SlotState::Disconnected(now);
+        true
+    }
+
+    // the player went back to the lobby so the slot is free right away
+    // returns false if the slot was already taken over by a newer connection
+    pub fn leave(&mut self, seat: &Seat) -> bool {
+        if !self.is_current(seat) {
+            return false;
+        }
+
+        self.slots[seat.id].state = SlotState::Open;
+        true
+    }
+
+    // takes the slot away from whoever has it, they can not resume it afterwards
+    // returns false if nobody had the slot
+    pub fn kick(&mut self, id: usize) -> bool {
+        match self.slots.get(id) {
+            Some(slot) if slot.state != SlotState::Open => (),
+            _ => return false,
+        }
+
+        // a new connection number tells the kicked one it is not current anymore
+        self.connections += 1;
+        let slot = &mut self.slots[id];
+        slot.state = SlotState::Open;
+        slot.token = 0;
+        slot.connection = self.connections;
+        true
+    }
+
+    // a seat that is not current was either kicked or resumed by a newer connection,
+    // only resuming keeps the token
+    pub fn was_kicked(&self, seat: &Seat) -> bool {
+        self.slots[seat.id].token != seat.token
+    }
+
+    // frees the slot if the player has been gone for the whole grace period
+    // returns true if the slot was freed
+    pub fn expire(&mut self, id: usize, grace_period: Duration, now: Instant) -> bool {
+        match self.slots[id].state {
+            SlotState::Disconnected(since) if now.duration_since(since) >= grace_period => {
+                self.slots[id].state = SlotState::Open;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    // players in the room, including the ones we are holding a slot for
+    pub fn players(&self) -> usize {
+        self.slots
+            .iter()
+            .filter(|slot| slot.state != SlotState::Open)
+            .count()
+    }
+
+    pub fn state(&self, id: usize) -> SlotState {
+        self.slots[id].state
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.slots.len()
+    }
+
+    pub fn all_connected(&self) -> bool {
+        self.slots
+            .iter()
+            .all(|slot| slot.state == SlotState::Connected)
+    }
+
+    pub fn all_open(&self) -> bool {
+        self.slots.iter().all(|slot| slot.state == SlotState::Open)
+    }
+}
+
+// tokens only need to be hard to guess, the standard library's randomly seeded hasher
+// is good enough for that without pulling in another crate
+fn new_session_token() -> u64 {
+    let mut hasher = RandomState::new().build_hasher();
+    if let Ok(time) = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH) {
+        hasher.write_u128(time.as_nanos());
+    }
+
+    hasher.finish()
+}
@@ -0,0 +1,215 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{sync_channel, Receiver, SyncSender, TrySendError};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use log::warn;
+
+type Job = Box<dyn FnOnce() + Send + 'static>;
+
+// the job was dropped without running
+#[derive(Debug)]
+pub struct PoolFull;
+
+// a fixed number of threads that take jobs off a queue, once every thread is busy and the
+// queue is full new jobs are turned away instead of piling up
+pub struct ThreadPool {
+    workers: Vec<Worker>,
+    sender: Option<SyncSender<Job>>, // dropped on shutdown so the workers stop waiting for jobs
+    receiver: Arc<Mutex<Receiver<Job>>>,
+    next_worker_id: usize,
+}
+
+impl ThreadPool {
+    // size is how many jobs run at once, queue_size how many more can wait for a thread
+    pub fn new(size: usize, queue_size: usize) -> ThreadPool {
+        assert!(size > 0, "a thread pool needs at least one thread");
+
+        let (sender, receiver) = sync_channel(queue_size);
+        let receiver = Arc::new(Mutex::new(receiver));
+
+        let mut pool = ThreadPool {
+            workers: Vec::with_capacity(size),
+            sender: Some(sender),
+            receiver,
+            next_worker_id: 0,
+        };
+        for _ in 0..size {
+            pool.spawn_worker();
+        }
+
+        pool
+    }
+
+    // queues the job unless every thread is busy and the queue is full
+    pub fn execute<F>(&mut self, job: F) -> Result<(), PoolFull>
+    where
+        F: FnOnce() + Send + 'static,
+    {
+        self.reclaim();
+
+        match self.sender.as_ref().unwrap().try_send(Box::new(job)) {
+            Ok(()) => Ok(()),
+            Err(TrySendError::Full(_)) | Err(TrySendError::Disconnected(_)) => Err(PoolFull),
+        }
+    }
+
+    // workers whose job panicked are gone, their threads are cleaned up and new ones put in
+    // their place
+    fn reclaim(&mut self) {
+        let (finished, running): (Vec<Worker>, Vec<Worker>) = self
+            .workers
+            .drain(..)
+            .partition(|worker| !worker.alive.load(Ordering::Relaxed));
+        self.workers = running;
+
+        for mut worker in finished {
+            if let Some(thread) = worker.thread.take() {
+                let _ = thread.join();
+            }
+            warn!(
+                "Worker {} stopped unexpectedly, starting a new one",
+                worker.id
+            );
+            self.spawn_worker();
+        }
+    }
+
+    fn spawn_worker(&mut self) {
+        let worker = Worker::new(self.next_worker_id, self.receiver.clone());
+        self.next_worker_id += 1;
+        self.workers.push(worker);
+    }
+}
+
+// waits for every job that has been started or queued to finish
+impl Drop for ThreadPool {
+    fn drop(&mut self) {
+        drop(self.sender.take());
+
+        for worker in &mut self.workers {
+            if let Some(thread) = worker.thread.take() {
+                // a worker that panicked has already said so
+                let _ = thread.join();
+            }
+        }
+    }
+}
+
+struct Worker {
+    id: usize,
+    thread: Option<thread::JoinHandle<()>>,
+    alive: Arc<AtomicBool>, // false once the thread has finished, for whatever reason
+}
+
+impl Worker {
+    fn new(id: usize, receiver: Arc<Mutex<Receiver<Job>>>) -> Worker {
+        let alive = Arc::new(AtomicBool::new(true));
+        let alive_inner = alive.clone();
+
+        let thread = thread::spawn(move || {
+            // dropped when the thread ends, even when a job panics
+            let _finished = Finished(alive_inner);
+
+            loop {
+                // the lock is only held while waiting, not while the job runs
+                let job = receiver.lock().unwrap().recv();
+                match job {
+                    Ok(job) => job(),
+                    Err(_) => break, // the pool is shutting down
+                }
+            }
+        });
+
+        Worker {
+            id,
+            thread: Some(thread),
+            alive,
+        }
+    }
+}
+
+struct Finished(Arc<AtomicBool>);
+
+impl Drop for Finished {
+    fn drop(&mut self) {
+        self.0.store(false, Ordering::Relaxed);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::AtomicUsize;
+    use std::sync::mpsc::channel;
+    use std::time::Duration;
+
+    #[test]
+    fn shutdown_waits_for_queued_jobs() {
+        let done = Arc::new(AtomicUsize::new(0));
+
+        let mut pool = ThreadPool::new(2, 6);
+        for _ in 0..6 {
+            let done = done.clone();
+            pool.execute(move || {
+                thread::sleep(Duration::from_millis(20));
+                done.fetch_add(1, Ordering::SeqCst);
+            })
+            .unwrap();
+        }
+        drop(pool);
+
+        assert_eq!(done.load(Ordering::SeqCst), 6);
+    }
+
+    #[test]
+    fn turns_jobs_away_when_full() {
+        let (release, wait) = channel::<()>();
+        let wait = Arc::new(Mutex::new(wait));
+
+        // one job running and one waiting fills a pool of one thread with a queue of one
+        let mut pool = ThreadPool::new(1, 1);
+        let (started, started_receiver) = channel();
+        let blocking = wait.clone();
+        pool.execute(move || {
+            started.send(()).unwrap();
+            let _ = blocking.lock().unwrap().recv();
+        })
+        .unwrap();
+        started_receiver.recv().unwrap();
+        pool.execute(|| ()).unwrap();
+
+        assert!(pool.execute(|| ()).is_err());
+
+        release.send(()).unwrap();
+    }
+
+    #[test]
+    fn replaces_workers_that_panicked() {
+        let mut pool = ThreadPool::new(1, 0);
+        let (finished, finished_receiver) = channel();
+
+        // nothing is waiting for the queue, so the job has to wait for the worker to be free
+        while pool.execute(|| panic!("job failed")).is_err() {
+            thread::yield_now();
+        }
+        while pool.workers[0].alive.load(Ordering::Relaxed) {
+            thread::yield_now();
+        }
+
+        // the next job goes to a fresh worker
+        while pool
+            .execute({
+                let finished = finished.clone();
+                move || finished.send(()).unwrap()
+            })
+            .is_err()
+        {
+            thread::yield_now();
+        }
+        finished_receiver.recv().unwrap();
+
+        assert_eq!(pool.workers.len(), 1);
+        assert_eq!(pool.next_worker_id, 2);
+    }
+}
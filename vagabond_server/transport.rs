@@ -0,0 +1,406 @@
+use std::io::{self, ErrorKind, Read, Write};
+use std::net::{Shutdown, SocketAddr, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use serde::Serialize;
+use tungstenite::{Message, WebSocket};
+
+use vagabond_protocol::frame::{complete_frame_len, write_frame, FrameError};
+use vagabond_protocol::udp::UdpConnection;
+
+// a websocket can only be read or written by one thread at a time, so reading gives it up
+// this often to let the snapshots out
+const WEBSOCKET_POLL_TIME: Duration = Duration::from_millis(10);
+
+// how long a browser gets to finish the websocket handshake
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(5);
+
+// a client connected over raw TCP, a websocket or UDP, either way it talks the same match
+// protocol. over a websocket every binary message carries exactly one frame, with the same
+// bytes a TCP client would send, and over UDP frames go inside packets that get them there
+// reliably unless they are sent with write_sequenced
+pub enum ClientSocket {
+    Tcp(TcpStream),
+    WebSocket(Arc<WebSocketConnection>),
+    Udp(Arc<UdpConnection>),
+}
+
+pub struct WebSocketConnection {
+    stream: TcpStream, // for the things a websocket does not do itself, like shutting down
+    socket: Mutex<Outgoing>,
+    incoming: Mutex<Vec<u8>>, // bytes of received messages the reader has not used yet
+    read_timeout: Mutex<Option<Duration>>,
+}
+
+struct Outgoing {
+    websocket: WebSocket<TcpStream>,
+    pending: Vec<u8>, // the start of a frame that has not been written completely yet
+}
+
+impl ClientSocket {
+    // finishes the websocket handshake on a freshly accepted connection
+    pub fn accept_websocket(stream: TcpStream) -> Result<ClientSocket, String> {
+        let peer = stream
+            .peer_addr()
+            .map(|addr| addr.to_string())
+            .unwrap_or_else(|_| String::from("unknown address"));
+        let failed = |e: io::Error| format!("Could not set up the websocket for {}: {}", peer, e);
+
+        // a client that never finishes the handshake should not hold on to a thread
+        stream
+            .set_read_timeout(Some(HANDSHAKE_TIMEOUT))
+            .map_err(failed)?;
+        let handle = stream.try_clone().map_err(failed)?;
+
+        let websocket = tungstenite::accept(stream)
+            .map_err(|e| format!("Websocket handshake with {} failed: {}", peer, e))?;
+        handle
+            .set_read_timeout(Some(WEBSOCKET_POLL_TIME))
+            .map_err(failed)?;
+
+        Ok(ClientSocket::WebSocket(Arc::new(WebSocketConnection {
+            stream: handle,
+            socket: Mutex::new(Outgoing {
+                websocket,
+                pending: Vec::new(),
+            }),
+            incoming: Mutex::new(Vec::new()),
+            read_timeout: Mutex::new(None),
+        })))
+    }
+
+    pub fn peer_addr(&self) -> io::Result<SocketAddr> {
+        match self {
+            ClientSocket::Tcp(stream) => stream.peer_addr(),
+            ClientSocket::WebSocket(connection) => connection.stream.peer_addr(),
+            ClientSocket::Udp(connection) => connection.peer_addr(),
+        }
+    }
+
+    pub fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        match self {
+            ClientSocket::Tcp(stream) => stream.set_read_timeout(timeout),
+            ClientSocket::WebSocket(connection) => {
+                // the stream keeps its short timeout, reading keeps trying until this is up
+                *connection.read_timeout.lock().unwrap() = timeout;
+                Ok(())
+            }
+            ClientSocket::Udp(connection) => connection.set_read_timeout(timeout),
+        }
+    }
+
+    pub fn try_clone(&self) -> io::Result<ClientSocket> {
+        match self {
+            ClientSocket::Tcp(stream) => Ok(ClientSocket::Tcp(stream.try_clone()?)),
+            ClientSocket::WebSocket(connection) => Ok(ClientSocket::WebSocket(connection.clone())),
+            ClientSocket::Udp(connection) => Ok(ClientSocket::Udp(connection.clone())),
+        }
+    }
+
+    pub fn shutdown(&self, how: Shutdown) -> io::Result<()> {
+        match self {
+            ClientSocket::Tcp(stream) => stream.shutdown(how),
+            ClientSocket::WebSocket(connection) => {
+                // say goodbye properly if the other end is still listening
+                {
+                    let mut socket = connection.socket.lock().unwrap();
+                    let _ = socket.websocket.close(None);
+                    let _ = socket.websocket.flush();
+                }
+                connection.stream.shutdown(how)
+            }
+            ClientSocket::Udp(connection) => {
+                connection.close();
+                Ok(())
+            }
+        }
+    }
+
+    // for messages that are outdated by the next one, like snapshots. over UDP one that is lost
+    // is not sent again, the other transports never lose anything
+    pub fn write_sequenced<T: Serialize>(&self, message: &T) -> Result<(), FrameError> {
+        match self {
+            ClientSocket::Udp(connection) => connection.write_sequenced(message),
+            _ => write_frame(&mut &*self, message),
+        }
+    }
+}
+
+impl WebSocketConnection {
+    fn read(&self, buffer: &mut [u8]) -> io::Result<usize> {
+        let started = Instant::now();
+        let read_timeout = *self.read_timeout.lock().unwrap();
+
+        loop {
+            {
+                let mut incoming = self.incoming.lock().unwrap();
+                if !incoming.is_empty() {
+                    let size = buffer.len().min(incoming.len());
+                    buffer[..size].copy_from_slice(&incoming[..size]);
+                    incoming.drain(..size);
+                    return Ok(size);
+                }
+            }
+
+            // the lock is given up between polls so writing never waits long
+            let message = self.socket.lock().unwrap().websocket.read();
+            match message {
+                Ok(Message::Binary(data)) => self.incoming.lock().unwrap().extend(data),
+                Ok(Message::Close(_)) => return Ok(0),
+                Ok(_) => (), // pings are answered by the websocket, text is not part of the protocol
+                Err(tungstenite::Error::Io(e)) => {
+                    if e.kind() != ErrorKind::WouldBlock && e.kind() != ErrorKind::TimedOut {
+                        return Err(e);
+                    }
+                    if let Some(read_timeout) = read_timeout {
+                        if started.elapsed() >= read_timeout {
+                            return Err(e);
+                        }
+                    }
+                }
+                Err(tungstenite::Error::ConnectionClosed)
+                | Err(tungstenite::Error::AlreadyClosed) => return Ok(0),
+                Err(e) => return Err(io::Error::new(ErrorKind::Other, e)),
+            }
+        }
+    }
+
+    // every complete frame goes out as a message of its own
+    fn write(&self, buffer: &[u8]) -> io::Result<usize> {
+        let mut socket = self.socket.lock().unwrap();
+        socket.pending.extend_from_slice(buffer);
+
+        loop {
+            let frame_len = match complete_frame_len(&socket.pending) {
+                Ok(Some(frame_len)) => frame_len,
+                Ok(None) => break,
+                Err(e) => return Err(io::Error::new(ErrorKind::InvalidData, e)),
+            };
+            let frame: Vec<u8> = socket.pending.drain(..frame_len).collect();
+            socket
+                .websocket
+                .send(Message::Binary(frame))
+                .map_err(|e| io::Error::new(ErrorKind::Other, e))?;
+        }
+
+        Ok(buffer.len())
+    }
+
+    fn flush(&self) -> io::Result<()> {
+        self.socket
+            .lock()
+            .unwrap()
+            .websocket
+            .flush()
+            .map_err(|e| io::Error::new(ErrorKind::Other, e))
+    }
+}
+
+// reading and writing only need a shared reference, like with a TcpStream
+impl Read for &ClientSocket {
+    fn read(&mut self, buffer: &mut [u8]) -> io::Result<usize> {
+        match self {
+            ClientSocket::Tcp(stream) => (&*stream).read(buffer),
+            ClientSocket::WebSocket(connection) => connection.read(buffer),
+            ClientSocket::Udp(connection) => (&**connection).read(buffer),
+        }
+    }
+}
+
+impl Write for &ClientSocket {
+    fn write(&mut self, buffer: &[u8]) -> io::Result<usize> {
+        match self {
+            ClientSocket::Tcp(stream) => (&*stream).write(buffer),
+            ClientSocket::WebSocket(connection) => connection.write(buffer),
+            ClientSocket::Udp(connection) => (&**connection).write(buffer),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            ClientSocket::Tcp(stream) => (&*stream).flush(),
+            ClientSocket::WebSocket(connection) => connection.flush(),
+            ClientSocket::Udp(connection) => (&**connection).flush(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::TcpListener;
+    use std::thread;
+
+    use vagabond_protocol::frame::{encode, write_frame, FrameReader};
+    use vagabond_protocol::handshake::{Hello, HelloResponse, MatchRules, ServerInfo};
+    use vagabond_protocol::lobby::{LobbyRequest, LobbyResponse};
+    use vagabond_protocol::message::{ClientMessage, ServerMessage};
+    use vagabond_protocol::udp::UdpListener;
+
+    use crate::tests::server;
+
+    fn dojo() -> ServerInfo {
+        ServerInfo {
+            name: String::from("dojo"),
+            motd: None,
+        }
+    }
+
+    // a websocket client on one end of a loopback connection and the server's socket on the other
+    fn connect() -> (ClientSocket, WebSocket<TcpStream>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let accepted = thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            ClientSocket::accept_websocket(stream).unwrap()
+        });
+        let stream = TcpStream::connect(addr).unwrap();
+        let (client, _) = tungstenite::client(format!("ws://{}/", addr), stream).unwrap();
+
+        (accepted.join().unwrap(), client)
+    }
+
+    // the next message that is not a snapshot, decoded from the single frame it carries
+    fn read_message<T: serde::de::DeserializeOwned>(client: &mut WebSocket<TcpStream>) -> T {
+        loop {
+            if let Message::Binary(data) = client.read().unwrap() {
+                let mut reader = FrameReader::new(&data[..]);
+                return reader.read_frame().unwrap();
+            }
+        }
+    }
+
+    #[test]
+    fn frames_travel_one_per_message() {
+        let (socket, mut client) = connect();
+
+        client
+            .send(Message::Binary(encode(&Hello::new()).unwrap()))
+            .unwrap();
+        let mut reader = FrameReader::new(&socket);
+        assert_eq!(reader.read_frame::<Hello>().unwrap(), Hello::new());
+
+        let response = HelloResponse::Lobby(dojo());
+        write_frame(&mut &socket, &response).unwrap();
+        match client.read().unwrap() {
+            Message::Binary(data) => assert_eq!(data, encode(&response).unwrap()),
+            message => panic!("expected a binary message, got {:?}", message),
+        }
+    }
+
+    #[test]
+    fn frames_split_across_messages_are_put_back_together() {
+        let (socket, mut client) = connect();
+
+        let frame = encode(&Hello::new()).unwrap();
+        let (start, end) = frame.split_at(2);
+        client.send(Message::Binary(start.to_vec())).unwrap();
+        client.send(Message::Binary(end.to_vec())).unwrap();
+
+        let mut reader = FrameReader::new(&socket);
+        assert_eq!(reader.read_frame::<Hello>().unwrap(), Hello::new());
+    }
+
+    #[test]
+    fn read_timeout_keeps_the_connection_usable() {
+        let (socket, mut client) = connect();
+        socket
+            .set_read_timeout(Some(Duration::from_millis(50)))
+            .unwrap();
+
+        let mut reader = FrameReader::new(&socket);
+        assert!(reader.read_frame::<Hello>().unwrap_err().is_timeout());
+
+        client
+            .send(Message::Binary(encode(&Hello::new()).unwrap()))
+            .unwrap();
+        assert_eq!(reader.read_frame::<Hello>().unwrap(), Hello::new());
+    }
+
+    #[test]
+    fn closing_the_websocket_closes_the_stream() {
+        let (socket, mut client) = connect();
+
+        client.close(None).unwrap();
+        let _ = client.flush();
+
+        let mut reader = FrameReader::new(&socket);
+        assert!(reader.read_frame::<Hello>().is_err());
+    }
+
+    #[test]
+    fn web_clients_play_the_same_protocol() {
+        let (socket, mut client) = connect();
+        thread::spawn(move || {
+            crate::handle_client(socket, &server());
+        });
+
+        client
+            .send(Message::Binary(encode(&Hello::new()).unwrap()))
+            .unwrap();
+        match read_message::<HelloResponse>(&mut client) {
+            HelloResponse::Lobby(_) => (),
+            response => panic!("expected to be let into the lobby, got {:?}", response),
+        }
+
+        let create = ClientMessage::Lobby(LobbyRequest::CreateRoom {
+            name: String::from("Browser"),
+            password: None,
+            rules: None,
+        });
+        client
+            .send(Message::Binary(encode(&create).unwrap()))
+            .unwrap();
+        match read_message::<ServerMessage>(&mut client) {
+            ServerMessage::Lobby(LobbyResponse::Joined(welcome)) => assert_eq!(welcome.room_id, 1),
+            message => panic!("expected to join the room, got {:?}", message),
+        }
+
+        // the match follows right away
+        match read_message::<ServerMessage>(&mut client) {
+            ServerMessage::Snapshot(snapshot) => {
+                assert_eq!(snapshot.game_match.server_entities.len(), 2)
+            }
+            message => panic!("expected a snapshot, got {:?}", message),
+        }
+    }
+
+    #[test]
+    fn udp_clients_play_the_same_protocol() {
+        let listener = UdpListener::bind("127.0.0.1:0").unwrap();
+        let client = UdpConnection::connect(listener.local_addr().unwrap()).unwrap();
+        write_frame(&mut &*client, &Hello::new()).unwrap();
+
+        let connection = listener.accept().unwrap();
+        thread::spawn(move || {
+            crate::handle_client(ClientSocket::Udp(connection), &server());
+        });
+
+        let mut reader = FrameReader::new(&*client);
+        match reader.read_frame::<HelloResponse>().unwrap() {
+            HelloResponse::Lobby(_) => (),
+            response => panic!("expected to be let into the lobby, got {:?}", response),
+        }
+
+        let create = ClientMessage::Lobby(LobbyRequest::CreateRoom {
+            name: String::from("Datagram"),
+            password: None,
+            rules: Some(MatchRules::new()),
+        });
+        write_frame(&mut &*client, &create).unwrap();
+        match reader.read_frame::<ServerMessage>().unwrap() {
+            ServerMessage::Lobby(LobbyResponse::Joined(welcome)) => assert_eq!(welcome.room_id, 1),
+            message => panic!("expected to join the room, got {:?}", message),
+        }
+
+        // snapshots are sequenced, one of them is all it takes
+        match reader.read_frame::<ServerMessage>().unwrap() {
+            ServerMessage::Snapshot(snapshot) => {
+                assert_eq!(snapshot.game_match.server_entities.len(), 2)
+            }
+            message => panic!("expected a snapshot, got {:?}", message),
+        }
+    }
+}